@@ -0,0 +1,215 @@
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{Request, State},
+    http::{header, request::Parts, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rmcp::{
+    model::ErrorCode,
+    service::RequestContext,
+    ErrorData as McpError, RoleServer,
+};
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// JSON-RPC error code returned when a key's scope does not permit a tool
+pub const PERMISSION_DENIED: ErrorCode = ErrorCode(-32001);
+
+/// Access level granted by an API key. Each scope includes the ones below it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    /// Search and retrieval only
+    Read,
+    /// Read plus storing and deleting memories
+    Write,
+    /// Everything, including administrative tools
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "read" => Ok(Scope::Read),
+            "write" => Ok(Scope::Write),
+            "admin" => Ok(Scope::Admin),
+            other => Err(anyhow!("Unknown API key scope: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Read => write!(f, "read"),
+            Scope::Write => write!(f, "write"),
+            Scope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// A bearer token accepted by the network transports
+#[derive(Clone)]
+pub struct ApiKey {
+    pub key: String,
+    pub scope: Scope,
+}
+
+// Never print the key itself, the config is logged at startup
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiKey")
+            .field("key", &"<redacted>")
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+impl FromStr for ApiKey {
+    type Err = anyhow::Error;
+
+    /// Parse a `key:scope` entry
+    fn from_str(s: &str) -> Result<Self> {
+        let (key, scope) = s
+            .trim()
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow!("API key entries must have the form key:scope"))?;
+        if key.is_empty() {
+            return Err(anyhow!("API key must not be empty"));
+        }
+        Ok(ApiKey {
+            key: key.to_string(),
+            scope: scope.parse()?,
+        })
+    }
+}
+
+/// Scope required to call a tool. Unknown tools require admin.
+pub fn required_scope(tool_name: &str) -> Scope {
+    match tool_name {
+        "retrieve_memory" | "search_by_tag" | "list_namespaces" => Scope::Read,
+        "store_memory" | "delete_memory" | "delete_by_tag" | "consolidate_memories" | "create_namespace" => Scope::Write,
+        "migrate_memories" | "drop_namespace" => Scope::Admin,
+        _ => Scope::Admin,
+    }
+}
+
+/// Scope of the caller of a request. Requests that did not come through the
+/// HTTP authentication middleware (stdio, Unix socket, or HTTP without
+/// configured keys) are trusted fully.
+pub fn request_scope(context: &RequestContext<RoleServer>) -> Scope {
+    context
+        .extensions
+        .get::<Parts>()
+        .and_then(|parts| parts.extensions.get::<Scope>())
+        .copied()
+        .unwrap_or(Scope::Admin)
+}
+
+/// Check that the caller may call the given tool
+pub fn authorize_tool(tool_name: &str, context: &RequestContext<RoleServer>) -> Result<(), McpError> {
+    let required = required_scope(tool_name);
+    let granted = request_scope(context);
+    if granted >= required {
+        Ok(())
+    } else {
+        Err(McpError::new(
+            PERMISSION_DENIED,
+            format!("Permission denied: '{}' requires the '{}' scope, but the API key only grants '{}'", tool_name, required, granted),
+            None,
+        ))
+    }
+}
+
+/// Keys accepted by [`require_api_key`]
+pub struct ApiKeys {
+    // Compare digests rather than the keys themselves so lookups don't leak key prefixes through timing
+    digests: Vec<([u8; 32], Scope)>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: &[ApiKey]) -> Self {
+        Self {
+            digests: keys.iter().map(|key| (digest(&key.key), key.scope)).collect(),
+        }
+    }
+
+    fn scope_for(&self, token: &str) -> Option<Scope> {
+        let token_digest = digest(token);
+        self.digests
+            .iter()
+            .filter(|(key_digest, _)| *key_digest == token_digest)
+            .map(|(_, scope)| *scope)
+            .max()
+    }
+}
+
+fn digest(value: &str) -> [u8; 32] {
+    Sha256::digest(value.as_bytes()).into()
+}
+
+/// Axum middleware rejecting requests without a valid `Authorization: Bearer`
+/// key. The key's scope is attached to the request for [`authorize_tool`].
+pub async fn require_api_key(State(keys): State<Arc<ApiKeys>>, mut request: Request, next: Next) -> Response {
+    let scope = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| keys.scope_for(token.trim()));
+
+    match scope {
+        Some(scope) => {
+            request.extensions_mut().insert(scope);
+            next.run(request).await
+        }
+        None => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            "Missing or invalid API key",
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tools_require_the_scope_of_what_they_change() {
+        for tool in ["retrieve_memory", "search_by_tag", "list_namespaces"] {
+            assert_eq!(required_scope(tool), Scope::Read, "{}", tool);
+        }
+        for tool in ["store_memory", "delete_memory", "delete_by_tag", "consolidate_memories", "create_namespace"] {
+            assert_eq!(required_scope(tool), Scope::Write, "{}", tool);
+        }
+        for tool in ["migrate_memories", "drop_namespace", "some_future_tool"] {
+            assert_eq!(required_scope(tool), Scope::Admin, "{}", tool);
+        }
+        assert!(Scope::Read < Scope::Write && Scope::Write < Scope::Admin);
+    }
+
+    #[test]
+    fn key_entries_are_parsed_with_their_scope() {
+        let key: ApiKey = " a:b:c:Write ".parse().unwrap();
+        assert_eq!((key.key.as_str(), key.scope), ("a:b:c", Scope::Write));
+        assert!("key".parse::<ApiKey>().is_err());
+        assert!(":read".parse::<ApiKey>().is_err());
+        assert!("key:owner".parse::<ApiKey>().is_err());
+        assert!(!format!("{:?}", key).contains("a:b:c"));
+    }
+
+    #[test]
+    fn keys_are_matched_exactly() {
+        let keys = ApiKeys::new(&["reader:read".parse().unwrap(), "writer:write".parse().unwrap(), "writer:admin".parse().unwrap()]);
+        assert_eq!(keys.scope_for("reader"), Some(Scope::Read));
+        // A key listed twice grants the wider scope
+        assert_eq!(keys.scope_for("writer"), Some(Scope::Admin));
+        for unknown in ["", "read", "reader2", "Reader"] {
+            assert_eq!(keys.scope_for(unknown), None, "{}", unknown);
+        }
+    }
+}
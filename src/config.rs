@@ -1,8 +1,23 @@
+use crate::auth::ApiKey;
+use crate::confirmation;
+use crate::storage::{
+    validate_namespace, CacheConfig, ChromaAuth, ChromaTls, HnswConfig, Quantization, QuantizationConfig, RetryPolicy,
+    DEFAULT_DATABASE, DEFAULT_NAMESPACE, DEFAULT_RETRY_INTERVAL, DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_TENANT, DEFAULT_TIMEOUT,
+};
 use anyhow::{Context, Result};
-use std::{env, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt,
+    net::SocketAddr,
+    path::PathBuf,
+    time::Duration,
+};
 use directories_next::ProjectDirs;
 use url::Url;
 
+// Source of configuration variables, normally the process environment
+type Vars<'a> = dyn Fn(&str) -> Result<String, env::VarError> + 'a;
+
 /// Storage backend options
 #[derive(Debug, Clone, PartialEq)]
 pub enum StorageBackend {
@@ -10,6 +25,10 @@ pub enum StorageBackend {
     InMemory,
     /// ChromaDB storage
     ChromaDB,
+    /// SQLite database file (no external service)
+    Sqlite,
+    /// Qdrant storage
+    Qdrant,
 }
 
 impl Default for StorageBackend {
@@ -18,6 +37,27 @@ impl Default for StorageBackend {
     }
 }
 
+/// A credential loaded from the environment. Never printed by `Debug`, since
+/// the config is logged at startup.
+#[derive(Clone)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Secret(value)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<redacted>")
+    }
+}
+
 /// Embedding model options
 #[derive(Debug, Clone, PartialEq)]
 pub enum EmbeddingModel {
@@ -33,13 +73,52 @@ impl Default for EmbeddingModel {
     }
 }
 
-#[derive(Debug)]
+/// Transport options
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Transport {
+    /// Single client over stdin/stdout
+    #[default]
+    Stdio,
+    /// Streamable HTTP (and legacy SSE) for many concurrent clients
+    Http,
+    /// Unix domain socket for local processes sharing one daemon
+    Unix,
+}
+
+#[derive(Debug, Clone)]
 pub struct Config {
     // Storage configuration
     pub storage_backend: StorageBackend,
     pub chroma_db_path: PathBuf,
     pub chroma_db_url: Option<Url>,
     pub chroma_collection_name: String,
+    pub chroma_tenant: String,
+    pub chroma_database: String,
+    pub chroma_auth: ChromaAuth,
+    pub chroma_tls: ChromaTls,
+    pub chroma_timeout: Duration,
+    pub chroma_retry: RetryPolicy,
+    // Writes made while ChromaDB is unreachable are queued here (None fails them)
+    pub chroma_queue_dir: Option<PathBuf>,
+    pub chroma_managed: bool,
+    pub chroma_command: String,
+    pub sqlite_path: PathBuf,
+    pub qdrant_url: Option<Url>,
+    pub qdrant_collection_name: String,
+    pub qdrant_api_key: Option<Secret>,
+    pub wal_path: Option<PathBuf>,
+    pub snapshot_interval: usize,
+    pub hnsw: Option<HnswConfig>,
+    pub quantization: Option<QuantizationConfig>,
+    pub cache: Option<CacheConfig>,
+    // Env files describing secondary storages that every write is replicated to
+    pub replicas: Vec<PathBuf>,
+    pub replica_retry_interval: Duration,
+    // Namespace used by tools called without one, and where the namespace registry is kept
+    pub namespace: String,
+    pub namespace_dir: PathBuf,
+    // Serve the memories without allowing changes, opening the storage read-only where supported
+    pub read_only: bool,
     
     // Embedding configuration
     pub embedding_model: EmbeddingModel,
@@ -48,6 +127,20 @@ pub struct Config {
     
     // Server configuration
     pub log_level: String,
+    pub transport: Transport,
+    pub listen_addr: SocketAddr,
+    pub socket_path: PathBuf,
+
+    // Authentication for network transports (empty disables authentication)
+    pub api_keys: Vec<ApiKey>,
+
+    // Bulk deletions affecting more memories than this need the user's confirmation
+    pub bulk_delete_threshold: usize,
+
+    // Directory holding the progress of interrupted migrations
+    pub migration_checkpoint_dir: PathBuf,
+    // Env files describing the storages `migrate_memories` may copy to, by name
+    pub migration_destinations: BTreeMap<String, PathBuf>,
 }
 
 impl Default for Config {
@@ -57,10 +150,40 @@ impl Default for Config {
             chroma_db_path: PathBuf::new(),
             chroma_db_url: None,
             chroma_collection_name: "memory_collection".to_string(),
+            chroma_tenant: DEFAULT_TENANT.to_string(),
+            chroma_database: DEFAULT_DATABASE.to_string(),
+            chroma_auth: ChromaAuth::None,
+            chroma_tls: ChromaTls::default(),
+            chroma_timeout: DEFAULT_TIMEOUT,
+            chroma_retry: RetryPolicy::default(),
+            chroma_queue_dir: None,
+            chroma_managed: false,
+            chroma_command: "chroma".to_string(),
+            sqlite_path: PathBuf::new(),
+            qdrant_url: None,
+            qdrant_collection_name: "memory_collection".to_string(),
+            qdrant_api_key: None,
+            wal_path: None,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            hnsw: None,
+            quantization: None,
+            cache: None,
+            replicas: Vec::new(),
+            replica_retry_interval: DEFAULT_RETRY_INTERVAL,
+            namespace: DEFAULT_NAMESPACE.to_string(),
+            namespace_dir: PathBuf::new(),
+            read_only: false,
             embedding_model: EmbeddingModel::default(),
             embedding_model_path: None,
             embedding_size: 384, // Default embedding size
             log_level: "info".to_string(),
+            transport: Transport::default(),
+            listen_addr: SocketAddr::from(([127, 0, 0, 1], 8765)),
+            socket_path: PathBuf::new(),
+            api_keys: Vec::new(),
+            bulk_delete_threshold: confirmation::DEFAULT_BULK_DELETE_THRESHOLD,
+            migration_checkpoint_dir: PathBuf::new(),
+            migration_destinations: BTreeMap::new(),
         }
     }
 }
@@ -70,37 +193,208 @@ impl Config {
         // Load .env file if present (optional)
         let _ = dotenvy::dotenv();
 
+        Self::from_vars(&|name| env::var(name))
+    }
+
+    /// Load the configuration with some variables replaced, e.g. to describe
+    /// a second storage backend next to the one the server is configured with
+    pub fn with_overrides(overrides: &HashMap<String, String>) -> Result<Self> {
+        Self::from_vars(&|name| match overrides.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => env::var(name),
+        })
+    }
+
+    fn from_vars(var: &Vars) -> Result<Self> {
         // Start with default config
         let mut config = Config::default();
 
         // Storage backend
-        if let Ok(backend) = env::var("MCP_MEMORY_STORAGE_BACKEND") {
+        if let Ok(backend) = var("MCP_MEMORY_STORAGE_BACKEND") {
             config.storage_backend = match backend.to_lowercase().as_str() {
                 "chromadb" => StorageBackend::ChromaDB,
+                "sqlite" => StorageBackend::Sqlite,
+                "qdrant" => StorageBackend::Qdrant,
                 _ => StorageBackend::InMemory,
             };
         }
 
+        // Read-only mode
+        if let Ok(read_only) = var("MCP_MEMORY_READ_ONLY") {
+            config.read_only = matches!(read_only.to_lowercase().as_str(), "1" | "true" | "yes");
+        }
+
         // ChromaDB configuration
-        let chroma_path_str = env::var("MCP_MEMORY_CHROMA_PATH")
+        let chroma_path_str = var("MCP_MEMORY_CHROMA_PATH")
             .or_else(|_| Self::get_default_path("chroma_db"))?;
         config.chroma_db_path = PathBuf::from(chroma_path_str);
 
-        // Validate and create path
-        Self::validate_or_create_path(&config.chroma_db_path)?;
+        // Validate and create path, unless nothing may be written
+        if !config.read_only {
+            Self::validate_or_create_path(&config.chroma_db_path)?;
+        }
 
         // ChromaDB URL (optional, for remote ChromaDB)
-        if let Ok(url) = env::var("MCP_MEMORY_CHROMA_URL") {
+        if let Ok(url) = var("MCP_MEMORY_CHROMA_URL") {
             config.chroma_db_url = Some(Url::parse(&url).context("Invalid ChromaDB URL")?);
         }
 
         // ChromaDB collection name
-        if let Ok(collection) = env::var("MCP_MEMORY_CHROMA_COLLECTION") {
+        if let Ok(collection) = var("MCP_MEMORY_CHROMA_COLLECTION") {
             config.chroma_collection_name = collection;
         }
 
+        // ChromaDB tenant and database
+        if let Ok(tenant) = var("MCP_MEMORY_CHROMA_TENANT") {
+            config.chroma_tenant = tenant;
+        }
+        if let Ok(database) = var("MCP_MEMORY_CHROMA_DATABASE") {
+            config.chroma_database = database;
+        }
+
+        // ChromaDB authentication: a token (sent as a bearer token or X-Chroma-Token) or basic auth
+        if let Some(token) = Self::load_secret(var, "MCP_MEMORY_CHROMA_TOKEN")? {
+            config.chroma_auth = match var("MCP_MEMORY_CHROMA_AUTH_HEADER").unwrap_or_default().to_lowercase().as_str() {
+                "x-chroma-token" => ChromaAuth::ChromaToken(token),
+                _ => ChromaAuth::Bearer(token),
+            };
+        } else if let Ok(username) = var("MCP_MEMORY_CHROMA_USERNAME") {
+            let password = Self::load_secret(var, "MCP_MEMORY_CHROMA_PASSWORD")?
+                .context("MCP_MEMORY_CHROMA_USERNAME is set but no ChromaDB password is configured")?;
+            config.chroma_auth = ChromaAuth::Basic { username, password };
+        }
+
+        // ChromaDB TLS
+        config.chroma_tls = ChromaTls {
+            ca_cert: var("MCP_MEMORY_CHROMA_CA_CERT").ok().map(PathBuf::from),
+            client_cert: var("MCP_MEMORY_CHROMA_CLIENT_CERT").ok().map(PathBuf::from),
+            client_key: var("MCP_MEMORY_CHROMA_CLIENT_KEY").ok().map(PathBuf::from),
+        };
+
+        // Local ChromaDB server launched and supervised by this process
+        if let Ok(managed) = var("MCP_MEMORY_CHROMA_MANAGED") {
+            config.chroma_managed = matches!(managed.to_lowercase().as_str(), "1" | "true" | "yes");
+        }
+        if let Ok(command) = var("MCP_MEMORY_CHROMA_COMMAND") {
+            config.chroma_command = command;
+        }
+
+        // ChromaDB request timeout in seconds
+        if let Ok(timeout) = var("MCP_MEMORY_CHROMA_TIMEOUT") {
+            config.chroma_timeout = Duration::from_secs(timeout.parse().context("Invalid ChromaDB timeout")?);
+        }
+
+        // Retries of failed ChromaDB requests, backoff in milliseconds, and the circuit breaker
+        if let Ok(retries) = var("MCP_MEMORY_CHROMA_RETRIES") {
+            config.chroma_retry.max_retries = retries.parse().context("Invalid ChromaDB retry count")?;
+        }
+        if let Ok(backoff) = var("MCP_MEMORY_CHROMA_RETRY_BACKOFF") {
+            config.chroma_retry.initial_backoff = Duration::from_millis(backoff.parse().context("Invalid ChromaDB retry backoff")?);
+        }
+        if let Ok(backoff) = var("MCP_MEMORY_CHROMA_RETRY_MAX_BACKOFF") {
+            config.chroma_retry.max_backoff = Duration::from_millis(backoff.parse().context("Invalid ChromaDB maximum retry backoff")?);
+        }
+        if let Ok(threshold) = var("MCP_MEMORY_CHROMA_BREAKER_THRESHOLD") {
+            config.chroma_retry.breaker_threshold = threshold.parse().context("Invalid ChromaDB circuit breaker threshold")?;
+        }
+        if let Ok(cooldown) = var("MCP_MEMORY_CHROMA_BREAKER_COOLDOWN") {
+            config.chroma_retry.breaker_cooldown = Duration::from_secs(cooldown.parse().context("Invalid ChromaDB circuit breaker cooldown")?);
+        }
+
+        // Queue of writes waiting for ChromaDB to come back; an empty path disables it
+        config.chroma_queue_dir = match var("MCP_MEMORY_CHROMA_QUEUE_PATH") {
+            Ok(path) if path.is_empty() => None,
+            Ok(path) => Some(PathBuf::from(path)),
+            Err(_) => Some(PathBuf::from(Self::get_default_path("chroma_queue")?)),
+        };
+
+        // SQLite database file
+        let sqlite_path_str = var("MCP_MEMORY_SQLITE_PATH")
+            .or_else(|_| Self::get_default_path("memory.db"))?;
+        config.sqlite_path = PathBuf::from(sqlite_path_str);
+
+        // Qdrant configuration
+        if let Ok(url) = var("MCP_MEMORY_QDRANT_URL") {
+            config.qdrant_url = Some(Url::parse(&url).context("Invalid Qdrant URL")?);
+        }
+        if let Ok(collection) = var("MCP_MEMORY_QDRANT_COLLECTION") {
+            config.qdrant_collection_name = collection;
+        }
+        config.qdrant_api_key = Self::load_secret(var, "MCP_MEMORY_QDRANT_API_KEY")?;
+
+        // Write-ahead log for the in-memory backend (optional, enables durability)
+        if let Ok(path) = var("MCP_MEMORY_WAL_PATH") {
+            config.wal_path = Some(PathBuf::from(path));
+        }
+        if let Ok(interval) = var("MCP_MEMORY_SNAPSHOT_INTERVAL") {
+            config.snapshot_interval = interval.parse().context("Invalid snapshot interval")?;
+        }
+
+        // HNSW index for similarity search in the in-memory backend, off unless asked for
+        if let Ok(enabled) = var("MCP_MEMORY_HNSW") {
+            config.hnsw = matches!(enabled.to_lowercase().as_str(), "1" | "true" | "yes").then(HnswConfig::default);
+        }
+        if let Some(hnsw) = &mut config.hnsw {
+            if let Ok(m) = var("MCP_MEMORY_HNSW_M") {
+                hnsw.m = m.parse().context("Invalid HNSW M")?;
+            }
+            if let Ok(ef) = var("MCP_MEMORY_HNSW_EF_CONSTRUCTION") {
+                hnsw.ef_construction = ef.parse().context("Invalid HNSW ef_construction")?;
+            }
+            if let Ok(ef) = var("MCP_MEMORY_HNSW_EF_SEARCH") {
+                hnsw.ef_search = ef.parse().context("Invalid HNSW ef_search")?;
+            }
+            if let Ok(threshold) = var("MCP_MEMORY_HNSW_EXACT_THRESHOLD") {
+                hnsw.exact_threshold = threshold.parse().context("Invalid HNSW exact search threshold")?;
+            }
+        }
+
+        // Quantised embeddings for similarity search in the in-process backends
+        if let Ok(quantization) = var("MCP_MEMORY_QUANTIZATION")
+            && !matches!(quantization.to_lowercase().as_str(), "" | "none")
+        {
+            config.quantization = Some(QuantizationConfig::new(quantization.parse::<Quantization>()?));
+        }
+        if let Some(quantization) = &mut config.quantization
+            && let Ok(oversampling) = var("MCP_MEMORY_QUANTIZATION_OVERSAMPLING")
+        {
+            quantization.oversampling = oversampling.parse().context("Invalid quantization oversampling")?;
+        }
+
+        // Cache in front of the storage backend
+        if let Ok(enabled) = var("MCP_MEMORY_CACHE")
+            && matches!(enabled.to_lowercase().as_str(), "1" | "true" | "yes")
+        {
+            config.cache = Some(CacheConfig::default());
+        }
+        if let Some(cache) = &mut config.cache {
+            if let Ok(capacity) = var("MCP_MEMORY_CACHE_CAPACITY") {
+                cache.capacity = capacity.parse().context("Invalid cache capacity")?;
+            }
+            if let Ok(ttl) = var("MCP_MEMORY_CACHE_QUERY_TTL") {
+                cache.query_ttl = Duration::from_secs(ttl.parse().context("Invalid cache query TTL")?);
+            }
+        }
+
+        // Secondary storages for replication and failover
+        if let Ok(replicas) = var("MCP_MEMORY_REPLICAS") {
+            config.replicas = replicas.split(',').map(str::trim).filter(|path| !path.is_empty()).map(PathBuf::from).collect();
+        }
+        if let Ok(interval) = var("MCP_MEMORY_REPLICA_RETRY_INTERVAL") {
+            config.replica_retry_interval = Duration::from_secs(interval.parse().context("Invalid replica retry interval")?);
+        }
+
+        // Namespace of tools called without one, and the registry of namespaces
+        if let Ok(namespace) = var("MCP_MEMORY_NAMESPACE") {
+            validate_namespace(&namespace)?;
+            config.namespace = namespace;
+        }
+        let namespace_dir_str = var("MCP_MEMORY_NAMESPACE_DIR")
+            .or_else(|_| Self::get_default_path("namespaces"))?;
+        config.namespace_dir = PathBuf::from(namespace_dir_str);
+
         // Embedding model
-        if let Ok(model) = env::var("MCP_MEMORY_EMBEDDING_MODEL") {
+        if let Ok(model) = var("MCP_MEMORY_EMBEDDING_MODEL") {
             config.embedding_model = match model.to_lowercase().as_str() {
                 "onnx" => EmbeddingModel::Onnx,
                 _ => EmbeddingModel::Dummy,
@@ -108,22 +402,72 @@ impl Config {
         }
 
         // Embedding model path
-        if let Ok(path) = env::var("MCP_MEMORY_EMBEDDING_MODEL_PATH") {
+        if let Ok(path) = var("MCP_MEMORY_EMBEDDING_MODEL_PATH") {
             config.embedding_model_path = Some(PathBuf::from(path));
         }
 
         // Embedding size
-        if let Ok(size) = env::var("MCP_MEMORY_EMBEDDING_SIZE") {
-            if let Ok(size) = size.parse::<usize>() {
-                config.embedding_size = size;
-            }
+        if let Ok(size) = var("MCP_MEMORY_EMBEDDING_SIZE")
+            && let Ok(size) = size.parse::<usize>()
+        {
+            config.embedding_size = size;
         }
 
         // Log level
-        if let Ok(level) = env::var("MCP_MEMORY_LOG_LEVEL") {
+        if let Ok(level) = var("MCP_MEMORY_LOG_LEVEL") {
             config.log_level = level;
         }
 
+        // Transport
+        if let Ok(transport) = var("MCP_MEMORY_TRANSPORT") {
+            config.transport = match transport.to_lowercase().as_str() {
+                "http" | "streamable-http" | "sse" => Transport::Http,
+                "unix" => Transport::Unix,
+                _ => Transport::Stdio,
+            };
+        }
+
+        // Listen address for the HTTP transport
+        if let Ok(addr) = var("MCP_MEMORY_LISTEN_ADDR") {
+            config.listen_addr = addr.parse().context("Invalid listen address")?;
+        }
+
+        // Socket path for the Unix socket transport and the stdio bridge
+        let socket_path_str = var("MCP_MEMORY_SOCKET_PATH")
+            .or_else(|_| Self::get_default_path("memory.sock"))?;
+        config.socket_path = PathBuf::from(socket_path_str);
+
+        // API keys, as comma-separated `key:scope` entries and/or a file with one entry per line
+        if let Ok(keys) = var("MCP_MEMORY_API_KEYS") {
+            config.api_keys.extend(Self::parse_api_keys(keys.split(','))?);
+        }
+        if let Ok(path) = var("MCP_MEMORY_API_KEYS_FILE") {
+            let keys = std::fs::read_to_string(&path)
+                .context(format!("Failed to read API keys file: {}", path))?;
+            config.api_keys.extend(Self::parse_api_keys(keys.lines())?);
+        }
+
+        // Confirmation threshold for bulk deletions
+        if let Ok(threshold) = var("MCP_MEMORY_BULK_DELETE_THRESHOLD") {
+            config.bulk_delete_threshold = threshold.parse().context("Invalid bulk delete threshold")?;
+        }
+
+        // Checkpoints of migrations between storage backends
+        let checkpoint_dir_str = var("MCP_MEMORY_MIGRATION_CHECKPOINT_DIR")
+            .or_else(|_| Self::get_default_path("migrations"))?;
+        config.migration_checkpoint_dir = PathBuf::from(checkpoint_dir_str);
+
+        // Destinations of the migrate_memories tool, as comma-separated `name=path` entries
+        if let Ok(destinations) = var("MCP_MEMORY_MIGRATION_DESTINATIONS") {
+            for entry in destinations.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+                let (name, path) = entry
+                    .split_once('=')
+                    .filter(|(name, path)| !name.trim().is_empty() && !path.trim().is_empty())
+                    .context(format!("Invalid migration destination '{}': expected name=path", entry))?;
+                config.migration_destinations.insert(name.trim().to_string(), PathBuf::from(path.trim()));
+            }
+        }
+
         tracing::info!("Using ChromaDB path: {:?}", config.chroma_db_path);
         if let Some(url) = &config.chroma_db_url {
             tracing::info!("Using ChromaDB URL: {}", url);
@@ -132,6 +476,68 @@ impl Config {
         Ok(config)
     }
 
+    /// Identifies the configured storage location, e.g. to tell migrations apart
+    pub fn storage_description(&self) -> String {
+        match self.storage_backend {
+            StorageBackend::InMemory => match &self.wal_path {
+                Some(path) => format!("inmemory:{}", path.display()),
+                None => "inmemory".to_string(),
+            },
+            StorageBackend::Sqlite => format!("sqlite:{}", self.sqlite_path.display()),
+            StorageBackend::ChromaDB => {
+                let location = match &self.chroma_db_url {
+                    Some(url) => url.to_string(),
+                    None => self.chroma_db_path.display().to_string(),
+                };
+                format!("chromadb:{}/{}/{}/{}", location, self.chroma_tenant, self.chroma_database, self.chroma_collection_name)
+            }
+            StorageBackend::Qdrant => {
+                let location = self.qdrant_url.as_ref().map(Url::to_string).unwrap_or_else(|| "http://localhost:6333/".to_string());
+                format!("qdrant:{}/{}", location, self.qdrant_collection_name)
+            }
+        }
+    }
+
+    /// Settings of the storage holding a namespace. The default namespace is
+    /// the configured storage itself; any other gets a collection, database
+    /// file or log directory of its own next to it.
+    pub fn for_namespace(&self, namespace: &str) -> Config {
+        let mut config = self.clone();
+        if namespace == DEFAULT_NAMESPACE {
+            return config;
+        }
+        config.chroma_collection_name = format!("{}-{}", self.chroma_collection_name, namespace);
+        config.qdrant_collection_name = format!("{}-{}", self.qdrant_collection_name, namespace);
+        let stem = self.sqlite_path.file_stem().unwrap_or_default().to_string_lossy();
+        config.sqlite_path = match self.sqlite_path.extension() {
+            Some(extension) => self.sqlite_path.with_file_name(format!("{}-{}.{}", stem, namespace, extension.to_string_lossy())),
+            None => self.sqlite_path.with_file_name(format!("{}-{}", stem, namespace)),
+        };
+        config.wal_path = self.wal_path.as_ref().map(|dir| dir.join("namespaces").join(namespace));
+        config
+    }
+
+    /// Read a secret from the variable itself or from the file named by `<name>_FILE`
+    fn load_secret(var: &Vars, name: &str) -> Result<Option<Secret>> {
+        if let Ok(value) = var(name) {
+            return Ok(Some(Secret::new(value)));
+        }
+        if let Ok(path) = var(&format!("{}_FILE", name)) {
+            let value = std::fs::read_to_string(&path)
+                .context(format!("Failed to read secret file: {}", path))?;
+            return Ok(Some(Secret::new(value.trim_end_matches(['\r', '\n']).to_string())));
+        }
+        Ok(None)
+    }
+
+    fn parse_api_keys<'a>(entries: impl Iterator<Item = &'a str>) -> Result<Vec<ApiKey>> {
+        entries
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty() && !entry.starts_with('#'))
+            .map(|entry| entry.parse::<ApiKey>().context("Invalid API key entry"))
+            .collect()
+    }
+
     fn get_default_path(sub_dir: &str) -> Result<String> {
         let proj_dirs = ProjectDirs::from("ai", "Anthropic", "MCPMemoryService")
             .context("Failed to get project directories")?;
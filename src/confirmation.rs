@@ -0,0 +1,249 @@
+use crate::models::Memory;
+use rmcp::{
+    model::{CreateElicitationRequestParam, ElicitationAction},
+    ErrorData as McpError, Peer, RoleServer,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Bulk deletions affecting more than this many memories need confirmation
+pub const DEFAULT_BULK_DELETE_THRESHOLD: usize = 10;
+/// How long a confirmation token stays valid
+pub const TOKEN_TTL: Duration = Duration::from_secs(300);
+
+// Number of memories listed in full in a deletion preview
+const PREVIEW_LIMIT: usize = 10;
+const PREVIEW_CONTENT_CHARS: usize = 80;
+
+/// Outcome of asking for confirmation of a deletion
+pub enum Confirmation {
+    /// The user explicitly accepted, or a valid token was echoed back
+    Confirmed,
+    /// The user declined or cancelled the elicitation
+    Rejected,
+    /// The client can't elicit; the message carries a token to echo back
+    TokenRequired(String),
+}
+
+/// Confirmation tokens issued to a session. Each token is bound to the exact
+/// set of memories it was issued for and can be redeemed once.
+#[derive(Default)]
+pub struct PendingConfirmations {
+    tokens: Mutex<HashMap<String, (String, Instant)>>,
+    counter: AtomicU64,
+}
+
+impl PendingConfirmations {
+    fn issue(&self, operation: &str) -> String {
+        let nonce = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let count = self.counter.fetch_add(1, Ordering::Relaxed);
+        let token = hex::encode(&Sha256::digest(format!("{}:{}:{}", operation, nonce, count).as_bytes())[..8]);
+
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.retain(|_, (_, issued)| issued.elapsed() < TOKEN_TTL);
+        tokens.insert(token.clone(), (operation.to_string(), Instant::now()));
+        token
+    }
+
+    fn redeem(&self, token: &str, operation: &str) -> bool {
+        match self.tokens.lock().unwrap().remove(token.trim()) {
+            Some((issued_for, issued)) => issued_for == operation && issued.elapsed() < TOKEN_TTL,
+            None => false,
+        }
+    }
+}
+
+// Identifies a deletion by the set of memories it removes
+fn operation_digest(memories: &[Memory]) -> String {
+    let mut hashes: Vec<&str> = memories.iter().map(|memory| memory.content_hash.as_str()).collect();
+    hashes.sort_unstable();
+    hex::encode(Sha256::digest(hashes.join(",").as_bytes()))
+}
+
+/// Human-readable list of the memories a deletion would remove
+pub fn preview(memories: &[Memory]) -> String {
+    let mut lines: Vec<String> = memories
+        .iter()
+        .take(PREVIEW_LIMIT)
+        .map(|memory| {
+            let mut content: String = memory.content.chars().take(PREVIEW_CONTENT_CHARS).collect();
+            if memory.content.chars().count() > PREVIEW_CONTENT_CHARS {
+                content.push_str("...");
+            }
+            format!("- {} [{}] {}", memory.content_hash, memory.tags.join(", "), content)
+        })
+        .collect();
+    if memories.len() > PREVIEW_LIMIT {
+        lines.push(format!("... and {} more", memories.len() - PREVIEW_LIMIT));
+    }
+    lines.join("\n")
+}
+
+/// Ask for confirmation before deleting the given memories.
+///
+/// Clients supporting elicitation ask the user directly, and only an explicit
+/// acceptance confirms; tokens are ignored for them, so that an agent can't
+/// confirm on the user's behalf. Other clients get a token that must be passed
+/// back as `confirmation_token` in a repeated call for the same memories.
+pub async fn confirm_deletion(
+    peer: &Peer<RoleServer>,
+    pending: &PendingConfirmations,
+    memories: &[Memory],
+    token: Option<&str>,
+) -> Result<Confirmation, McpError> {
+    let operation = operation_digest(memories);
+    let summary = format!("Delete {} memor{}?\n{}", memories.len(), if memories.len() == 1 { "y" } else { "ies" }, preview(memories));
+
+    if !peer.supports_elicitation() {
+        if let Some(token) = token
+            && pending.redeem(token, &operation)
+        {
+            return Ok(Confirmation::Confirmed);
+        }
+        let reason = match token {
+            Some(_) => "The confirmation token is invalid, expired or was issued for different memories.",
+            None => "Deletion needs confirmation.",
+        };
+        let token = pending.issue(&operation);
+        return Ok(Confirmation::TokenRequired(format!(
+            "{}\n{}\nTo confirm, repeat the call with confirmation_token \"{}\".",
+            reason, summary, token
+        )));
+    }
+
+    let requested_schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "confirm": {
+                "type": "boolean",
+                "title": "Delete",
+                "description": "Permanently delete the memories listed above"
+            }
+        },
+        "required": ["confirm"]
+    });
+    let result = peer
+        .create_elicitation(CreateElicitationRequestParam {
+            message: summary,
+            requested_schema: requested_schema.as_object().cloned().unwrap_or_default(),
+        })
+        .await
+        .map_err(|e| McpError::internal_error(format!("Elicitation request failed: {}", e), None))?;
+
+    let confirmed = result.action == ElicitationAction::Accept
+        && result
+            .content
+            .as_ref()
+            .and_then(|content| content.get("confirm"))
+            .and_then(|confirm| confirm.as_bool())
+            .unwrap_or(false);
+    Ok(if confirmed { Confirmation::Confirmed } else { Confirmation::Rejected })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::memory;
+    use rmcp::{
+        model::{ClientCapabilities, ClientInfo, CreateElicitationResult},
+        service::{RequestContext, RunningService},
+        ClientHandler, RoleClient, ServerHandler, ServiceExt,
+    };
+    use std::sync::{atomic::AtomicUsize, Arc};
+
+    struct Server;
+
+    impl ServerHandler for Server {}
+
+    // Client whose user answers every elicitation the same way, or that can't elicit at all
+    #[derive(Clone)]
+    struct Client {
+        elicitation: Option<ElicitationAction>,
+        elicited: Arc<AtomicUsize>,
+    }
+
+    impl ClientHandler for Client {
+        fn get_info(&self) -> ClientInfo {
+            let capabilities = match self.elicitation {
+                Some(_) => ClientCapabilities::builder().enable_elicitation().build(),
+                None => ClientCapabilities::default(),
+            };
+            ClientInfo { capabilities, ..Default::default() }
+        }
+
+        async fn create_elicitation(
+            &self,
+            _request: CreateElicitationRequestParam,
+            _context: RequestContext<RoleClient>,
+        ) -> Result<CreateElicitationResult, McpError> {
+            self.elicited.fetch_add(1, Ordering::SeqCst);
+            let action = self.elicitation.clone().unwrap_or(ElicitationAction::Decline);
+            let content = (action == ElicitationAction::Accept).then(|| serde_json::json!({ "confirm": true }));
+            Ok(CreateElicitationResult { action, content })
+        }
+    }
+
+    // Server side of a session with the given client, and the client to keep it open
+    async fn session(client: Client) -> (Peer<RoleServer>, RunningService<RoleClient, Client>) {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        let (server, client) = tokio::join!(Server.serve(server_io), client.serve(client_io));
+        let server = server.unwrap();
+        let peer = server.peer().clone();
+        tokio::spawn(server.waiting());
+        (peer, client.unwrap())
+    }
+
+    fn token_in(confirmation: Confirmation) -> String {
+        match confirmation {
+            Confirmation::TokenRequired(message) => message.rsplit('"').nth(1).unwrap().to_string(),
+            _ => panic!("expected a token to be required"),
+        }
+    }
+
+    #[tokio::test]
+    async fn clients_without_elicitation_confirm_with_a_token() {
+        let (peer, _client) = session(Client { elicitation: None, elicited: Arc::default() }).await;
+        let pending = PendingConfirmations::default();
+        let memories = [memory("a", &[], 0, &[]), memory("b", &[], 0, &[])];
+
+        let token = token_in(confirm_deletion(&peer, &pending, &memories, None).await.unwrap());
+        // Issued for the same memories in any order, but not for others
+        let other = confirm_deletion(&peer, &pending, &[memory("a", &[], 0, &[])], Some(&token)).await.unwrap();
+        assert!(matches!(other, Confirmation::TokenRequired(_)));
+        let token = token_in(confirm_deletion(&peer, &pending, &memories, Some("invalid")).await.unwrap());
+        let reversed = [memory("b", &[], 0, &[]), memory("a", &[], 0, &[])];
+        assert!(matches!(confirm_deletion(&peer, &pending, &reversed, Some(&token)).await.unwrap(), Confirmation::Confirmed));
+        // Tokens can be redeemed once
+        let reused = confirm_deletion(&peer, &pending, &memories, Some(&token)).await.unwrap();
+        assert!(matches!(reused, Confirmation::TokenRequired(_)));
+    }
+
+    #[tokio::test]
+    async fn clients_with_elicitation_always_ask_the_user() {
+        let elicited = Arc::new(AtomicUsize::new(0));
+        let (peer, _client) = session(Client { elicitation: Some(ElicitationAction::Decline), elicited: elicited.clone() }).await;
+        let pending = PendingConfirmations::default();
+        let memories = [memory("a", &[], 0, &[])];
+
+        // A valid token doesn't skip asking
+        let token = pending.issue(&operation_digest(&memories));
+        assert!(matches!(confirm_deletion(&peer, &pending, &memories, Some(&token)).await.unwrap(), Confirmation::Rejected));
+        // Nor is a token handed out for an invalid one
+        assert!(matches!(confirm_deletion(&peer, &pending, &memories, Some("invalid")).await.unwrap(), Confirmation::Rejected));
+        assert_eq!(elicited.load(Ordering::SeqCst), 2);
+
+        let (peer, _client) = session(Client { elicitation: Some(ElicitationAction::Accept), elicited: elicited.clone() }).await;
+        assert!(matches!(confirm_deletion(&peer, &pending, &memories, None).await.unwrap(), Confirmation::Confirmed));
+        assert_eq!(elicited.load(Ordering::SeqCst), 3);
+    }
+}
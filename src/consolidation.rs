@@ -0,0 +1,109 @@
+use crate::models::Memory;
+use crate::roots::ROOT_METADATA_KEY;
+use crate::storage::{MemoryStorage, StorageError};
+use crate::utils;
+use anyhow::Result;
+use std::collections::{BTreeSet, HashMap};
+
+/// Memory type given to summaries produced by `consolidate_memories`
+pub const CONSOLIDATED_MEMORY_TYPE: &str = "consolidated";
+/// Tag added to source memories when they are archived after consolidation
+pub const ARCHIVED_TAG: &str = "archived";
+
+pub const DEFAULT_MAX_MEMORIES: usize = 20;
+pub const DEFAULT_MAX_TOKENS: u32 = 1024;
+/// A cluster needs at least this many memories to be worth consolidating
+pub const MIN_MEMORIES: usize = 2;
+
+// Metadata keys linking summaries and their sources
+pub const CONSOLIDATED_FROM_KEY: &str = "consolidated_from";
+pub const CONSOLIDATED_INTO_KEY: &str = "consolidated_into";
+pub const ARCHIVED_FROM_KEY: &str = "archived_from";
+const SOURCE_COUNT_KEY: &str = "source_count";
+const SUMMARY_MODEL_KEY: &str = "summary_model";
+
+pub const SYSTEM_PROMPT: &str = "You consolidate fragments of a long-term memory store. \
+Merge the memories you are given into one concise summary that keeps every distinct fact, decision and preference. \
+Drop duplicates, and where memories contradict each other prefer the more recent one. \
+Reply with the summary text only.";
+
+/// Build the sampling prompt listing the memories in chronological order
+pub fn build_prompt(memories: &[Memory]) -> String {
+    let listing = memories
+        .iter()
+        .enumerate()
+        .map(|(i, memory)| {
+            format!(
+                "Memory {} ({}, tags: {}):\n{}",
+                i + 1,
+                memory.timestamp().format("%Y-%m-%d %H:%M UTC"),
+                if memory.tags.is_empty() { "none".to_string() } else { memory.tags.join(", ") },
+                memory.content
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    format!("Consolidate the following {} memories into a single summary:\n\n{}", memories.len(), listing)
+}
+
+/// Create the summary memory, linked to its sources through metadata and
+/// stamped with the client root it was consolidated in, if any
+pub fn summary_memory(summary: String, sources: &[Memory], model: &str, root: Option<&String>) -> Result<Memory> {
+    // Keep the union of the source tags so the summary is found by the same tag searches
+    let tags: BTreeSet<String> = sources
+        .iter()
+        .flat_map(|memory| memory.tags.iter().cloned())
+        .filter(|tag| tag != ARCHIVED_TAG)
+        .collect();
+
+    let mut metadata = HashMap::new();
+    metadata.insert(
+        CONSOLIDATED_FROM_KEY.to_string(),
+        sources.iter().map(|memory| memory.content_hash.as_str()).collect::<Vec<_>>().join(","),
+    );
+    metadata.insert(SOURCE_COUNT_KEY.to_string(), sources.len().to_string());
+    metadata.insert(SUMMARY_MODEL_KEY.to_string(), model.to_string());
+    if let Some(root) = root {
+        metadata.insert(ROOT_METADATA_KEY.to_string(), root.clone());
+    }
+
+    let content_hash = utils::generate_content_hash(&summary, &metadata)?;
+
+    Ok(Memory {
+        content: summary,
+        content_hash,
+        tags: tags.into_iter().collect(),
+        memory_type: Some(CONSOLIDATED_MEMORY_TYPE.to_string()),
+        timestamp_seconds: utils::get_current_timestamp().timestamp(),
+        metadata,
+        embedding: None,
+    })
+}
+
+/// Copy of a source memory marked as archived. It gets a hash of its own, so
+/// it can be stored before the source is deleted; `archived_from` holds the
+/// source's hash, which the summary's `consolidated_from` links refer to.
+pub fn archived_copy(memory: &Memory, summary_hash: &str) -> Result<Memory> {
+    let mut archived = memory.clone();
+    if !archived.tags.iter().any(|tag| tag == ARCHIVED_TAG) {
+        archived.tags.push(ARCHIVED_TAG.to_string());
+    }
+    archived.metadata.insert(CONSOLIDATED_INTO_KEY.to_string(), summary_hash.to_string());
+    archived.metadata.insert(ARCHIVED_FROM_KEY.to_string(), memory.content_hash.clone());
+    archived.content_hash = utils::generate_content_hash(&archived.content, &archived.metadata)?;
+    Ok(archived)
+}
+
+/// Replace a source memory with its archived copy. The storage backends have
+/// no update, so the copy is stored first and the source deleted after, so
+/// that a failure never loses the memory. A copy stored by an earlier,
+/// interrupted run is as good as a new one.
+pub async fn archive(storage: &dyn MemoryStorage, memory: &Memory, summary_hash: &str) -> Result<()> {
+    let archived = archived_copy(memory, summary_hash)?;
+    storage.store(&archived).await?;
+    match storage.delete(&memory.content_hash).await {
+        Ok(_) | Err(StorageError::NotFound(_)) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
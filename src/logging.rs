@@ -0,0 +1,337 @@
+use rmcp::{
+    model::{LoggingLevel, LoggingMessageNotificationParam},
+    Peer, RoleServer,
+};
+use serde_json::{Map, Value};
+use std::{
+    fmt,
+    future::Future,
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::mpsc;
+use tracing::{field::Field, Event, Level, Subscriber};
+use tracing_subscriber::{layer::Context, Layer};
+
+/// Only events emitted by this crate are forwarded. Events from `rmcp` itself
+/// (which logs while sending notifications) would otherwise feed back into the
+/// transport.
+const FORWARDED_TARGET: &str = env!("CARGO_CRATE_NAME");
+
+/// Level used for a client until it sends `logging/setLevel`. Warnings and
+/// errors (Chroma failures, ONNX fallbacks, ...) reach the client by default.
+const DEFAULT_CLIENT_LEVEL: LoggingLevel = LoggingLevel::Warning;
+
+/// Server-wide records at or above this level are kept for clients that
+/// attach later, since startup problems are logged before any client exists
+const RECENT_LEVEL: LoggingLevel = LoggingLevel::Warning;
+/// Number of such records kept
+const RECENT_CAPACITY: usize = 32;
+
+tokio::task_local! {
+    // Client of the session whose request is being handled, if it has initialized
+    static SESSION_CLIENT: Option<Arc<ClientLogger>>;
+}
+
+/// Handle a request of a session, so that the events it logs are forwarded
+/// to that session's client only. Events logged outside any request, such as
+/// storage failing or recovering in the background, concern the whole server
+/// and are forwarded to every client.
+pub async fn in_session<F: Future>(client: Option<Arc<ClientLogger>>, request: F) -> F::Output {
+    SESSION_CLIENT.scope(client, request).await
+}
+
+#[derive(Debug, Clone)]
+struct LogRecord {
+    level: LoggingLevel,
+    logger: String,
+    data: Value,
+}
+
+// LoggingLevel has no ordering of its own, so rank it by severity
+fn severity(level: &LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+fn to_logging_level(level: &Level) -> LoggingLevel {
+    match *level {
+        Level::ERROR => LoggingLevel::Error,
+        Level::WARN => LoggingLevel::Warning,
+        Level::INFO => LoggingLevel::Info,
+        Level::DEBUG | Level::TRACE => LoggingLevel::Debug,
+    }
+}
+
+// Recent server-wide warnings and errors, numbered in the order they were logged
+#[derive(Default)]
+struct Recent {
+    records: VecDeque<(u64, LogRecord)>,
+    next: u64,
+}
+
+/// A single connected client that receives log notifications
+pub struct ClientLogger {
+    level: Mutex<LoggingLevel>,
+    sender: mpsc::UnboundedSender<LogRecord>,
+    recent: Arc<Mutex<Recent>>,
+    // Numbers of the recent records already sent, so that replays don't repeat them
+    delivered: Mutex<HashSet<u64>>,
+}
+
+impl ClientLogger {
+    /// Update the minimum level forwarded to this client (`logging/setLevel`).
+    /// Recent warnings and errors it now accepts are sent if it hasn't had them.
+    pub fn set_level(&self, level: LoggingLevel) {
+        *self.level.lock().unwrap() = level;
+        self.replay(&self.recent.lock().unwrap());
+    }
+
+    fn replay(&self, recent: &Recent) {
+        for (number, record) in &recent.records {
+            self.send_recent(*number, record);
+        }
+        let oldest = recent.records.front().map_or(recent.next, |(number, _)| *number);
+        self.delivered.lock().unwrap().retain(|number| *number >= oldest);
+    }
+
+    fn send_recent(&self, number: u64, record: &LogRecord) {
+        if self.accepts(&record.level) && self.delivered.lock().unwrap().insert(number) {
+            let _ = self.sender.send(record.clone());
+        }
+    }
+
+    fn accepts(&self, level: &LoggingLevel) -> bool {
+        severity(level) >= severity(&self.level.lock().unwrap())
+    }
+
+    fn send(&self, record: &LogRecord) {
+        if self.accepts(&record.level) {
+            // The receiving task only stops once the peer is gone
+            let _ = self.sender.send(record.clone());
+        }
+    }
+}
+
+/// Forwards tracing events to MCP clients as `notifications/message`.
+///
+/// The forwarder is shared between the tracing layer and the server; each
+/// session attaches its peer once the client is initialized. The last few
+/// server-wide warnings and errors are kept and replayed to clients as they
+/// attach.
+#[derive(Clone, Default)]
+pub struct LogForwarder {
+    clients: Arc<Mutex<Vec<Weak<ClientLogger>>>>,
+    recent: Arc<Mutex<Recent>>,
+}
+
+impl LogForwarder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracing layer feeding this forwarder
+    pub fn layer(&self) -> McpLoggingLayer {
+        McpLoggingLayer { forwarder: self.clone() }
+    }
+
+    /// Register a client peer. It receives events for as long as the returned
+    /// handle is alive, starting with the recent warnings and errors logged
+    /// before it attached.
+    pub fn attach(&self, peer: Peer<RoleServer>) -> Arc<ClientLogger> {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<LogRecord>();
+        let client = Arc::new(ClientLogger {
+            level: Mutex::new(DEFAULT_CLIENT_LEVEL),
+            sender,
+            recent: self.recent.clone(),
+            delivered: Mutex::default(),
+        });
+
+        tokio::spawn(async move {
+            while let Some(record) = receiver.recv().await {
+                let params = LoggingMessageNotificationParam {
+                    level: record.level,
+                    logger: Some(record.logger),
+                    data: record.data,
+                };
+                // Don't log failures here: that would only queue more messages for a dead peer
+                if peer.notify_logging_message(params).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Hold the recent records until the client is registered, so that a
+        // record logged meanwhile is either replayed or dispatched to it
+        let recent = self.recent.lock().unwrap();
+        client.replay(&recent);
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain(|c| c.strong_count() > 0);
+        clients.push(Arc::downgrade(&client));
+        drop(clients);
+        drop(recent);
+
+        client
+    }
+
+    fn dispatch(&self, record: LogRecord) {
+        if let Ok(session_client) = SESSION_CLIENT.try_with(Clone::clone) {
+            // Logged while handling a session's request, which no other client should see
+            if let Some(client) = session_client {
+                client.send(&record);
+            }
+            return;
+        }
+
+        if severity(&record.level) < severity(&RECENT_LEVEL) {
+            self.clients.lock().unwrap().retain(|client| match client.upgrade() {
+                Some(client) => {
+                    client.send(&record);
+                    true
+                }
+                None => false,
+            });
+            return;
+        }
+
+        let mut recent = self.recent.lock().unwrap();
+        let number = recent.next;
+        recent.next += 1;
+        if recent.records.len() == RECENT_CAPACITY {
+            recent.records.pop_front();
+        }
+        recent.records.push_back((number, record.clone()));
+        self.clients.lock().unwrap().retain(|client| match client.upgrade() {
+            Some(client) => {
+                client.send_recent(number, &record);
+                true
+            }
+            None => false,
+        });
+    }
+}
+
+/// `tracing_subscriber` layer that hands this crate's events to a [`LogForwarder`]
+pub struct McpLoggingLayer {
+    forwarder: LogForwarder,
+}
+
+impl<S: Subscriber> Layer<S> for McpLoggingLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if !metadata.target().starts_with(FORWARDED_TARGET) {
+            return;
+        }
+
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+
+        self.forwarder.dispatch(LogRecord {
+            level: to_logging_level(metadata.level()),
+            logger: metadata.target().to_string(),
+            data: Value::Object(visitor.fields),
+        });
+    }
+}
+
+// Collects event fields (including `message`) into a JSON object
+#[derive(Default)]
+struct JsonVisitor {
+    fields: Map<String, Value>,
+}
+
+impl tracing::field::Visit for JsonVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields.insert(field.name().to_string(), Value::from(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::{
+        model::LoggingMessageNotificationParam,
+        service::{NotificationContext, RunningService},
+        ClientHandler, RoleClient, ServerHandler, ServiceExt,
+    };
+    use tracing_subscriber::layer::SubscriberExt;
+
+    struct Server;
+
+    impl ServerHandler for Server {}
+
+    // Client passing on the messages it is sent
+    #[derive(Clone)]
+    struct Client(mpsc::UnboundedSender<LoggingMessageNotificationParam>);
+
+    impl ClientHandler for Client {
+        async fn on_logging_message(&self, params: LoggingMessageNotificationParam, _context: NotificationContext<RoleClient>) {
+            let _ = self.0.send(params);
+        }
+    }
+
+    async fn session(client: Client) -> (Peer<RoleServer>, RunningService<RoleClient, Client>) {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        let (server, client) = tokio::join!(Server.serve(server_io), client.serve(client_io));
+        let server = server.unwrap();
+        let peer = server.peer().clone();
+        tokio::spawn(server.waiting());
+        (peer, client.unwrap())
+    }
+
+    fn message(params: &LoggingMessageNotificationParam) -> &str {
+        params.data["message"].as_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn warnings_logged_before_a_client_attaches_are_replayed() {
+        let forwarder = LogForwarder::new();
+        let subscriber = tracing::Dispatch::new(tracing_subscriber::registry().with(forwarder.layer()));
+        tracing::dispatcher::with_default(&subscriber, || {
+            tracing::info!("Loading the embedding model");
+            tracing::warn!("Falling back to the hash embedding generator");
+        });
+
+        let (sender, mut received) = mpsc::unbounded_channel();
+        let (peer, _client) = session(Client(sender)).await;
+        let logger = forwarder.attach(peer);
+        let replayed = received.recv().await.unwrap();
+        assert_eq!(replayed.level, LoggingLevel::Warning);
+        assert_eq!(message(&replayed), "Falling back to the hash embedding generator");
+
+        // Lowering the level doesn't repeat the warning, and info was never kept
+        logger.set_level(LoggingLevel::Debug);
+        tracing::dispatcher::with_default(&subscriber, || tracing::error!("Storage is unavailable"));
+        let live = received.recv().await.unwrap();
+        assert_eq!(live.level, LoggingLevel::Error);
+        assert_eq!(message(&live), "Storage is unavailable");
+    }
+}
@@ -1,29 +1,73 @@
 use anyhow::{Result, Context};
 use rmcp::{
-    model::{CallToolResult, Content, Implementation, ProtocolVersion, ServerCapabilities, ServerInfo},
-    tool, Error as McpError, ServerHandler, ServiceExt,
-    transport::stdio,
+    handler::server::{router::tool::ToolRouter, tool::ToolCallContext, wrapper::Parameters},
+    model::{
+        CallToolRequestParam, CallToolResult, Content, CreateMessageRequestParam, ErrorCode, Implementation, ListToolsResult,
+        PaginatedRequestParam, ProtocolVersion, Role, SamplingMessage, ServerCapabilities, ServerInfo,
+        SetLevelRequestParam,
+    },
+    service::{NotificationContext, RequestContext},
+    tool, tool_router, ErrorData as McpError, Peer, RoleServer, ServerHandler,
 };
-use std::sync::Arc;
+use std::{collections::HashMap, path::Path, sync::{Arc, Mutex}};
 // *** Import MakeWriter trait ***
-use tracing_subscriber::{self, EnvFilter}; // Removed unused MakeWriter import
+use tracing_subscriber::{self, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer}; // Removed unused MakeWriter import
 
 // Module declarations
+mod auth;
 mod config;
+mod confirmation;
+mod consolidation;
 mod embeddings;
+mod logging;
+mod migrate;
 mod models;
+mod report;
+mod roots;
 mod storage;
+mod transport;
 mod utils;
 
 // Import specific items
 use config::Config;
+use confirmation::{Confirmation, PendingConfirmations};
 use embeddings::{EmbeddingGenerator, DummyEmbeddingGenerator, OnnxEmbeddingGenerator, EmbeddingError};
-use models::{StoreMemoryRequest, RetrieveMemoryRequest, SearchByTagRequest, DeleteMemoryRequest};
-use storage::{MemoryStorage, InMemoryStorage, ChromaMemoryStorage};
+use logging::{ClientLogger, LogForwarder};
+use migrate::MigrationReport;
+use models::{
+    StoreMemoryRequest, RetrieveMemoryRequest, SearchByTagRequest, DeleteMemoryRequest, DeleteByTagRequest, ConsolidateMemoriesRequest,
+    MigrateMemoriesRequest, CreateNamespaceRequest, DropNamespaceRequest,
+};
+use storage::{
+    MemoryStorage, CachedStorage, DeferredStorage, DeleteOutcome, InMemoryStorage, ChromaMemoryStorage, ChromaOptions, ChromaProcess,
+    NamespaceOpener, Namespaces, QdrantMemoryStorage, ReplicatedStorage, SqliteMemoryStorage, StorageError, StorageOpener, StoreOutcome,
+    WalConfig, DEFAULT_NAMESPACE,
+};
+
+// Error codes for storage failures with no standard code, from the range
+// JSON-RPC leaves to servers, after those of permission denied and resource
+// not found
+const STORAGE_UNAVAILABLE: ErrorCode = ErrorCode(-32003);
+const STORAGE_CONFLICT: ErrorCode = ErrorCode(-32004);
+const STORAGE_READ_ONLY: ErrorCode = ErrorCode(-32005);
 
 // Helper functions to convert errors to McpError
 fn to_mcp_error(error: anyhow::Error) -> McpError {
-    McpError::internal_error(error.to_string(), None)
+    storage_error_to_mcp(error.into())
+}
+
+// This function maps StorageError variants to McpError codes, naming the
+// variant in the error data so clients can react to it
+fn storage_error_to_mcp(error: StorageError) -> McpError {
+    let (code, kind) = match &error {
+        StorageError::NotFound(_) => (ErrorCode::RESOURCE_NOT_FOUND, "not_found"),
+        StorageError::InvalidFilter(_) => (ErrorCode::INVALID_PARAMS, "invalid_filter"),
+        StorageError::Unavailable(_) => (STORAGE_UNAVAILABLE, "unavailable"),
+        StorageError::Conflict(_) => (STORAGE_CONFLICT, "conflict"),
+        StorageError::ReadOnly(_) => (STORAGE_READ_ONLY, "read_only"),
+        StorageError::Other(_) => return McpError::internal_error(error.to_string(), None),
+    };
+    McpError::new(code, error.to_string(), Some(serde_json::json!({ "storage_error": kind })))
 }
 
 // This function maps EmbeddingError variants to McpError types
@@ -45,22 +89,146 @@ fn embedding_error_to_mcp(error: EmbeddingError) -> McpError {
 
 #[derive(Clone)]
 struct MemoryServer {
-    storage: Arc<dyn MemoryStorage>,
+    namespaces: Arc<Namespaces>,
     embedding_generator: Arc<dyn EmbeddingGenerator>,
+    log_forwarder: LogForwarder,
+    config: Arc<Config>,
+    // Per-session state, set once the client has initialized
+    client_logger: Arc<Mutex<Option<Arc<ClientLogger>>>>,
+    // Client roots, fetched lazily and refreshed when the client reports a change
+    roots: Arc<Mutex<Option<Vec<String>>>>,
+    pending_confirmations: Arc<PendingConfirmations>,
+    tool_router: ToolRouter<Self>,
 }
 
-#[tool(tool_box)]
+#[tool_router]
 impl MemoryServer {
-    fn new(storage: Arc<dyn MemoryStorage>, embedding_generator: Arc<dyn EmbeddingGenerator>) -> Self {
-        Self { storage, embedding_generator }
+    fn new(namespaces: Arc<Namespaces>, embedding_generator: Arc<dyn EmbeddingGenerator>, log_forwarder: LogForwarder, config: Arc<Config>) -> Self {
+        let mut tool_router = Self::tool_router();
+        if config.read_only {
+            // Only tools annotated as read-only are offered, so new mutating tools are excluded without being listed here
+            for tool in tool_router.list_all() {
+                if tool.annotations.as_ref().and_then(|annotations| annotations.read_only_hint) != Some(true) {
+                    tool_router.remove_route(&tool.name);
+                }
+            }
+        }
+        Self {
+            namespaces,
+            embedding_generator,
+            log_forwarder,
+            config,
+            client_logger: Arc::new(Mutex::new(None)),
+            roots: Arc::new(Mutex::new(None)),
+            pending_confirmations: Arc::new(PendingConfirmations::default()),
+            tool_router,
+        }
+    }
+
+    /// Server instance for a new client session. Storage and embeddings are
+    /// shared with every other session; per-session state starts out empty.
+    fn new_session(&self) -> Self {
+        Self {
+            client_logger: Arc::new(Mutex::new(None)),
+            roots: Arc::new(Mutex::new(None)),
+            pending_confirmations: Arc::new(PendingConfirmations::default()),
+            ..self.clone()
+        }
+    }
+
+    /// Storage holding the requested namespace, or the server's default one
+    async fn storage(&self, namespace: Option<&str>) -> Result<Arc<dyn MemoryStorage>, McpError> {
+        let name = namespace.unwrap_or(&self.config.namespace);
+        match self.namespaces.storage(name).await.map_err(to_mcp_error)? {
+            Some(storage) => Ok(storage),
+            None => Err(McpError::invalid_params(
+                format!("Unknown namespace '{}'; create it with create_namespace", name),
+                None,
+            )),
+        }
+    }
+
+    /// Roots of the client on the other end of this session. Empty if the
+    /// client doesn't support roots, in which case nothing is scoped.
+    async fn session_roots(&self, peer: &Peer<RoleServer>) -> Vec<String> {
+        if let Some(roots) = self.roots.lock().unwrap().clone() {
+            return roots;
+        }
+        let roots = roots::fetch_roots(peer).await;
+        if let Some(roots) = &roots {
+            *self.roots.lock().unwrap() = Some(roots.clone());
+        }
+        roots.unwrap_or_default()
+    }
+
+    /// Ask for confirmation before deleting the given memories. Returns the
+    /// tool result to send back instead if the deletion must not go ahead yet.
+    async fn confirm_deletion(&self, peer: &Peer<RoleServer>, memories: &[models::Memory], token: Option<&str>) -> Result<Option<CallToolResult>, McpError> {
+        match confirmation::confirm_deletion(peer, &self.pending_confirmations, memories, token).await? {
+            Confirmation::Confirmed => Ok(None),
+            Confirmation::Rejected => Ok(Some(CallToolResult::error(vec![Content::text(
+                "Deletion was not confirmed; nothing was deleted.".to_string(),
+            )]))),
+            Confirmation::TokenRequired(message) => Ok(Some(CallToolResult::error(vec![Content::text(message)]))),
+        }
+    }
+
+    // Select the memories to consolidate: by query if given, else by tags, else by time window.
+    // The remaining criteria narrow the selection down further.
+    async fn select_consolidation_sources(&self, storage: &dyn MemoryStorage, request: &ConsolidateMemoriesRequest, roots: &[String]) -> Result<Vec<models::Memory>, McpError> {
+        let max_memories = request.max_memories.unwrap_or(consolidation::DEFAULT_MAX_MEMORIES);
+        let start = request.start_timestamp.unwrap_or(i64::MIN);
+        let end = request.end_timestamp.unwrap_or(i64::MAX);
+        let tags = request.tags.clone().unwrap_or_default();
+
+        let candidates = if let Some(query) = &request.query {
+            let query_embedding = self.embedding_generator
+                .generate_embedding(query).await
+                .map_err(embedding_error_to_mcp)?;
+            storage.retrieve(&query_embedding, max_memories).await
+                .map_err(storage_error_to_mcp)?
+                .into_iter()
+                .map(|result| result.memory)
+                .collect()
+        } else if !tags.is_empty() {
+            storage.search_by_tag(&tags).await.map_err(storage_error_to_mcp)?
+        } else if request.start_timestamp.is_some() || request.end_timestamp.is_some() {
+            storage.search_by_time_range(start, end).await.map_err(storage_error_to_mcp)?
+        } else {
+            return Err(McpError::invalid_params(
+                "Provide tags, a time window or a query to select memories to consolidate".to_string(),
+                None,
+            ));
+        };
+
+        let mut sources: Vec<models::Memory> = candidates
+            .into_iter()
+            .filter(|memory| roots::is_visible(memory, roots))
+            .filter(|memory| !memory.tags.iter().any(|tag| tag == consolidation::ARCHIVED_TAG))
+            .filter(|memory| memory.timestamp_seconds >= start && memory.timestamp_seconds <= end)
+            .filter(|memory| tags.is_empty() || memory.tags.iter().any(|tag| tags.contains(tag)))
+            .collect();
+
+        sources.sort_by_key(|memory| memory.timestamp_seconds);
+        sources.truncate(max_memories);
+        Ok(sources)
     }
 
-    #[tool(description = "Store a new memory")]
+    #[tool(
+        description = "Store a new memory",
+        // Storing identical content again is detected by its hash and has no effect
+        annotations(title = "Store Memory", read_only_hint = false, destructive_hint = false, idempotent_hint = true, open_world_hint = false)
+    )]
     async fn store_memory(
         &self,
-        #[tool(aggr)] request: StoreMemoryRequest,
+        peer: Peer<RoleServer>,
+        Parameters(request): Parameters<StoreMemoryRequest>,
     ) -> Result<CallToolResult, McpError> {
-        let metadata = request.metadata.unwrap_or_default();
+        let mut metadata = request.metadata.unwrap_or_default();
+        // Stamp the memory with the client's active root, unless the caller set one explicitly
+        if let Some(root) = self.session_roots(&peer).await.into_iter().next() {
+            metadata.entry(roots::ROOT_METADATA_KEY.to_string()).or_insert(root);
+        }
         let content = request.content.clone();
         let content_hash = utils::generate_content_hash(&content, &metadata).map_err(to_mcp_error)?;
 
@@ -75,26 +243,54 @@ impl MemoryServer {
             embedding: None,
         };
 
-        let (success, message) = self.storage.store(&memory).await.map_err(to_mcp_error)?;
+        let storage = self.storage(request.namespace.as_deref()).await?;
+        let outcome = storage.store(&memory).await.map_err(storage_error_to_mcp)?;
 
-        if success {
-            Ok(CallToolResult::success(vec![Content::text(message)]))
+        let (message, name) = match outcome {
+            StoreOutcome::Stored => (format!("Successfully stored memory with hash: {}", memory.content_hash), "stored"),
+            StoreOutcome::Queued => (
+                format!("Storage is unreachable; memory with hash {} was queued and will be stored once it is back", memory.content_hash),
+                "queued",
+            ),
+            StoreOutcome::Duplicate => ("Duplicate content detected".to_string(), "duplicate"),
+        };
+        let result = if outcome.is_stored() {
+            CallToolResult::success(vec![Content::text(message)])
         } else {
-            Ok(CallToolResult::error(vec![Content::text(message)]))
-        }
+            CallToolResult::error(vec![Content::text(message)])
+        };
+        // The outcome is repeated as structured content for clients to act on
+        Ok(CallToolResult {
+            structured_content: Some(serde_json::json!({ "outcome": name, "content_hash": memory.content_hash })),
+            ..result
+        })
     }
 
-    #[tool(description = "Retrieve memories semantically similar to the query")]
+    #[tool(
+        description = "Retrieve memories semantically similar to the query",
+        annotations(title = "Retrieve Memories", read_only_hint = true, open_world_hint = false)
+    )]
     async fn retrieve_memory(
         &self,
-        #[tool(aggr)] request: RetrieveMemoryRequest,
+        peer: Peer<RoleServer>,
+        Parameters(request): Parameters<RetrieveMemoryRequest>,
     ) -> Result<CallToolResult, McpError> {
         let query_embedding = self.embedding_generator
             .generate_embedding(&request.query).await
             .map_err(embedding_error_to_mcp)?;
 
-        let results = self.storage.retrieve(&query_embedding, request.n_results.unwrap_or(5)).await
-            .map_err(to_mcp_error)?;
+        let storage = self.storage(request.namespace.as_deref()).await?;
+        let n_results = request.n_results.unwrap_or(5);
+        let results = if request.all_roots.unwrap_or(false) {
+            storage.retrieve(&query_embedding, n_results).await.map_err(storage_error_to_mcp)?
+        } else {
+            let roots = self.session_roots(&peer).await;
+            let fetch = if roots.is_empty() { n_results } else { n_results * roots::ROOT_OVERFETCH_FACTOR };
+            let mut results = storage.retrieve(&query_embedding, fetch).await.map_err(storage_error_to_mcp)?;
+            results.retain(|result| roots::is_visible(&result.memory, &roots));
+            results.truncate(n_results);
+            results
+        };
 
         if results.is_empty() {
             Ok(CallToolResult::success(vec![Content::text(
@@ -125,17 +321,26 @@ impl MemoryServer {
         }
     }
 
-    #[tool(description = "Search memories by tags")]
+    #[tool(
+        description = "Search memories by tags",
+        annotations(title = "Search Memories by Tag", read_only_hint = true, open_world_hint = false)
+    )]
     async fn search_by_tag(
         &self,
-        #[tool(aggr)] request: SearchByTagRequest,
+        peer: Peer<RoleServer>,
+        Parameters(request): Parameters<SearchByTagRequest>,
     ) -> Result<CallToolResult, McpError> {
          if request.tags.is_empty() {
              let error_message = "No tags provided for search.".to_string();
              return Ok(CallToolResult::error(vec![Content::text(error_message)]));
          }
 
-        let memories = self.storage.search_by_tag(&request.tags).await.map_err(to_mcp_error)?;
+        let storage = self.storage(request.namespace.as_deref()).await?;
+        let mut memories = storage.search_by_tag(&request.tags).await.map_err(storage_error_to_mcp)?;
+        if !request.all_roots.unwrap_or(false) {
+            let roots = self.session_roots(&peer).await;
+            memories.retain(|memory| roots::is_visible(memory, &roots));
+        }
 
         if memories.is_empty() {
             Ok(CallToolResult::success(vec![Content::text(
@@ -166,61 +371,356 @@ impl MemoryServer {
         }
     }
 
-    #[tool(description = "Delete a memory by its hash")]
+    #[tool(
+        description = "Delete a memory by its hash",
+        annotations(title = "Delete Memory", read_only_hint = false, destructive_hint = true, idempotent_hint = true, open_world_hint = false)
+    )]
     async fn delete_memory(
         &self,
-        #[tool(aggr)] request: DeleteMemoryRequest,
+        peer: Peer<RoleServer>,
+        Parameters(request): Parameters<DeleteMemoryRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let storage = self.storage(request.namespace.as_deref()).await?;
+        let Some(memory) = storage.get(&request.content_hash).await.map_err(storage_error_to_mcp)? else {
+            let error_message = format!("No memory found with hash: {}", request.content_hash);
+            return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+        };
+        if let Some(result) = self.confirm_deletion(&peer, &[memory], request.confirmation_token.as_deref()).await? {
+            return Ok(result);
+        }
+
+        match storage.delete(&request.content_hash).await {
+            Ok(DeleteOutcome::Deleted) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Successfully deleted memory with hash: {}",
+                request.content_hash
+            ))])),
+            Ok(DeleteOutcome::Queued) => Ok(CallToolResult::success(vec![Content::text(format!(
+                "Storage is unreachable; deletion of memory with hash {} was queued and will happen once it is back",
+                request.content_hash
+            ))])),
+            // Deleted by someone else since it was looked up
+            Err(e @ StorageError::NotFound(_)) => Ok(CallToolResult::error(vec![Content::text(e.to_string())])),
+            Err(e) => Err(storage_error_to_mcp(e)),
+        }
+    }
+
+    #[tool(
+        description = "Delete all memories with any of the given tags",
+        annotations(title = "Delete Memories by Tag", read_only_hint = false, destructive_hint = true, idempotent_hint = true, open_world_hint = false)
+    )]
+    async fn delete_by_tag(
+        &self,
+        peer: Peer<RoleServer>,
+        Parameters(request): Parameters<DeleteByTagRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if request.tags.is_empty() {
+            let error_message = "No tags provided for deletion.".to_string();
+            return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+        }
+
+        let storage = self.storage(request.namespace.as_deref()).await?;
+        let mut memories = storage.search_by_tag(&request.tags).await.map_err(storage_error_to_mcp)?;
+        if !request.all_roots.unwrap_or(false) {
+            let roots = self.session_roots(&peer).await;
+            memories.retain(|memory| roots::is_visible(memory, &roots));
+        }
+        if memories.is_empty() {
+            return Ok(CallToolResult::success(vec![Content::text(
+                "No memories found with the specified tags".to_string(),
+            )]));
+        }
+
+        if memories.len() > self.config.bulk_delete_threshold
+            && let Some(result) = self.confirm_deletion(&peer, &memories, request.confirmation_token.as_deref()).await?
+        {
+            return Ok(result);
+        }
+
+        let mut deleted = 0;
+        for memory in &memories {
+            match storage.delete(&memory.content_hash).await {
+                Ok(_) => deleted += 1,
+                Err(StorageError::NotFound(_)) => tracing::debug!("Memory {} was already deleted", memory.content_hash),
+                Err(e) => return Err(storage_error_to_mcp(e)),
+            }
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Deleted {} memories with tags {:?}",
+            deleted,
+            request.tags
+        ))]))
+    }
+
+    #[tool(
+        description = "Consolidate related memories, selected by tags, time window or query, into a single summary written by the client's LLM",
+        // Archiving rewrites the original memories
+        annotations(title = "Consolidate Memories", read_only_hint = false, destructive_hint = true, idempotent_hint = false, open_world_hint = false)
+    )]
+    async fn consolidate_memories(
+        &self,
+        peer: Peer<RoleServer>,
+        Parameters(request): Parameters<ConsolidateMemoriesRequest>,
     ) -> Result<CallToolResult, McpError> {
-        let (success, message) = self.storage.delete(&request.content_hash).await.map_err(to_mcp_error)?;
+        if peer.peer_info().is_none_or(|info| info.capabilities.sampling.is_none()) {
+            let error_message = "The connected client does not support sampling, which is required to consolidate memories.".to_string();
+            return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+        }
+
+        let storage = self.storage(request.namespace.as_deref()).await?;
+        let roots = self.session_roots(&peer).await;
+        let sources = self.select_consolidation_sources(storage.as_ref(), &request, &roots).await?;
+        if sources.len() < consolidation::MIN_MEMORIES {
+            let error_message = format!("Found {} matching memories; at least {} are needed to consolidate.", sources.len(), consolidation::MIN_MEMORIES);
+            return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+        }
+
+        // Archiving deletes the sources, so it needs the same confirmation as any deletion, asked before sampling
+        if request.archive_originals.unwrap_or(false)
+            && let Some(result) = self.confirm_deletion(&peer, &sources, request.confirmation_token.as_deref()).await?
+        {
+            return Ok(result);
+        }
+
+        let result = peer
+            .create_message(CreateMessageRequestParam {
+                messages: vec![SamplingMessage {
+                    role: Role::User,
+                    content: Content::text(consolidation::build_prompt(&sources)),
+                }],
+                model_preferences: None,
+                system_prompt: Some(consolidation::SYSTEM_PROMPT.to_string()),
+                include_context: None,
+                temperature: None,
+                max_tokens: request.max_tokens.unwrap_or(consolidation::DEFAULT_MAX_TOKENS),
+                stop_sequences: None,
+                metadata: None,
+            })
+            .await
+            .map_err(|e| McpError::internal_error(format!("Sampling request failed: {}", e), None))?;
+
+        let summary = result.message.content.as_text().map(|text| text.text.trim().to_string()).unwrap_or_default();
+        if summary.is_empty() {
+            let error_message = "The client returned an empty summary; nothing was stored.".to_string();
+            return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+        }
+
+        let summary_memory = consolidation::summary_memory(summary, &sources, &result.model, roots.first()).map_err(to_mcp_error)?;
+        if !storage.store(&summary_memory).await.map_err(storage_error_to_mcp)?.is_stored() {
+            let error_message = format!("A memory with the same summary already exists with hash: {}", summary_memory.content_hash);
+            return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+        }
+
+        let mut archived = 0;
+        let mut failures = Vec::new();
+        if request.archive_originals.unwrap_or(false) {
+            for source in &sources {
+                match consolidation::archive(storage.as_ref(), source, &summary_memory.content_hash).await {
+                    Ok(()) => archived += 1,
+                    Err(e) => {
+                        tracing::warn!("Failed to archive memory {}: {:#}", source.content_hash, e);
+                        failures.push(format!("{}: {:#}", source.content_hash, e));
+                    }
+                }
+            }
+        }
+
+        let mut message = format!(
+            "Consolidated {} memories into memory with hash: {} (archived {} originals)",
+            sources.len(),
+            summary_memory.content_hash,
+            archived
+        );
+        if !failures.is_empty() {
+            message.push_str(&format!("\nThese originals were kept unarchived:\n{}", failures.join("\n")));
+        }
+        Ok(CallToolResult::success(vec![Content::text(format!("{}\nSummary:\n{}", message, summary_memory.content))]))
+    }
+
+    #[tool(
+        description = "Copy every memory to one of the destination storages configured on the server, keeping hashes, timestamps, tags, metadata and embeddings. An interrupted migration to the same destination resumes where it stopped; counts are verified at the end",
+        annotations(title = "Migrate Memories", read_only_hint = false, destructive_hint = false, idempotent_hint = true, open_world_hint = true)
+    )]
+    async fn migrate_memories(
+        &self,
+        Parameters(request): Parameters<MigrateMemoriesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        // Destinations are only ever described by the server's own settings, so callers can't
+        // point the server at hosts or paths of their choosing
+        let Some(destination_file) = self.config.migration_destinations.get(&request.destination) else {
+            let configured: Vec<&String> = self.config.migration_destinations.keys().collect();
+            let error_message = format!("Unknown migration destination '{}'; configured destinations: {:?}", request.destination, configured);
+            return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+        };
+        let destination_config = match read_settings(destination_file) {
+            Ok(config) => config,
+            Err(e) => {
+                let error_message = format!("Invalid destination settings: {:#}", e);
+                return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+            }
+        };
+
+        // Memories go to the same namespace of the destination
+        let namespace = request.namespace.clone().unwrap_or_else(|| self.config.namespace.clone());
+        let storage = self.storage(Some(&namespace)).await?;
+        let (source_config, destination_config) = (self.config.for_namespace(&namespace), destination_config.for_namespace(&namespace));
+        let restart = request.restart.unwrap_or(false);
+        match migrate_to(storage.as_ref(), &source_config, &destination_config, self.embedding_generator.clone(), restart).await {
+            Ok(report) if report.is_verified() => Ok(CallToolResult::success(vec![Content::text(report.to_string())])),
+            Ok(report) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "{}\nCall migrate_memories again to copy the missing memories.",
+                report
+            ))])),
+            Err(e) => Ok(CallToolResult::error(vec![Content::text(format!("Migration failed: {:#}", e))])),
+        }
+    }
+
+    #[tool(
+        description = "List the namespaces memories can be stored in",
+        annotations(title = "List Namespaces", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn list_namespaces(&self) -> Result<CallToolResult, McpError> {
+        let namespaces = self.namespaces.list().await;
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "{} namespaces (tools use '{}' unless given another):\n{}",
+            namespaces.len(),
+            self.config.namespace,
+            namespaces.iter().map(|name| format!("- {}", name)).collect::<Vec<_>>().join("\n")
+        ))]))
+    }
 
-        if success {
-             Ok(CallToolResult::success(vec![Content::text(message)]))
+    #[tool(
+        description = "Create a namespace, a separate set of memories that tools use when passed its name as 'namespace'",
+        annotations(title = "Create Namespace", read_only_hint = false, destructive_hint = false, idempotent_hint = true, open_world_hint = false)
+    )]
+    async fn create_namespace(
+        &self,
+        Parameters(request): Parameters<CreateNamespaceRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if let Err(e) = storage::validate_namespace(&request.namespace) {
+            return Ok(CallToolResult::error(vec![Content::text(e.to_string())]));
+        }
+        if self.namespaces.create(&request.namespace).await.map_err(to_mcp_error)? {
+            Ok(CallToolResult::success(vec![Content::text(format!("Created namespace '{}'", request.namespace))]))
         } else {
-             Ok(CallToolResult::error(vec![Content::text(message)]))
+            Ok(CallToolResult::success(vec![Content::text(format!("Namespace '{}' already exists", request.namespace))]))
         }
     }
+
+    #[tool(
+        description = "Drop a namespace, deleting every memory in it",
+        annotations(title = "Drop Namespace", read_only_hint = false, destructive_hint = true, idempotent_hint = true, open_world_hint = false)
+    )]
+    async fn drop_namespace(
+        &self,
+        peer: Peer<RoleServer>,
+        Parameters(request): Parameters<DropNamespaceRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        if request.namespace == DEFAULT_NAMESPACE || request.namespace == self.config.namespace {
+            let error_message = format!("'{}' is the server's default namespace and can't be dropped.", request.namespace);
+            return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+        }
+        let Some(memories) = self.namespaces.memories(&request.namespace).await.map_err(to_mcp_error)? else {
+            let error_message = format!("No namespace named '{}'", request.namespace);
+            return Ok(CallToolResult::error(vec![Content::text(error_message)]));
+        };
+        if !memories.is_empty()
+            && let Some(result) = self.confirm_deletion(&peer, &memories, request.confirmation_token.as_deref()).await?
+        {
+            return Ok(result);
+        }
+
+        let deleted = self.namespaces.remove(&request.namespace).await.map_err(to_mcp_error)?.unwrap_or_default();
+        Ok(CallToolResult::success(vec![Content::text(format!(
+            "Dropped namespace '{}' and deleted its {} memories",
+            request.namespace, deleted
+        ))]))
+    }
 }
 
-#[tool(tool_box)]
 impl ServerHandler for MemoryServer {
     fn get_info(&self) -> ServerInfo {
         let embedding_model_name = self.embedding_generator.name();
         let embedding_size = self.embedding_generator.get_embedding_size();
 
-        let base_instructions = "This server provides memory storage and retrieval functionality. Use 'store_memory' to store new memories, 'retrieve_memory' for semantic search, 'search_by_tag' to find memories by tags, and 'delete_memory' to remove memories.";
-        let instructions = format!("{} Currently using {} embedding model (size {}).", base_instructions, embedding_model_name, embedding_size);
+        let base_instructions = "This server provides memory storage and retrieval functionality. Use 'store_memory' to store new memories, 'retrieve_memory' for semantic search, 'search_by_tag' to find memories by tags, 'delete_memory' and 'delete_by_tag' to remove memories (after asking the user to confirm), and 'consolidate_memories' to merge related memories into a summary. Memories are kept in separate namespaces: every tool takes an optional 'namespace', and 'list_namespaces', 'create_namespace' and 'drop_namespace' manage them.";
+        let read_only_instructions = "This server provides read-only access to a curated memory store: use 'retrieve_memory' for semantic search and 'search_by_tag' to find memories by tags. Memories can't be stored, changed or deleted through this server. Memories are kept in separate namespaces: every tool takes an optional 'namespace', and 'list_namespaces' lists them.";
+        let instructions = format!(
+            "{} Tools use the '{}' namespace unless given another. Currently using {} embedding model (size {}).",
+            if self.config.read_only { read_only_instructions } else { base_instructions },
+            self.config.namespace,
+            embedding_model_name,
+            embedding_size
+        );
 
         ServerInfo {
-            protocol_version: ProtocolVersion::V_2024_11_05,
+            // Newest revision we support; rmcp falls back to the client's version if that is older
+            protocol_version: ProtocolVersion::V_2025_06_18,
             capabilities: ServerCapabilities::builder()
+                .enable_logging()
                 .enable_tools()
                 .build(),
             server_info: Implementation {
                 name: "mcp-memory-service-rs".to_string(),
                 version: "0.1.2".to_string(),
+                ..Default::default()
             },
             instructions: Some(instructions),
         }
     }
-}
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize logging
-    let config_for_log = Config::load().unwrap_or_default();
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config_for_log.log_level)))
-        // *** FIX E0277/E0599: Wrap stderr() in a closure ***
-        .with_writer(|| std::io::stderr()) // Closure satisfies MakeWriter trait bound
-        .with_ansi(false)
-        .init();
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        auth::authorize_tool(&request.name, &context)?;
+        if self.config.read_only && !self.tool_router.has_route(&request.name) && Self::tool_router().has_route(&request.name) {
+            return Err(McpError::invalid_request(format!("The server is read-only; '{}' is not available", request.name), None));
+        }
+        let client_logger = self.client_logger.lock().unwrap().clone();
+        logging::in_session(client_logger, self.tool_router.call(ToolCallContext::new(self, request, context))).await
+    }
 
-    // Load configuration
-    let config = Config::load()?;
-    tracing::info!("Configuration loaded: {:?}", config);
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(self.tool_router.list_all()))
+    }
 
-    // Initialize embedding generator based on configuration
-    let embedding_generator: Arc<dyn EmbeddingGenerator> = match config.embedding_model {
+    // rmcp handles this notification before it starts serving the session, so
+    // no request can be made to the client from here: it would never be sent.
+    // Roots are fetched by the first tool call needing them instead.
+    async fn on_initialized(&self, context: NotificationContext<RoleServer>) {
+        let logger = self.log_forwarder.attach(context.peer);
+        *self.client_logger.lock().unwrap() = Some(logger);
+    }
+
+    async fn on_roots_list_changed(&self, context: NotificationContext<RoleServer>) {
+        let roots = roots::fetch_roots(&context.peer).await;
+        *self.roots.lock().unwrap() = roots;
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        match self.client_logger.lock().unwrap().as_ref() {
+            Some(logger) => {
+                logger.set_level(request.level);
+                Ok(())
+            }
+            None => Err(McpError::internal_error("Client logging is not initialized".to_string(), None)),
+        }
+    }
+}
+
+// Initialize embedding generator based on configuration
+fn build_embedding_generator(config: &Config) -> Arc<dyn EmbeddingGenerator> {
+    match config.embedding_model {
         config::EmbeddingModel::Dummy => {
             tracing::info!("Using dummy embedding generator with size {}", config.embedding_size);
             Arc::new(DummyEmbeddingGenerator::new(config.embedding_size))
@@ -245,53 +745,453 @@ async fn main() -> Result<()> {
                 Arc::new(DummyEmbeddingGenerator::new(config.embedding_size))
             }
         }
-    };
+    }
+}
 
-    // Initialize storage based on configuration
+/// Open the configured storage backend. A local Chroma server started for it
+/// is returned as well and must be shut down once the storage is dropped.
+async fn open_storage(config: &Config, embedding_generator: Arc<dyn EmbeddingGenerator>) -> Result<(Arc<dyn MemoryStorage>, Option<ChromaProcess>)> {
     let storage: Arc<dyn MemoryStorage> = match config.storage_backend {
         config::StorageBackend::InMemory => {
-            tracing::info!("Using in-memory storage");
-            Arc::new(InMemoryStorage::new(embedding_generator.clone()))
+            let wal_config = config.wal_path.clone().map(|dir| WalConfig {
+                dir,
+                snapshot_interval: config.snapshot_interval,
+            });
+            match &wal_config {
+                Some(wal) => tracing::info!("Using in-memory storage with write-ahead log in {:?}", wal.dir),
+                None => tracing::info!("Using in-memory storage"),
+            }
+            Arc::new(InMemoryStorage::new(embedding_generator, wal_config, config.hnsw.clone(), config.quantization.clone(), config.read_only)?)
+        },
+        config::StorageBackend::Sqlite => {
+            tracing::info!("Using SQLite storage at {:?}", config.sqlite_path);
+            Arc::new(SqliteMemoryStorage::new(config.sqlite_path.clone(), embedding_generator, config.quantization.clone(), config.read_only)?)
+        },
+        config::StorageBackend::Qdrant => {
+            let url = match config.qdrant_url.clone() {
+                Some(url) => url,
+                None => url::Url::parse("http://localhost:6333").context("Failed to parse default Qdrant URL")?,
+            };
+            tracing::info!("Using Qdrant storage at {}", url);
+            let storage = QdrantMemoryStorage::new(
+                url,
+                config.qdrant_collection_name.clone(),
+                config.qdrant_api_key.as_ref().map(|key| key.expose().to_string()),
+                embedding_generator,
+            ).await.context("Failed to initialize Qdrant storage")?;
+            Arc::new(storage)
         },
         config::StorageBackend::ChromaDB => {
             tracing::info!("Using ChromaDB storage");
-            let storage_result = if let Some(url) = config.chroma_db_url.clone() {
+            let mut chroma_process = None;
+            let url = if let Some(url) = config.chroma_db_url.clone() {
                 tracing::info!("Connecting to remote ChromaDB at {}", url);
-                ChromaMemoryStorage::new(
-                    url,
-                    config.chroma_collection_name.clone(),
-                    embedding_generator.clone(),
-                ).await
+                url
+            } else if config.chroma_managed {
+                tracing::info!("Starting local ChromaDB with data in {:?}", config.chroma_db_path);
+                let process = ChromaProcess::start(&config.chroma_command, &config.chroma_db_path)
+                    .await
+                    .context("Failed to initialize ChromaDB storage")?;
+                let url = process.url().clone();
+                chroma_process = Some(process);
+                url
             } else {
                 tracing::info!("Using local ChromaDB (expecting server at http://localhost:8000 from path {:?})", config.chroma_db_path);
-                 let default_chroma_url = url::Url::parse("http://localhost:8000")
-                     .context("Failed to parse default ChromaDB URL")?;
-                 ChromaMemoryStorage::new(
-                     default_chroma_url,
-                     config.chroma_collection_name.clone(),
-                     embedding_generator.clone(),
-                 ).await
+                url::Url::parse("http://localhost:8000")
+                    .context("Failed to parse default ChromaDB URL")?
             };
 
-            match storage_result {
-                 Ok(storage) => Arc::new(storage),
-                 Err(e) => {
-                     tracing::error!("Failed to initialize ChromaDB storage: {}", e);
-                     tracing::warn!("Falling back to in-memory storage.");
-                     Arc::new(InMemoryStorage::new(embedding_generator.clone()))
-                 }
-             }
+            let options = ChromaOptions {
+                base_url: url,
+                collection_name: config.chroma_collection_name.clone(),
+                tenant: config.chroma_tenant.clone(),
+                database: config.chroma_database.clone(),
+                auth: config.chroma_auth.clone(),
+                tls: config.chroma_tls.clone(),
+                timeout: config.chroma_timeout,
+                retry: config.chroma_retry.clone(),
+                // Nothing is written, so there is nothing to queue
+                queue_dir: config.chroma_queue_dir.clone().filter(|_| !config.read_only),
+            };
+            return match ChromaMemoryStorage::new(options, embedding_generator).await {
+                Ok(storage) => Ok((Arc::new(storage), chroma_process)),
+                Err(e) => {
+                    if let Some(process) = chroma_process {
+                        process.shutdown().await;
+                    }
+                    Err(e.context("Failed to initialize ChromaDB storage"))
+                }
+            };
         }
     };
+    Ok((storage, None))
+}
 
-    // Create and run server
-    let service = MemoryServer::new(storage, embedding_generator).serve(stdio()).await?;
+/// Copy every memory from `source`, the storage described by `source_config`,
+/// to the storage described by `destination_config`
+async fn migrate_to(
+    source: &dyn MemoryStorage,
+    source_config: &Config,
+    destination_config: &Config,
+    embedding_generator: Arc<dyn EmbeddingGenerator>,
+    restart: bool,
+) -> Result<MigrationReport> {
+    let source_description = source_config.storage_description();
+    let destination_description = destination_config.storage_description();
+    if source_description == destination_description {
+        anyhow::bail!("Source and destination are the same storage: {}", source_description);
+    }
 
-    tracing::info!("MCP Memory Service running on stdio. Waiting for requests...");
+    // The destination is written to even if the server itself is read-only
+    let destination_config = Config { read_only: false, ..destination_config.clone() };
+    let (destination, destination_process) = open_storage(&destination_config, embedding_generator)
+        .await
+        .context("Failed to open destination storage")?;
+    let checkpoint_path = migrate::checkpoint_path(&source_config.migration_checkpoint_dir, &source_description, &destination_description);
+    let result = migrate::migrate(source, destination.as_ref(), &source_description, &destination_description, &checkpoint_path, restart).await;
 
-    service.waiting().await?;
+    if let Some(process) = destination_process {
+        process.shutdown().await;
+    }
+    result
+}
 
-    tracing::info!("MCP Memory Service shutting down.");
+/// Configuration described by the `MCP_MEMORY_*` settings in an env file,
+/// with settings it lacks taken from the environment
+fn read_settings(path: &Path) -> Result<Config> {
+    let overrides = dotenvy::from_path_iter(path)
+        .and_then(|vars| vars.collect::<Result<HashMap<String, String>, _>>())
+        .context(format!("Failed to read storage settings from {:?}", path))?;
+    Config::with_overrides(&overrides)
+}
 
+/// Open a namespace of the configured storage as the primary of a
+/// `ReplicatedStorage`, with the same namespace of each of `config.replicas`
+/// as a secondary. Replicas that can't be opened
+/// yet are opened on a later use; Chroma servers started for any replica are
+/// added to `processes`.
+async fn open_replicated(
+    config: &Config,
+    namespace: &str,
+    embedding_generator: Arc<dyn EmbeddingGenerator>,
+    processes: Arc<Mutex<Vec<ChromaProcess>>>,
+) -> Result<Arc<dyn MemoryStorage>> {
+    let mut configs = vec![config.for_namespace(namespace)];
+    for path in &config.replicas {
+        configs.push(read_settings(path)?.for_namespace(namespace));
+    }
+
+    let mut replicas = Vec::with_capacity(configs.len());
+    for replica_config in configs {
+        let name = replica_config.storage_description();
+        let storage: Arc<dyn MemoryStorage> = match open_storage(&replica_config, embedding_generator.clone()).await {
+            Ok((storage, process)) => {
+                processes.lock().unwrap_or_else(|e| e.into_inner()).extend(process);
+                storage
+            }
+            Err(e) => {
+                tracing::warn!("Storage replica {} is unavailable, opening it later: {:#}", name, e);
+                let (replica_config, embedding_generator, processes) = (Arc::new(replica_config), embedding_generator.clone(), processes.clone());
+                let opener: StorageOpener = Box::new(move || {
+                    let (replica_config, embedding_generator, processes) = (replica_config.clone(), embedding_generator.clone(), processes.clone());
+                    Box::pin(async move {
+                        let (storage, process) = open_storage(&replica_config, embedding_generator).await?;
+                        processes.lock().unwrap_or_else(|e| e.into_inner()).extend(process);
+                        Ok(storage)
+                    })
+                });
+                Arc::new(DeferredStorage::new(opener))
+            }
+        };
+        tracing::info!("Replicating memories to {}", name);
+        replicas.push((name, storage));
+    }
+    Ok(Arc::new(ReplicatedStorage::new(replicas, config.replica_retry_interval)))
+}
+
+/// Open the storage holding a namespace other than the default one, with the
+/// same replicas and cache as the default namespace
+async fn open_namespace(
+    config: &Config,
+    namespace: &str,
+    embedding_generator: Arc<dyn EmbeddingGenerator>,
+    processes: Arc<Mutex<Vec<ChromaProcess>>>,
+) -> Result<Arc<dyn MemoryStorage>> {
+    let storage = if config.replicas.is_empty() {
+        let (storage, process) = open_storage(&config.for_namespace(namespace), embedding_generator).await?;
+        processes.lock().unwrap_or_else(|e| e.into_inner()).extend(process);
+        storage
+    } else {
+        open_replicated(config, namespace, embedding_generator, processes).await?
+    };
+    Ok(match config.cache.clone() {
+        Some(cache_config) => Arc::new(CachedStorage::new(storage, cache_config)),
+        None => storage,
+    })
+}
+
+/// `migrate <destination.env> [--restart]`: copy every memory from the
+/// configured storage to the one described by the settings in the file
+async fn run_migration(config: &Config, args: &[String]) -> Result<()> {
+    let restart = args.iter().any(|arg| arg == "--restart");
+    let Some(destination_file) = args.iter().find(|arg| !arg.starts_with("--")) else {
+        anyhow::bail!("Usage: mcp-rust-server migrate <destination.env> [--restart]");
+    };
+    let destination_config = read_settings(Path::new(destination_file))?;
+
+    let embedding_generator = build_embedding_generator(config);
+    let (source, source_process) = open_storage(config, embedding_generator.clone())
+        .await
+        .context("Failed to open source storage")?;
+    let result = migrate_to(source.as_ref(), config, &destination_config, embedding_generator, restart).await;
+    if let Some(process) = source_process {
+        process.shutdown().await;
+    }
+
+    let report = result?;
+    println!("{}", report);
+    if !report.is_verified() {
+        anyhow::bail!("Migration could not be verified; run it again to copy the missing memories");
+    }
+    Ok(())
+}
+
+/// `quantization-report [--queries <n>]`: measure the memory saved and the
+/// recall lost by the configured storage's similarity search
+async fn run_quantization_report(config: &Config, args: &[String]) -> Result<()> {
+    let queries = match args.iter().position(|arg| arg == "--queries") {
+        Some(index) => args
+            .get(index + 1)
+            .and_then(|queries| queries.parse().ok())
+            .context("Usage: mcp-rust-server quantization-report [--queries <n>]")?,
+        None => report::DEFAULT_QUERIES,
+    };
+
+    let embedding_generator = build_embedding_generator(config);
+    let (storage, chroma_process) = open_storage(config, embedding_generator.clone()).await?;
+    // Only the in-process backends quantise
+    let quantization = config.quantization.as_ref().map(|quantization| quantization.quantization);
+    let index_threshold = config.hnsw.as_ref().map(|hnsw| hnsw.exact_threshold);
+    let (quantization, residence) = match config.storage_backend {
+        config::StorageBackend::InMemory => (quantization, report::EmbeddingResidence::Memory { index_threshold }),
+        config::StorageBackend::Sqlite => (quantization, report::EmbeddingResidence::Disk),
+        config::StorageBackend::ChromaDB | config::StorageBackend::Qdrant => (None, report::EmbeddingResidence::Remote),
+    };
+    let result = report::measure(storage.as_ref(), embedding_generator.as_ref(), quantization, residence, queries).await;
+    if let Some(process) = chroma_process {
+        process.shutdown().await;
+    }
+
+    println!("{}", result?);
     Ok(())
 }
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Initialize logging
+    let config_for_log = Config::load().unwrap_or_default();
+    let log_forwarder = LogForwarder::new();
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                // *** FIX E0277/E0599: Wrap stderr() in a closure ***
+                .with_writer(|| std::io::stderr()) // Closure satisfies MakeWriter trait bound
+                .with_ansi(false)
+                .with_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config_for_log.log_level))),
+        )
+        // Forward events to the connected client as MCP log notifications, filtered by the client's own level
+        .with(log_forwarder.layer())
+        .init();
+
+    // Load configuration
+    let mut config = Config::load()?;
+    tracing::info!("Configuration loaded: {:?}", config);
+
+    // `bridge` relays stdio to a daemon serving the Unix socket transport
+    if std::env::args().nth(1).as_deref() == Some("bridge") {
+        return transport::run_bridge(&config.socket_path).await;
+    }
+
+    // `migrate` copies every memory to another storage backend and exits
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("migrate") {
+        return run_migration(&config, &args[2..]).await;
+    }
+    // `quantization-report` measures what quantised search saves and loses, then exits
+    if args.get(1).map(String::as_str) == Some("quantization-report") {
+        return run_quantization_report(&config, &args[2..]).await;
+    }
+
+    let embedding_generator = build_embedding_generator(&config);
+
+    // Initialize storage based on configuration. Without replicas, the network backends fall back to in-memory storage
+    let chroma_processes = Arc::new(Mutex::new(Vec::new()));
+    let opened = if config.replicas.is_empty() {
+        open_storage(&config, embedding_generator.clone()).await
+    } else {
+        open_replicated(&config, DEFAULT_NAMESPACE, embedding_generator.clone(), chroma_processes.clone()).await.map(|storage| (storage, None))
+    };
+    let (mut storage, chroma_process) = match opened {
+        Ok(opened) => opened,
+        Err(e) if matches!(config.storage_backend, config::StorageBackend::Qdrant | config::StorageBackend::ChromaDB) => {
+            tracing::error!("{:#}", e);
+            tracing::warn!("Falling back to in-memory storage.");
+            // Describe the storage actually in use, so migrations start from the right place
+            config.storage_backend = config::StorageBackend::InMemory;
+            config.wal_path = None;
+            let storage: Arc<dyn MemoryStorage> = Arc::new(InMemoryStorage::new(embedding_generator.clone(), None, config.hnsw.clone(), config.quantization.clone(), config.read_only)?);
+            (storage, None)
+        }
+        Err(e) => return Err(e),
+    };
+    let cache = config.cache.clone().map(|cache_config| {
+        tracing::info!("Caching storage lookups for up to {} entries", cache_config.capacity);
+        let cache = Arc::new(CachedStorage::new(storage.clone(), cache_config));
+        storage = cache.clone();
+        cache
+    });
+
+    // Other namespaces are opened like the default one, reusing a Chroma server started for it
+    let namespace_config = {
+        let mut namespace_config = config.clone();
+        if let Some(process) = &chroma_process {
+            namespace_config.chroma_db_url = Some(process.url().clone());
+        }
+        Arc::new(namespace_config)
+    };
+    let (opener_generator, opener_processes) = (embedding_generator.clone(), chroma_processes.clone());
+    let opener: NamespaceOpener = Box::new(move |namespace| {
+        let (config, embedding_generator, processes) = (namespace_config.clone(), opener_generator.clone(), opener_processes.clone());
+        Box::pin(async move { open_namespace(&config, &namespace, embedding_generator, processes).await })
+    });
+    let registry = storage::registry_path(&config.namespace_dir, &config.storage_description());
+    let namespaces = Arc::new(Namespaces::new(storage, opener, Some(registry))?);
+    if config.read_only {
+        if namespaces.storage(&config.namespace).await?.is_none() {
+            anyhow::bail!("Namespace '{}' doesn't exist and can't be created on a read-only server", config.namespace);
+        }
+        tracing::info!("Serving memories read-only");
+    } else if namespaces.create(&config.namespace).await? {
+        tracing::info!("Created the default namespace '{}'", config.namespace);
+    }
+
+    // Create and run server
+    let config = Arc::new(config);
+    let server = MemoryServer::new(namespaces, embedding_generator, log_forwarder, config.clone());
+    let result = match config.transport {
+        config::Transport::Stdio => transport::serve_stdio(server).await,
+        config::Transport::Http => transport::serve_http(server, config.listen_addr, &config.api_keys).await,
+        config::Transport::Unix => transport::serve_unix(server, &config.socket_path).await,
+    };
+
+    tracing::info!("MCP Memory Service shutting down.");
+    if let Some(cache) = cache {
+        tracing::info!("Storage cache: {}", cache.stats());
+    }
+
+    let processes = std::mem::take(&mut *chroma_processes.lock().unwrap_or_else(|e| e.into_inner()));
+    for process in chroma_process.into_iter().chain(processes) {
+        process.shutdown().await;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rmcp::{
+        model::{ClientCapabilities, ClientInfo, ListRootsResult, RawContent, Root},
+        service::RunningService,
+        ClientHandler, RoleClient, ServiceExt,
+    };
+    use std::time::Duration;
+
+    const WORKSPACE: &str = "file:///workspace";
+
+    // Client with a workspace open, advertising it as its only root
+    struct RootsClient;
+
+    impl ClientHandler for RootsClient {
+        fn get_info(&self) -> ClientInfo {
+            ClientInfo {
+                capabilities: ClientCapabilities::builder().enable_roots().build(),
+                ..Default::default()
+            }
+        }
+
+        async fn list_roots(&self, _context: RequestContext<RoleClient>) -> Result<ListRootsResult, McpError> {
+            Ok(ListRootsResult {
+                roots: vec![Root { uri: WORKSPACE.to_string(), name: None }],
+            })
+        }
+    }
+
+    pub(crate) fn test_server() -> MemoryServer {
+        let embedding_generator: Arc<dyn EmbeddingGenerator> = Arc::new(DummyEmbeddingGenerator::new(8));
+        let storage: Arc<dyn MemoryStorage> = Arc::new(InMemoryStorage::new(embedding_generator.clone(), None, None, None, false).unwrap());
+        let opener: NamespaceOpener = Box::new(|namespace| Box::pin(async move { anyhow::bail!("No storage for namespace '{}'", namespace) }));
+        let namespaces = Arc::new(Namespaces::new(storage, opener, None).unwrap());
+        MemoryServer::new(namespaces, embedding_generator, LogForwarder::new(), Arc::new(Config::default()))
+    }
+
+    // Start a session of the server over an in-process pipe and connect the client to it
+    async fn connect<C: ClientHandler>(server: MemoryServer, client: C) -> RunningService<RoleClient, C> {
+        let (server_io, client_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            if let Ok(service) = server.new_session().serve(server_io).await {
+                let _ = service.waiting().await;
+            }
+        });
+        client.serve(client_io).await.unwrap()
+    }
+
+    async fn call(client: &RunningService<RoleClient, impl ClientHandler>, tool: &str, arguments: serde_json::Value) -> CallToolResult {
+        let request = CallToolRequestParam {
+            name: tool.to_string().into(),
+            arguments: arguments.as_object().cloned(),
+        };
+        tokio::time::timeout(Duration::from_secs(10), client.call_tool(request))
+            .await
+            .expect("tool call was never answered")
+            .unwrap()
+    }
+
+    fn text(result: &CallToolResult) -> String {
+        result
+            .content
+            .iter()
+            .filter_map(|content| match &content.raw {
+                RawContent::Text(text) => Some(text.text.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn clients_with_roots_are_served() {
+        let server = test_server();
+        let client = connect(server.clone(), RootsClient).await;
+
+        let tools = tokio::time::timeout(Duration::from_secs(10), client.list_all_tools())
+            .await
+            .expect("tools/list was never answered")
+            .unwrap();
+        assert!(tools.iter().any(|tool| tool.name == "store_memory"));
+
+        let stored = call(&client, "store_memory", serde_json::json!({ "content": "roots are fetched lazily" })).await;
+        assert_ne!(stored.is_error, Some(true), "{}", text(&stored));
+        let retrieved = call(&client, "retrieve_memory", serde_json::json!({ "query": "roots are fetched lazily" })).await;
+        assert!(text(&retrieved).contains("roots are fetched lazily"), "{}", text(&retrieved));
+
+        // The memory was scoped to the client's workspace
+        let storage = server.namespaces.storage(DEFAULT_NAMESPACE).await.unwrap().unwrap();
+        let memories = storage.search_by_time_range(i64::MIN, i64::MAX).await.unwrap();
+        assert_eq!(memories[0].metadata.get(roots::ROOT_METADATA_KEY).map(String::as_str), Some(WORKSPACE));
+
+        // Logging is attached once the client has initialized
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(client.peer().set_level(SetLevelRequestParam { level: rmcp::model::LoggingLevel::Debug }).await.is_ok());
+        client.cancel().await.unwrap();
+    }
+}
@@ -0,0 +1,306 @@
+use crate::storage::MemoryStorage;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Number of memories copied between two checkpoints
+pub const BATCH_SIZE: usize = 256;
+
+/// Progress of a migration, saved after every batch so an interrupted run can
+/// pick up where it stopped
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Checkpoint {
+    source: String,
+    destination: String,
+    // Source list cursor of the next batch to copy
+    cursor: Option<String>,
+    copied: usize,
+    skipped: usize,
+}
+
+impl Checkpoint {
+    fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(path).context(format!("Failed to read migration checkpoint {:?}", path))?;
+        Ok(Some(serde_json::from_str(&contents).context(format!("Corrupt migration checkpoint {:?}", path))?))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(format!("Failed to create directory: {:?}", parent))?;
+        }
+        // Replace the previous checkpoint atomically so a crash never leaves half of one behind
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, serde_json::to_vec(self)?).context(format!("Failed to write migration checkpoint {:?}", temp_path))?;
+        std::fs::rename(&temp_path, path).context(format!("Failed to move migration checkpoint to {:?}", path))?;
+        Ok(())
+    }
+}
+
+/// Outcome of a migration
+#[derive(Debug)]
+pub struct MigrationReport {
+    /// Whether the run continued an interrupted one
+    pub resumed: bool,
+    /// Memories written to the destination, including by interrupted runs
+    pub copied: usize,
+    /// Memories the destination already had
+    pub skipped: usize,
+    /// Memories in the source when the copy finished
+    pub source_count: usize,
+    /// Memories in the destination when the copy finished
+    pub destination_count: usize,
+    /// Source memories the destination still lacks
+    pub missing: usize,
+}
+
+impl MigrationReport {
+    /// Every source memory made it to the destination
+    pub fn is_verified(&self) -> bool {
+        self.missing == 0
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} memories ({} already present){}.\nVerification: source has {}, destination has {}, {} missing from destination.",
+            if self.is_verified() { "Migrated" } else { "Incompletely migrated" },
+            self.copied,
+            self.skipped,
+            if self.resumed { ", resuming an interrupted run" } else { "" },
+            self.source_count,
+            self.destination_count,
+            self.missing
+        )
+    }
+}
+
+/// Checkpoint file for migrating between the two described storages
+pub fn checkpoint_path(dir: &Path, source: &str, destination: &str) -> PathBuf {
+    let digest = Sha256::digest(format!("{}\n{}", source, destination).as_bytes());
+    dir.join(format!("{}.json", hex::encode(&digest[..8])))
+}
+
+/// Copy every memory from `source` to `destination`, keeping content hashes,
+/// timestamps, tags, metadata and embeddings, then check that the destination
+/// holds every source memory.
+///
+/// `source_description` and `destination_description` identify the two
+/// storages in the checkpoint, which is resumed unless `restart` is set and
+/// removed once the migration is verified.
+pub async fn migrate(
+    source: &dyn MemoryStorage,
+    destination: &dyn MemoryStorage,
+    source_description: &str,
+    destination_description: &str,
+    checkpoint_path: &Path,
+    restart: bool,
+) -> Result<MigrationReport> {
+    let previous = if restart { None } else { Checkpoint::load(checkpoint_path)? };
+    let resumed = previous.is_some();
+    let mut checkpoint = match previous {
+        Some(checkpoint) if checkpoint.source != source_description || checkpoint.destination != destination_description => {
+            anyhow::bail!("Migration checkpoint {:?} belongs to a different migration", checkpoint_path);
+        }
+        Some(checkpoint) => {
+            tracing::info!("Resuming migration after {} copied memories", checkpoint.copied);
+            checkpoint
+        }
+        None => Checkpoint {
+            source: source_description.to_string(),
+            destination: destination_description.to_string(),
+            ..Default::default()
+        },
+    };
+
+    tracing::info!("Migrating memories from {} to {}", source_description, destination_description);
+    loop {
+        let page = source.list(checkpoint.cursor.as_deref(), BATCH_SIZE).await.context("Failed to read from source")?;
+        for memory in &page.memories {
+            if destination.check_duplicate_exists(&memory.content_hash).await? {
+                checkpoint.skipped += 1;
+                continue;
+            }
+            if memory.embedding.is_none() {
+                tracing::warn!("Memory {} has no embedding; the destination will generate one", memory.content_hash);
+            }
+            if destination.store(memory).await.context("Failed to write to destination")?.is_stored() {
+                checkpoint.copied += 1;
+            } else {
+                tracing::warn!("Destination already has memory {}", memory.content_hash);
+                checkpoint.skipped += 1;
+            }
+        }
+
+        checkpoint.cursor = page.next_cursor;
+        checkpoint.save(checkpoint_path)?;
+        tracing::info!("Migration progress: {} copied, {} already present", checkpoint.copied, checkpoint.skipped);
+        if checkpoint.cursor.is_none() {
+            break;
+        }
+    }
+
+    // Verify against a fresh listing of both sides rather than the running counts
+    let mut source_count = 0;
+    let mut missing = 0;
+    let mut cursor = None;
+    loop {
+        let page = source.list(cursor.as_deref(), BATCH_SIZE).await?;
+        source_count += page.memories.len();
+        for memory in &page.memories {
+            if !destination.check_duplicate_exists(&memory.content_hash).await? {
+                tracing::warn!("Memory {} is missing from the destination", memory.content_hash);
+                missing += 1;
+            }
+        }
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    let destination_count = count(destination).await?;
+
+    let report = MigrationReport {
+        resumed,
+        copied: checkpoint.copied,
+        skipped: checkpoint.skipped,
+        source_count,
+        destination_count,
+        missing,
+    };
+    if report.is_verified() {
+        std::fs::remove_file(checkpoint_path).context(format!("Failed to remove migration checkpoint {:?}", checkpoint_path))?;
+    } else {
+        // The next run makes a fresh pass over the source to copy what's missing
+        Checkpoint { cursor: None, copied: 0, skipped: 0, ..checkpoint }.save(checkpoint_path)?;
+    }
+    Ok(report)
+}
+
+async fn count(storage: &dyn MemoryStorage) -> Result<usize> {
+    let mut total = 0;
+    let mut cursor = None;
+    loop {
+        let page = storage.list(cursor.as_deref(), BATCH_SIZE).await?;
+        total += page.memories.len();
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            return Ok(total);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::models::Memory;
+    use crate::storage::{conformance::memory, InMemoryStorage};
+    use std::sync::Arc;
+
+    fn in_memory() -> InMemoryStorage {
+        InMemoryStorage::new(Arc::new(DummyEmbeddingGenerator::new(4)), None, None, None, false).unwrap()
+    }
+
+    // Hashed "hash-0000", "hash-0001", ..., so pages follow their numbers
+    fn numbered(i: usize) -> Memory {
+        memory(&format!("{:04}", i), &["migrated"], i as i64, &[1.0, i as f32, 0.0, 0.0])
+    }
+
+    async fn source_of(count: usize) -> InMemoryStorage {
+        let source = in_memory();
+        for i in 0..count {
+            source.store(&numbered(i)).await.unwrap();
+        }
+        source
+    }
+
+    fn checkpoint_in(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("migrate-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        checkpoint_path(&dir, "source", "destination")
+    }
+
+    #[tokio::test]
+    async fn copies_everything_and_verifies() {
+        let source = source_of(3).await;
+        let destination = in_memory();
+        // Already present, and one the source doesn't have
+        destination.store(&numbered(1)).await.unwrap();
+        destination.store(&numbered(99)).await.unwrap();
+        let path = checkpoint_in("verify");
+
+        let report = migrate(&source, &destination, "source", "destination", &path, false).await.unwrap();
+        assert!(!report.resumed);
+        assert_eq!((report.copied, report.skipped, report.missing), (2, 1, 0));
+        assert_eq!((report.source_count, report.destination_count), (3, 4));
+        assert!(report.is_verified());
+        assert!(!path.exists());
+        let copied = destination.get("hash-0002").await.unwrap().unwrap();
+        assert_eq!((copied.timestamp_seconds, copied.embedding), (2, numbered(2).embedding));
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn resumes_after_the_last_checkpointed_batch() {
+        let source = source_of(BATCH_SIZE + 10).await;
+        let destination = in_memory();
+        let path = checkpoint_in("resume");
+
+        // A run interrupted right after checkpointing its first batch
+        let first = source.list(None, BATCH_SIZE).await.unwrap();
+        for memory in &first.memories {
+            destination.store(memory).await.unwrap();
+        }
+        let interrupted = Checkpoint {
+            source: "source".to_string(),
+            destination: "destination".to_string(),
+            cursor: first.next_cursor,
+            copied: BATCH_SIZE,
+            skipped: 0,
+        };
+        interrupted.save(&path).unwrap();
+
+        let report = migrate(&source, &destination, "source", "destination", &path, false).await.unwrap();
+        assert!(report.resumed);
+        // The first batch isn't looked at again, so nothing counts as already present
+        assert_eq!((report.copied, report.skipped, report.missing), (BATCH_SIZE + 10, 0, 0));
+        assert_eq!((report.source_count, report.destination_count), (BATCH_SIZE + 10, BATCH_SIZE + 10));
+        assert!(!path.exists());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn checkpoints_of_other_migrations_are_rejected_unless_restarting() {
+        let source = source_of(2).await;
+        let destination = in_memory();
+        let path = checkpoint_in("other");
+        let other = Checkpoint {
+            source: "elsewhere".to_string(),
+            destination: "destination".to_string(),
+            cursor: Some("hash-0001".to_string()),
+            copied: 1,
+            skipped: 0,
+        };
+        other.save(&path).unwrap();
+
+        let error = migrate(&source, &destination, "source", "destination", &path, false).await.unwrap_err();
+        assert!(error.to_string().contains("different migration"), "{}", error);
+        assert_eq!(count(&destination).await.unwrap(), 0);
+
+        // Restarting ignores the checkpoint and copies from the start
+        let report = migrate(&source, &destination, "source", "destination", &path, true).await.unwrap();
+        assert!(!report.resumed);
+        assert_eq!((report.copied, report.skipped, report.missing), (2, 0, 0));
+        assert!(!path.exists());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -37,6 +37,14 @@ pub struct MemoryQueryResult {
     pub relevance_score: f32,
 }
 
+/// One page of a listing of every memory in a store
+#[derive(Debug, Clone)]
+pub struct MemoryPage {
+    pub memories: Vec<Memory>,
+    // Opaque position to continue from, None after the last page
+    pub next_cursor: Option<String>,
+}
+
 // Request types for tools
 #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct StoreMemoryRequest {
@@ -44,20 +52,91 @@ pub struct StoreMemoryRequest {
     pub tags: Option<Vec<String>>,
     pub memory_type: Option<String>,
     pub metadata: Option<HashMap<String, String>>,
+    /// Namespace to use instead of the server's default one
+    pub namespace: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct RetrieveMemoryRequest {
     pub query: String,
     pub n_results: Option<usize>,
+    /// Search memories from every client root instead of only the current one
+    pub all_roots: Option<bool>,
+    /// Namespace to use instead of the server's default one
+    pub namespace: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct SearchByTagRequest {
     pub tags: Vec<String>,
+    /// Search memories from every client root instead of only the current one
+    pub all_roots: Option<bool>,
+    /// Namespace to use instead of the server's default one
+    pub namespace: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct DeleteMemoryRequest {
     pub content_hash: String,
+    /// Token returned by a previous call, confirming the deletion on clients without elicitation support
+    pub confirmation_token: Option<String>,
+    /// Namespace to use instead of the server's default one
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct DeleteByTagRequest {
+    /// Delete memories carrying any of these tags
+    pub tags: Vec<String>,
+    /// Delete matching memories from every client root instead of only the current one
+    pub all_roots: Option<bool>,
+    /// Token returned by a previous call, confirming the deletion on clients without elicitation support
+    pub confirmation_token: Option<String>,
+    /// Namespace to use instead of the server's default one
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct ConsolidateMemoriesRequest {
+    /// Select memories carrying any of these tags
+    pub tags: Option<Vec<String>>,
+    /// Start of the time window (seconds since epoch, inclusive)
+    pub start_timestamp: Option<i64>,
+    /// End of the time window (seconds since epoch, inclusive)
+    pub end_timestamp: Option<i64>,
+    /// Select memories semantically similar to this query
+    pub query: Option<String>,
+    /// Maximum number of memories to consolidate (default 20)
+    pub max_memories: Option<usize>,
+    /// Maximum number of tokens the client may generate for the summary (default 1024)
+    pub max_tokens: Option<u32>,
+    /// Replace the source memories with copies tagged as archived once the summary is stored
+    pub archive_originals: Option<bool>,
+    /// Token returned by a previous call, confirming the replacement of the sources on clients without elicitation support
+    pub confirmation_token: Option<String>,
+    /// Namespace to use instead of the server's default one
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct MigrateMemoriesRequest {
+    /// Name of a destination storage configured on the server in MCP_MEMORY_MIGRATION_DESTINATIONS
+    pub destination: String,
+    /// Discard the progress of an earlier, interrupted run instead of resuming it
+    pub restart: Option<bool>,
+    /// Namespace to migrate from instead of the server's default one
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct CreateNamespaceRequest {
+    /// Name of the new namespace: letters, digits, '-' and '_'
+    pub namespace: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct DropNamespaceRequest {
+    pub namespace: String,
+    /// Token returned by a previous call, confirming the deletion on clients without elicitation support
+    pub confirmation_token: Option<String>,
 }
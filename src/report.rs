@@ -0,0 +1,176 @@
+use crate::embeddings::EmbeddingGenerator;
+use crate::storage::{MemoryStorage, Quantization};
+use anyhow::Result;
+use std::fmt;
+
+/// Queries run by `quantization-report` unless told otherwise
+pub const DEFAULT_QUERIES: usize = 100;
+/// Results per query compared by `quantization-report`
+pub const RECALL_AT: usize = 10;
+
+// Page size while reading every embedding
+const PAGE_SIZE: usize = 256;
+
+/// Where the measured storage keeps the embeddings it searches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddingResidence {
+    /// In memory: a copy in the scan matrix, another in the HNSW graph once
+    /// the store holds more than `index_threshold` memories, and the
+    /// memories' own full-precision embeddings unless quantised, when
+    /// rescoring generates them again instead
+    Memory { index_threshold: Option<usize> },
+    /// In a database file: the full-precision embeddings, plus codes stored
+    /// next to them when quantised
+    Disk,
+    /// In a storage service, which this server doesn't quantise
+    Remote,
+}
+
+/// Memory saved by quantised embeddings and recall lost by searching them
+#[derive(Debug)]
+pub struct QuantizationReport {
+    pub quantization: Option<Quantization>,
+    pub residence: EmbeddingResidence,
+    /// Memories with an embedding of the common dimensions
+    pub memories: usize,
+    pub dimensions: usize,
+    /// Bytes of one copy of the searched embeddings at full precision
+    pub full_precision_bytes: usize,
+    /// Bytes of one copy of the searched embeddings as codes
+    pub quantized_bytes: usize,
+    /// Bytes of one full-precision embedding per memory, the baseline the
+    /// storage's footprint is compared with
+    pub baseline_bytes: usize,
+    /// Bytes of every copy of the embeddings the storage keeps as configured
+    pub total_bytes: usize,
+    pub queries: usize,
+    /// Mean share of the exact top results that the storage returned
+    pub recall: f64,
+}
+
+impl fmt::Display for QuantizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = self.quantization.map_or("full-precision".to_string(), |quantization| format!("{:?}", quantization).to_lowercase());
+        writeln!(f, "{} memories with {}-dimensional embeddings, searched as {} vectors.", self.memories, self.dimensions, name)?;
+        let change = change(self.baseline_bytes, self.total_bytes);
+        match (self.residence, self.quantization) {
+            (EmbeddingResidence::Memory { .. }, Some(_)) => writeln!(
+                f,
+                "Memory: {} bytes of embeddings held, against {} for one full-precision embedding per memory ({}). Only codes are held; rescoring generates the candidates' embeddings again.",
+                self.total_bytes, self.baseline_bytes, change
+            )?,
+            (EmbeddingResidence::Memory { .. }, None) => writeln!(
+                f,
+                "Memory: {} bytes of embeddings held, against {} for one full-precision embedding per memory ({}), as the search structures hold copies of the memories' own embeddings.",
+                self.total_bytes, self.baseline_bytes, change
+            )?,
+            (EmbeddingResidence::Disk, Some(_)) => writeln!(
+                f,
+                "Disk: {} bytes of embeddings stored, against {} for one full-precision embedding per memory ({}), as codes are stored next to the full-precision embeddings read back for rescoring. Each search reads {} bytes of codes instead of {} bytes of embeddings.",
+                self.total_bytes, self.baseline_bytes, change, self.quantized_bytes, self.full_precision_bytes
+            )?,
+            (EmbeddingResidence::Disk, None) => {
+                writeln!(f, "Disk: {} bytes of embeddings stored, one full-precision embedding per memory.", self.total_bytes)?
+            }
+            (EmbeddingResidence::Remote, _) => writeln!(f, "Memory: the embeddings are held by the storage service, which this server doesn't quantise.")?,
+        }
+        write!(
+            f,
+            "Recall@{}: {:.3} over {} queries against an exact search ({:.1}% lost).",
+            RECALL_AT,
+            self.recall,
+            self.queries,
+            100.0 * (1.0 - self.recall)
+        )
+    }
+}
+
+// Relative change from `before` to `after`, for the report
+fn change(before: usize, after: usize) -> String {
+    if before == 0 || before == after {
+        return "unchanged".to_string();
+    }
+    let percent = 100.0 * (after as f64 - before as f64).abs() / before as f64;
+    format!("{:.1}% {}", percent, if after < before { "less" } else { "more" })
+}
+
+/// Compare the storage's similarity search with an exact ranking of the
+/// stored full-precision embeddings, generated again for memories listed
+/// without one. Each query sits between two stored memories, so neither is
+/// trivially the best match.
+pub async fn measure(
+    storage: &dyn MemoryStorage,
+    embedding_generator: &dyn EmbeddingGenerator,
+    quantization: Option<Quantization>,
+    residence: EmbeddingResidence,
+    queries: usize,
+) -> Result<QuantizationReport> {
+    let mut embeddings: Vec<(String, Vec<f32>)> = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = storage.list(cursor.as_deref(), PAGE_SIZE).await?;
+        for memory in page.memories {
+            let embedding = match memory.embedding {
+                Some(embedding) => embedding,
+                None => embedding_generator.generate_embedding(&memory.content).await?,
+            };
+            embeddings.push((memory.content_hash, embedding));
+        }
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    let dimensions = embeddings.first().map_or(0, |(_, embedding)| embedding.len());
+    embeddings.retain(|(_, embedding)| embedding.len() == dimensions);
+    for (_, embedding) in &mut embeddings {
+        let magnitude = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if magnitude > 0.0 {
+            embedding.iter_mut().for_each(|x| *x /= magnitude);
+        }
+    }
+
+    let count = embeddings.len();
+    let queries = if count < 2 { 0 } else { queries.min(count) };
+    let mut total_recall = 0.0;
+    for i in 0..queries {
+        let (a, b) = (&embeddings[i * count / queries].1, &embeddings[(i * count / queries + count / 2) % count].1);
+        let query: Vec<f32> = a.iter().zip(b).map(|(x, y)| x + y).collect();
+
+        let mut exact: Vec<(&str, f32)> = embeddings
+            .iter()
+            .map(|(hash, embedding)| (hash.as_str(), embedding.iter().zip(&query).map(|(x, y)| x * y).sum()))
+            .collect();
+        exact.sort_by(|a, b| b.1.total_cmp(&a.1));
+        exact.truncate(RECALL_AT);
+
+        let found = storage.retrieve(&query, RECALL_AT).await?;
+        let hits = exact.iter().filter(|(hash, _)| found.iter().any(|result| result.memory.content_hash == *hash)).count();
+        total_recall += hits as f64 / exact.len() as f64;
+    }
+
+    let full_precision_bytes = count * dimensions * size_of::<f32>();
+    let quantized_bytes = quantization.map_or(full_precision_bytes, |quantization| count * quantization.code_len(dimensions));
+    let total_bytes = match (residence, quantization) {
+        (EmbeddingResidence::Memory { index_threshold }, _) => {
+            let search_copies = 1 + usize::from(index_threshold.is_some_and(|threshold| count > threshold));
+            let own_embeddings = if quantization.is_some() { 0 } else { full_precision_bytes };
+            own_embeddings + quantized_bytes * search_copies
+        }
+        // Codes are stored after a byte tagging their kind
+        (EmbeddingResidence::Disk, Some(_)) => full_precision_bytes + quantized_bytes + count,
+        (EmbeddingResidence::Disk | EmbeddingResidence::Remote, _) => full_precision_bytes,
+    };
+    Ok(QuantizationReport {
+        quantization,
+        residence,
+        memories: count,
+        dimensions,
+        full_precision_bytes,
+        quantized_bytes,
+        baseline_bytes: full_precision_bytes,
+        total_bytes,
+        queries,
+        recall: if queries > 0 { total_recall / queries as f64 } else { 1.0 },
+    })
+}
@@ -0,0 +1,41 @@
+use crate::models::Memory;
+use rmcp::{Peer, RoleServer};
+
+/// Metadata key recording the client root (workspace) a memory was stored from
+pub const ROOT_METADATA_KEY: &str = "root";
+
+/// Retrieval fetches this many times the requested results before filtering
+/// by root, so that scoping doesn't leave the result list short
+pub const ROOT_OVERFETCH_FACTOR: usize = 4;
+
+/// Ask the client for its roots. Returns `None` if the client doesn't
+/// support roots or the request fails, in which case nothing is scoped.
+pub async fn fetch_roots(peer: &Peer<RoleServer>) -> Option<Vec<String>> {
+    let supports_roots = peer
+        .peer_info()
+        .is_some_and(|info| info.capabilities.roots.is_some());
+    if !supports_roots {
+        return None;
+    }
+
+    match peer.list_roots().await {
+        Ok(result) => {
+            let roots: Vec<String> = result.roots.into_iter().map(|root| root.uri).collect();
+            tracing::debug!("Client roots: {:?}", roots);
+            Some(roots)
+        }
+        Err(e) => {
+            tracing::warn!("Failed to list client roots: {}", e);
+            None
+        }
+    }
+}
+
+/// Whether a memory is visible from a session with the given roots. Memories
+/// stored without a root (e.g. by clients without roots support) are global.
+pub fn is_visible(memory: &Memory, roots: &[String]) -> bool {
+    match memory.metadata.get(ROOT_METADATA_KEY) {
+        Some(root) => roots.is_empty() || roots.contains(root),
+        None => true,
+    }
+}
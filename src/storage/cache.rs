@@ -0,0 +1,339 @@
+use crate::models::{Memory, MemoryPage, MemoryQueryResult};
+use super::{DeleteOutcome, MemoryStorage, StorageError, StorageResult, StoreOutcome};
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Default number of memories and of query results kept
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+/// Default lifetime of cached query results
+pub const DEFAULT_QUERY_TTL: Duration = Duration::from_secs(10);
+
+/// Settings of `CachedStorage`
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Entries kept in each of the hash and query caches before the least
+    /// recently used are evicted
+    pub capacity: usize,
+    /// How long the results of a similarity search are reused
+    pub query_ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CACHE_CAPACITY,
+            query_ttl: DEFAULT_QUERY_TTL,
+        }
+    }
+}
+
+/// Lookups answered from the cache and passed to the wrapped storage
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    /// `get` and `check_duplicate_exists` calls answered from the cache
+    pub hash_hits: u64,
+    pub hash_misses: u64,
+    /// `retrieve` calls answered from the cache
+    pub query_hits: u64,
+    pub query_misses: u64,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "hash lookups {} hits / {} misses, queries {} hits / {} misses",
+            self.hash_hits, self.hash_misses, self.query_hits, self.query_misses
+        )
+    }
+}
+
+// What is known about a content hash
+#[derive(Clone)]
+enum Known {
+    Absent,
+    // Exists, but hasn't been fetched
+    Present,
+    Memory(Memory),
+}
+
+// Map evicting the least recently used entry once it holds `capacity` entries
+struct Lru<K, V> {
+    entries: HashMap<K, (V, u64)>,
+    // Entries by the tick of their last use
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    capacity: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> Lru<K, V> {
+    fn new(capacity: usize) -> Self {
+        Self { entries: HashMap::new(), order: BTreeMap::new(), tick: 0, capacity: capacity.max(1) }
+    }
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let (_, used) = self.entries.get_mut(key)?;
+        self.order.remove(used);
+        self.tick += 1;
+        *used = self.tick;
+        self.order.insert(self.tick, key.clone());
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        self.remove(&key);
+        if self.entries.len() >= self.capacity
+            && let Some((_, oldest)) = self.order.pop_first()
+        {
+            self.entries.remove(&oldest);
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    fn remove(&mut self, key: &K) {
+        if let Some((_, used)) = self.entries.remove(key) {
+            self.order.remove(&used);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+// Query embeddings are compared bit for bit
+type QueryKey = (Vec<u32>, usize);
+
+/// Storage decorator caching lookups by content hash and, for a short time,
+/// the results of identical similarity searches. Writes through the wrapper
+/// keep the cache consistent; writes made to the storage by anyone else are
+/// only seen once their entries are evicted or expire.
+pub struct CachedStorage<S: MemoryStorage> {
+    inner: S,
+    config: CacheConfig,
+    hashes: Mutex<Lru<String, Known>>,
+    queries: Mutex<Lru<QueryKey, (Instant, Vec<MemoryQueryResult>)>>,
+    // Bumped after every write, so lookups that overlapped one don't cache what they read
+    generation: AtomicU64,
+    hash_hits: AtomicU64,
+    hash_misses: AtomicU64,
+    query_hits: AtomicU64,
+    query_misses: AtomicU64,
+}
+
+impl<S: MemoryStorage> CachedStorage<S> {
+    pub fn new(inner: S, config: CacheConfig) -> Self {
+        Self {
+            inner,
+            hashes: Mutex::new(Lru::new(config.capacity)),
+            queries: Mutex::new(Lru::new(config.capacity)),
+            config,
+            generation: AtomicU64::new(0),
+            hash_hits: AtomicU64::new(0),
+            hash_misses: AtomicU64::new(0),
+            query_hits: AtomicU64::new(0),
+            query_misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Hit and miss counts since the cache was created
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hash_hits: self.hash_hits.load(Ordering::Relaxed),
+            hash_misses: self.hash_misses.load(Ordering::Relaxed),
+            query_hits: self.query_hits.load(Ordering::Relaxed),
+            query_misses: self.query_misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn lookup(&self, content_hash: &str) -> Option<Known> {
+        self.hashes.lock().unwrap_or_else(|e| e.into_inner()).get(&content_hash.to_string()).cloned()
+    }
+
+    fn count_hash_lookup(&self, hit: bool) {
+        let counter = if hit { &self.hash_hits } else { &self.hash_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Cache what a lookup read, unless a write finished while it was reading
+    fn remember(&self, content_hash: &str, known: Known, generation: u64) {
+        let mut hashes = self.hashes.lock().unwrap_or_else(|e| e.into_inner());
+        if self.generation.load(Ordering::Acquire) == generation {
+            hashes.insert(content_hash.to_string(), known);
+        }
+    }
+
+    // Record a finished write to a memory, dropping every cached query result
+    fn written(&self, content_hash: &str, known: Option<Known>) {
+        let mut hashes = self.hashes.lock().unwrap_or_else(|e| e.into_inner());
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        match known {
+            Some(known) => hashes.insert(content_hash.to_string(), known),
+            None => hashes.remove(&content_hash.to_string()),
+        }
+        self.queries.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+#[async_trait]
+impl<S: MemoryStorage> MemoryStorage for CachedStorage<S> {
+    async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+        let result = self.inner.store(memory).await;
+        // The stored copy may differ from the argument, e.g. by a generated embedding, so only its existence is cached
+        let known = matches!(result, Ok(_) | Err(StorageError::Conflict(_))).then_some(Known::Present);
+        self.written(&memory.content_hash, known);
+        result
+    }
+
+    async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>> {
+        let key: QueryKey = (query_embedding.iter().map(|value| value.to_bits()).collect(), n_results);
+        let cached = match self.queries.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            Some((cached_at, results)) if cached_at.elapsed() < self.config.query_ttl => Some(results.clone()),
+            _ => None,
+        };
+        if let Some(results) = cached {
+            self.query_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(results);
+        }
+        self.query_misses.fetch_add(1, Ordering::Relaxed);
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let results = self.inner.retrieve(query_embedding, n_results).await?;
+        let mut queries = self.queries.lock().unwrap_or_else(|e| e.into_inner());
+        if self.generation.load(Ordering::Acquire) == generation {
+            queries.insert(key, (Instant::now(), results.clone()));
+        }
+        Ok(results)
+    }
+
+    async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>> {
+        let cached = match self.lookup(content_hash) {
+            Some(Known::Memory(memory)) => Some(Some(memory)),
+            Some(Known::Absent) => Some(None),
+            Some(Known::Present) | None => None,
+        };
+        self.count_hash_lookup(cached.is_some());
+        if let Some(memory) = cached {
+            return Ok(memory);
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let memory = self.inner.get(content_hash).await?;
+        let known = memory.clone().map_or(Known::Absent, Known::Memory);
+        self.remember(content_hash, known, generation);
+        Ok(memory)
+    }
+
+    async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>> {
+        self.inner.search_by_tag(tags).await
+    }
+
+    async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>> {
+        self.inner.search_by_time_range(start_seconds, end_seconds).await
+    }
+
+    async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+        let result = self.inner.delete(content_hash).await;
+        let known = matches!(result, Ok(_) | Err(StorageError::NotFound(_))).then_some(Known::Absent);
+        self.written(content_hash, known);
+        result
+    }
+
+    async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool> {
+        let cached = self.lookup(content_hash).map(|known| !matches!(known, Known::Absent));
+        self.count_hash_lookup(cached.is_some());
+        if let Some(exists) = cached {
+            return Ok(exists);
+        }
+
+        let generation = self.generation.load(Ordering::Acquire);
+        let exists = self.inner.check_duplicate_exists(content_hash).await?;
+        self.remember(content_hash, if exists { Known::Present } else { Known::Absent }, generation);
+        Ok(exists)
+    }
+
+    async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage> {
+        self.inner.list(cursor, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::storage::{conformance::memory, InMemoryStorage};
+    use std::sync::Arc;
+
+    fn cached(config: CacheConfig) -> CachedStorage<InMemoryStorage> {
+        let inner = InMemoryStorage::new(Arc::new(DummyEmbeddingGenerator::new(2)), None, None, None, false).unwrap();
+        CachedStorage::new(inner, config)
+    }
+
+    #[tokio::test]
+    async fn writes_invalidate_hash_lookups() {
+        let storage = cached(CacheConfig::default());
+        let first = memory("first", &[], 0, &[1.0, 0.0]);
+
+        assert!(!storage.check_duplicate_exists(&first.content_hash).await.unwrap());
+        assert!(storage.get(&first.content_hash).await.unwrap().is_none());
+        assert_eq!(storage.stats().hash_hits, 1);
+
+        assert_eq!(storage.store(&first).await.unwrap(), StoreOutcome::Stored);
+        assert!(storage.check_duplicate_exists(&first.content_hash).await.unwrap());
+        assert_eq!(storage.get(&first.content_hash).await.unwrap().unwrap().content, "first");
+        assert_eq!(storage.get(&first.content_hash).await.unwrap().unwrap().content, "first");
+        assert_eq!(storage.store(&first).await.unwrap(), StoreOutcome::Duplicate);
+
+        assert_eq!(storage.delete(&first.content_hash).await.unwrap(), DeleteOutcome::Deleted);
+        assert!(!storage.check_duplicate_exists(&first.content_hash).await.unwrap());
+        assert!(storage.get(&first.content_hash).await.unwrap().is_none());
+
+        let stats = storage.stats();
+        assert_eq!((stats.hash_hits, stats.hash_misses), (5, 2));
+    }
+
+    #[tokio::test]
+    async fn query_results_expire_and_are_invalidated_by_writes() {
+        let storage = cached(CacheConfig { query_ttl: Duration::from_millis(100), ..CacheConfig::default() });
+        storage.store(&memory("first", &[], 0, &[1.0, 0.0])).await.unwrap();
+        let query = vec![1.0, 1.0];
+
+        assert_eq!(storage.retrieve(&query, 5).await.unwrap().len(), 1);
+        assert_eq!(storage.retrieve(&query, 5).await.unwrap().len(), 1);
+        // A different number of results is a different query
+        storage.retrieve(&query, 4).await.unwrap();
+        assert_eq!((storage.stats().query_hits, storage.stats().query_misses), (1, 2));
+
+        storage.store(&memory("second", &[], 0, &[0.0, 1.0])).await.unwrap();
+        assert_eq!(storage.retrieve(&query, 5).await.unwrap().len(), 2);
+        assert_eq!(storage.stats().query_misses, 3);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        storage.retrieve(&query, 5).await.unwrap();
+        assert_eq!((storage.stats().query_hits, storage.stats().query_misses), (1, 4));
+    }
+
+    #[test]
+    fn least_recently_used_entries_are_evicted() {
+        let mut lru = Lru::new(2);
+        lru.insert("a", 1);
+        lru.insert("b", 2);
+        assert_eq!(lru.get(&"a"), Some(&1));
+        lru.insert("c", 3);
+        assert_eq!(lru.get(&"b"), None);
+        assert_eq!(lru.get(&"a"), Some(&1));
+        assert_eq!(lru.get(&"c"), Some(&3));
+    }
+}
@@ -1,104 +1,471 @@
-use crate::models::{Memory, MemoryQueryResult};
+use crate::config::Secret;
+use crate::models::{Memory, MemoryPage, MemoryQueryResult};
 use crate::embeddings::EmbeddingGenerator;
-use super::MemoryStorage;
+use super::resilience::{CircuitBreaker, RetryPolicy};
+use super::wal::WalEntry;
+use super::write_queue::WriteQueue;
+use super::{
+    check_time_range, cosine_similarity, duplicate_or_conflict, DeleteOutcome, MemoryStorage, StorageError, StorageResult, StoreOutcome,
+};
 use anyhow::{Context, Result};
 use async_trait::async_trait;
-use std::{collections::HashMap, sync::Arc, path::Path};
-use reqwest::Client;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc, path::PathBuf, time::Duration};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    Certificate, Client, Identity, Response, StatusCode,
+};
+use tokio::sync::Mutex;
 use url::Url;
 
+/// Default tenant and database of a Chroma server
+pub const DEFAULT_TENANT: &str = "default_tenant";
+pub const DEFAULT_DATABASE: &str = "default_database";
+
+/// Default timeout for requests to the Chroma server
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Prefix of the metadata keys flagging a memory's tags
+const TAG_PREFIX: &str = "tag_";
+
+/// How to authenticate with the Chroma server
+#[derive(Debug, Clone, Default)]
+pub enum ChromaAuth {
+    #[default]
+    None,
+    /// `Authorization: Bearer <token>`
+    Bearer(Secret),
+    /// `X-Chroma-Token: <token>`
+    ChromaToken(Secret),
+    /// HTTP basic authentication
+    Basic { username: String, password: Secret },
+}
+
+/// TLS settings for the connection to the Chroma server
+#[derive(Debug, Clone, Default)]
+pub struct ChromaTls {
+    /// Additional CA certificate (PEM) to trust, e.g. for a private CA
+    pub ca_cert: Option<PathBuf>,
+    /// Client certificate (PEM) for mutual TLS
+    pub client_cert: Option<PathBuf>,
+    /// Private key (PKCS#8 PEM) of the client certificate
+    pub client_key: Option<PathBuf>,
+}
+
+/// Where to find the collection on a Chroma server, and how to connect
+#[derive(Debug, Clone)]
+pub struct ChromaOptions {
+    pub base_url: Url,
+    pub collection_name: String,
+    pub tenant: String,
+    pub database: String,
+    pub auth: ChromaAuth,
+    pub tls: ChromaTls,
+    pub timeout: Duration,
+    pub retry: RetryPolicy,
+    /// Directory of the queues holding writes made while the server was
+    /// unreachable; None fails those writes instead
+    pub queue_dir: Option<PathBuf>,
+}
+
+/// Generation of the Chroma REST API spoken by the server
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ApiVersion {
+    /// `/api/v1/...`, tenant and database passed as query parameters
+    V1,
+    /// `/api/v2/tenants/{tenant}/databases/{database}/...`
+    V2,
+}
+
 /// ChromaDB storage implementation
 pub struct ChromaMemoryStorage {
     client: Client,
-    base_url: Url,
-    collection_name: String,
+    options: ChromaOptions,
+    api_version: ApiVersion,
+    // Collections are addressed by UUID, resolved once from the name at startup
+    collection_id: String,
     embedding_generator: Arc<dyn EmbeddingGenerator>,
+    breaker: CircuitBreaker,
+    // Writes waiting to be replayed, oldest first. Writes hold the lock while
+    // they run so that they reach the server in the order they were accepted.
+    queue: Option<Mutex<WriteQueue>>,
+}
+
+// Whether a request failed because the server couldn't be reached, rather
+// than because the server rejected it
+fn is_unavailable(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<StorageError>(), Some(StorageError::Unavailable(_)))
 }
 
 impl ChromaMemoryStorage {
-    /// Create a new ChromaDB storage instance
+    /// Create a new ChromaDB storage instance, creating the collection if needed
     pub async fn new(
-        base_url: Url,
-        collection_name: String,
+        options: ChromaOptions,
         embedding_generator: Arc<dyn EmbeddingGenerator>,
     ) -> Result<Self> {
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()
-            .context("Failed to create HTTP client")?;
+        let client = Self::build_client(&options)?;
 
-        // Ensure the collection exists
+        let api_version = Self::detect_api_version(&client, &options.base_url).await?;
+        let collection_id = Self::resolve_collection_id(&client, &options, api_version).await?;
+        tracing::info!(
+            "Using ChromaDB collection '{}' ({}) via API {:?}",
+            options.collection_name, collection_id, api_version
+        );
+
+        // One queue per collection, so pointing the server elsewhere never replays writes meant for another
+        let queue = match &options.queue_dir {
+            Some(dir) => {
+                let identity = format!("{}|{}|{}|{}", options.base_url, options.tenant, options.database, options.collection_name);
+                let name = format!("{}-{}.jsonl", options.collection_name, &hex::encode(Sha256::digest(identity.as_bytes()))[..16]);
+                Some(Mutex::new(WriteQueue::open(&dir.join(name))?))
+            }
+            None => None,
+        };
+
+        let breaker = CircuitBreaker::new(&options.retry);
         let storage = Self {
             client,
-            base_url,
-            collection_name,
+            options,
+            api_version,
+            collection_id,
             embedding_generator,
+            breaker,
+            queue,
+        };
+        if let Some(queue) = &storage.queue {
+            let mut queue = queue.lock().await;
+            if let Err(e) = storage.replay(&mut queue).await {
+                tracing::warn!("Failed to replay queued writes: {}", e);
+            }
+        }
+        Ok(storage)
+    }
+
+    /// HTTP client with the configured timeout, TLS settings and credentials
+    fn build_client(options: &ChromaOptions) -> Result<Client> {
+        let mut builder = Client::builder().timeout(options.timeout);
+
+        if let Some(path) = &options.tls.ca_cert {
+            let pem = std::fs::read(path).context(format!("Failed to read ChromaDB CA certificate {:?}", path))?;
+            let certificate = Certificate::from_pem(&pem).context("Invalid ChromaDB CA certificate")?;
+            builder = builder.add_root_certificate(certificate);
+        }
+
+        match (&options.tls.client_cert, &options.tls.client_key) {
+            (Some(cert_path), Some(key_path)) => {
+                let cert = std::fs::read(cert_path).context(format!("Failed to read ChromaDB client certificate {:?}", cert_path))?;
+                let key = std::fs::read(key_path).context(format!("Failed to read ChromaDB client key {:?}", key_path))?;
+                let identity = Identity::from_pkcs8_pem(&cert, &key).context("Invalid ChromaDB client certificate or key")?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => return Err(anyhow::anyhow!("A ChromaDB client certificate needs both a certificate and a key")),
+        }
+
+        let mut headers = HeaderMap::new();
+        let auth_header = match &options.auth {
+            ChromaAuth::None => None,
+            ChromaAuth::Bearer(token) => Some((AUTHORIZATION, format!("Bearer {}", token.expose()))),
+            ChromaAuth::ChromaToken(token) => Some((HeaderName::from_static("x-chroma-token"), token.expose().to_string())),
+            ChromaAuth::Basic { username, password } => {
+                let credentials = base64::engine::general_purpose::STANDARD
+                    .encode(format!("{}:{}", username, password.expose()));
+                Some((AUTHORIZATION, format!("Basic {}", credentials)))
+            }
         };
+        if let Some((name, value)) = auth_header {
+            let mut value = HeaderValue::from_str(&value).context("ChromaDB credentials contain invalid characters")?;
+            // Keep credentials out of debug output
+            value.set_sensitive(true);
+            headers.insert(name, value);
+        }
 
-        storage.ensure_collection_exists().await?;
+        builder
+            .default_headers(headers)
+            .build()
+            .context("Failed to create HTTP client")
+    }
 
-        Ok(storage)
+    /// Find out which API the server speaks from its heartbeat endpoints
+    async fn detect_api_version(client: &Client, base_url: &Url) -> Result<ApiVersion> {
+        for (version, path) in [(ApiVersion::V2, "/api/v2/heartbeat"), (ApiVersion::V1, "/api/v1/heartbeat")] {
+            let response = client.get(base_url.join(path)?)
+                .send()
+                .await
+                .context("Failed to reach ChromaDB heartbeat")?;
+            if response.status().is_success() {
+                return Ok(version);
+            }
+        }
+        Err(anyhow::anyhow!("ChromaDB at {} answered neither the v2 nor the v1 heartbeat", base_url))
     }
 
-    /// Create a new ChromaDB storage instance from a local path
-    pub async fn from_path<P: AsRef<Path>>(
-        _path: P,
-        collection_name: String,
-        embedding_generator: Arc<dyn EmbeddingGenerator>,
-    ) -> Result<Self> {
-        // For local ChromaDB, we would typically use the HTTP API on localhost
-        // This is a simplified approach - in a real implementation, you might want to
-        // start the ChromaDB server if it's not running
-        let base_url = Url::parse("http://localhost:8000").context("Failed to parse ChromaDB URL")?;
-        
-        Self::new(base_url, collection_name, embedding_generator).await
+    /// URL of the collections endpoint for the configured tenant and database
+    fn collections_url(options: &ChromaOptions, api_version: ApiVersion) -> Result<Url> {
+        let url = match api_version {
+            ApiVersion::V2 => options.base_url.join(&format!(
+                "/api/v2/tenants/{}/databases/{}/collections",
+                options.tenant, options.database
+            ))?,
+            ApiVersion::V1 => {
+                let mut url = options.base_url.join("/api/v1/collections")?;
+                url.query_pairs_mut()
+                    .append_pair("tenant", &options.tenant)
+                    .append_pair("database", &options.database);
+                url
+            }
+        };
+        Ok(url)
     }
 
-    /// Ensure the collection exists, creating it if necessary
-    async fn ensure_collection_exists(&self) -> Result<()> {
-        // Check if collection exists
-        let collections_url = self.base_url.join("/api/v1/collections")?;
-        let response = self.client.get(collections_url)
+    /// Look up the collection's UUID, creating the collection if it doesn't exist
+    async fn resolve_collection_id(client: &Client, options: &ChromaOptions, api_version: ApiVersion) -> Result<String> {
+        let response = client.post(Self::collections_url(options, api_version)?)
+            .json(&serde_json::json!({
+                "name": options.collection_name,
+                "metadata": { "hnsw:space": "cosine" }, // Use cosine similarity
+                "get_or_create": true
+            }))
             .send()
             .await
-            .context("Failed to get collections")?;
+            .context("Failed to get or create collection")?;
 
         if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Failed to get collections: {}", response.status()));
+            return Err(anyhow::anyhow!("Failed to get or create collection: {}", response.status()));
         }
 
-        let collections: serde_json::Value = response.json().await
-            .context("Failed to parse collections response")?;
+        let collection: serde_json::Value = response.json().await
+            .context("Failed to parse collection response")?;
 
-        // Check if our collection exists
-        let collection_exists = if let Some(collections_array) = collections.as_array() {
-            collections_array.iter().any(|c| {
-                c.get("name").and_then(|n| n.as_str()) == Some(&self.collection_name)
-            })
-        } else {
-            false
+        collection.get("id")
+            .and_then(|id| id.as_str())
+            .map(|id| id.to_string())
+            .ok_or_else(|| anyhow::anyhow!("Collection response has no id"))
+    }
+
+    /// URL of an operation (`add`, `get`, `query`, `delete`) on the collection
+    fn collection_url(&self, operation: &str) -> Result<Url> {
+        let path = match self.api_version {
+            ApiVersion::V2 => format!(
+                "/api/v2/tenants/{}/databases/{}/collections/{}/{}",
+                self.options.tenant, self.options.database, self.collection_id, operation
+            ),
+            ApiVersion::V1 => format!("/api/v1/collections/{}/{}", self.collection_id, operation),
         };
+        Ok(self.options.base_url.join(&path)?)
+    }
 
-        // Create collection if it doesn't exist
-        if !collection_exists {
-            let create_url = self.base_url.join("/api/v1/collections")?;
-            let response = self.client.post(create_url)
-                .json(&serde_json::json!({
-                    "name": self.collection_name,
-                    "metadata": { "hnsw:space": "cosine" } // Use cosine similarity
-                }))
-                .send()
-                .await
-                .context("Failed to create collection")?;
+    /// POST a request to the server. Every request this backend sends is
+    /// idempotent, so failures to reach the server and server errors are
+    /// retried with exponential backoff; those still failing after the last
+    /// retry, or rejected by the open circuit breaker, are `Unavailable`.
+    async fn post(&self, url: Url, body: &serde_json::Value, what: &str) -> Result<Response> {
+        let Some(permit) = self.breaker.allow() else {
+            return Err(StorageError::Unavailable(format!("{}: ChromaDB is not responding, waiting before trying again", what)).into());
+        };
+
+        let mut retry = 0;
+        loop {
+            let failure = match self.client.post(url.clone()).json(body).send().await {
+                Ok(response) if response.status().is_success() => {
+                    permit.record_success();
+                    return Ok(response);
+                }
+                Ok(response) if response.status().is_server_error() || response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    format!("{}: {}", what, response.status())
+                }
+                Ok(response) => {
+                    // The server is up but refused the request, which retrying won't change
+                    permit.record_success();
+                    return Err(anyhow::anyhow!("{}: {}", what, response.status()));
+                }
+                Err(e) => format!("{}: {}", what, e),
+            };
+
+            if retry >= self.options.retry.max_retries {
+                permit.record_failure();
+                return Err(StorageError::Unavailable(failure).into());
+            }
+            let backoff = self.options.retry.backoff(retry);
+            tracing::debug!("{}, retrying in {:?}", failure, backoff);
+            tokio::time::sleep(backoff).await;
+            retry += 1;
+        }
+    }
+
+    /// Send queued writes to the server in order, removing those that were
+    /// sent. Succeeds once the queue is empty. A write the server rejects is
+    /// logged and dropped, so that it can't hold up the ones behind it.
+    async fn replay(&self, queue: &mut WriteQueue) -> Result<()> {
+        if queue.is_empty() {
+            return Ok(());
+        }
+        let total = queue.len();
+        let mut replayed = 0;
+        let mut outcome = Ok(());
+        for entry in queue.entries() {
+            let result = match entry {
+                WalEntry::Store { memory, embedding } => match embedding {
+                    Some(embedding) => self.upsert(memory, embedding).await,
+                    None => Err(anyhow::anyhow!("queued memory has no embedding")),
+                },
+                WalEntry::Delete { content_hash } => self.remove(content_hash).await,
+            };
+            match result {
+                Err(e) if is_unavailable(&e) => {
+                    outcome = Err(e);
+                    break;
+                }
+                Err(e) => tracing::error!("ChromaDB rejected queued write to memory {}, dropping it: {}", entry.content_hash(), e),
+                Ok(()) => {}
+            }
+            replayed += 1;
+        }
+
+        if replayed > 0 {
+            queue.remove_replayed(replayed)?;
+            tracing::info!("Replayed {} of {} queued writes to ChromaDB", replayed, total);
+        }
+        outcome
+    }
 
-            if !response.status().is_success() {
-                return Err(anyhow::anyhow!("Failed to create collection: {}", response.status()));
+    /// Replay queued writes before a read, so that it sees them once the
+    /// server is back. The read goes ahead whether or not that worked.
+    async fn replay_pending(&self) {
+        if let Some(queue) = &self.queue {
+            let mut queue = queue.lock().await;
+            if let Err(e) = self.replay(&mut queue).await {
+                tracing::debug!("Queued writes not replayed yet: {}", e);
             }
         }
+    }
+
+    /// Replay queued writes, then look up the latest write to a memory still
+    /// queued: Some(Some(memory)) for a store, Some(None) for a delete
+    async fn queued(&self, content_hash: &str) -> Option<Option<Memory>> {
+        let queue = self.queue.as_ref()?;
+        let mut queue = queue.lock().await;
+        if let Err(e) = self.replay(&mut queue).await {
+            tracing::debug!("Queued writes not replayed yet: {}", e);
+        }
+        queue.latest(content_hash).map(|entry| match entry {
+            WalEntry::Store { memory, embedding } => Some(Memory { embedding: embedding.clone(), ..memory.clone() }),
+            WalEntry::Delete { .. } => None,
+        })
+    }
+
+    /// Add or replace a memory. Upserting rather than adding keeps retries
+    /// and replays harmless if an earlier attempt did reach the server.
+    async fn upsert(&self, memory: &Memory, embedding: &[f32]) -> Result<()> {
+        let metadata = self.format_metadata(memory);
+        let body = serde_json::json!({
+            "ids": [memory.content_hash],
+            "embeddings": [embedding],
+            "metadatas": [metadata],
+            "documents": [memory.content]
+        });
+        self.post(self.collection_url("upsert")?, &body, "Failed to store memory").await?;
+        Ok(())
+    }
 
+    async fn remove(&self, content_hash: &str) -> Result<()> {
+        let body = serde_json::json!({ "ids": [content_hash] });
+        self.post(self.collection_url("delete")?, &body, "Failed to delete memory").await?;
         Ok(())
     }
 
+    /// Content of a memory the server holds, ignoring the queue
+    async fn stored_content(&self, content_hash: &str) -> Result<Option<String>> {
+        let body = serde_json::json!({ "ids": [content_hash], "include": ["documents"] });
+        let response = self.post(self.collection_url("get")?, &body, "Failed to check for duplicate").await?;
+
+        let result: serde_json::Value = response.json().await
+            .context("Failed to parse duplicate check response")?;
+
+        // Check if any documents were returned
+        Ok(result.get("documents")
+            .and_then(|documents| documents.as_array())
+            .and_then(|documents| documents.first())
+            .map(|document| document.as_str().unwrap_or_default().to_string()))
+    }
+
+    async fn embedding(&self, memory: &Memory) -> Result<Vec<f32>> {
+        match &memory.embedding {
+            Some(embedding) => Ok(embedding.clone()),
+            None => Ok(self.embedding_generator.generate_embedding(&memory.content).await?),
+        }
+    }
+
+    async fn store_now(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+        if let Some(existing) = self.stored_content(&memory.content_hash).await? {
+            return duplicate_or_conflict(memory, &existing);
+        }
+        let embedding = self.embedding(memory).await?;
+        self.upsert(memory, &embedding).await?;
+        Ok(StoreOutcome::Stored)
+    }
+
+    async fn delete_now(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+        if self.stored_content(content_hash).await?.is_none() {
+            return Err(StorageError::NotFound(content_hash.to_string()));
+        }
+        self.remove(content_hash).await?;
+        Ok(DeleteOutcome::Deleted)
+    }
+
+    /// Fetch all memories matching a `where` filter
+    async fn get_where(&self, where_filter: serde_json::Value) -> Result<Vec<Memory>> {
+        self.get_matching(serde_json::json!({ "where": where_filter })).await
+    }
+
+    /// Fetch all memories selected by a get request body (`where` filter or `ids`)
+    async fn get_matching(&self, selector: serde_json::Value) -> Result<Vec<Memory>> {
+        let get_url = self.collection_url("get")?;
+        
+        let mut body = selector;
+        body["include"] = serde_json::json!(["metadatas", "documents", "embeddings"]);
+        self.replay_pending().await;
+        let response = self.post(get_url, &body, "Failed to get memories").await?;
+        
+        let result: serde_json::Value = response.json().await
+            .context("Failed to parse get response")?;
+            
+        // Process results
+        let ids = result.get("ids").and_then(|ids| ids.as_array());
+        let documents = result.get("documents").and_then(|docs| docs.as_array());
+        let metadatas = result.get("metadatas").and_then(|meta| meta.as_array());
+        let embeddings = result.get("embeddings").and_then(|emb| emb.as_array());
+        
+        let mut memories = Vec::new();
+        
+        if let (Some(ids), Some(documents), Some(metadatas)) = (ids, documents, metadatas) {
+            for i in 0..ids.len() {
+                if let (Some(id), Some(document), Some(metadata)) = (
+                    ids.get(i).and_then(|v| v.as_str()),
+                    documents.get(i).and_then(|v| v.as_str()),
+                    metadatas.get(i).and_then(|v| v.as_object()),
+                ) {
+                    // Convert metadata to HashMap
+                    let metadata_map: HashMap<String, serde_json::Value> = metadata.iter()
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect();
+                    
+                    // Extract embedding if available
+                    let embedding = embeddings.and_then(|embs| embs.get(i))
+                        .and_then(|emb| emb.as_array())
+                        .map(|arr| arr.iter().filter_map(|v| v.as_f64().map(|d| d as f32)).collect::<Vec<f32>>());
+                    
+                    // Parse memory
+                    let memory = self.parse_metadata(id, document, &metadata_map, embedding)?;
+                    memories.push(memory);
+                }
+            }
+        }
+        
+        Ok(memories)
+    }
+
     /// Format memory metadata for ChromaDB
     fn format_metadata(&self, memory: &Memory) -> HashMap<String, serde_json::Value> {
         let mut metadata = HashMap::new();
@@ -112,10 +479,14 @@ impl ChromaMemoryStorage {
             metadata.insert("memory_type".to_string(), serde_json::Value::String(memory_type.clone()));
         }
         
-        // Add tags as JSON array
-        metadata.insert("tags".to_string(), serde_json::Value::Array(
-            memory.tags.iter().map(|t| serde_json::Value::String(t.clone())).collect()
+        // Chroma metadata values can't be lists, so the tags are kept as a JSON
+        // string, plus a flag per tag for `search_by_tag` to filter on
+        metadata.insert("tags".to_string(), serde_json::Value::String(
+            serde_json::to_string(&memory.tags).unwrap_or_default()
         ));
+        for tag in &memory.tags {
+            metadata.insert(format!("{}{}", TAG_PREFIX, tag), serde_json::Value::Bool(true));
+        }
         
         // Add user metadata
         for (key, value) in &memory.metadata {
@@ -146,16 +517,15 @@ impl ChromaMemoryStorage {
             .and_then(|v| v.as_str())
             .map(|s| s.to_string());
         
-        // Extract tags
-        let tags = metadata.get("tags")
-            .and_then(|v| v.as_array())
-            .map(|arr| {
-                arr.iter()
-                    .filter_map(|v| v.as_str())
-                    .map(|s| s.to_string())
-                    .collect()
-            })
-            .unwrap_or_default();
+        // Extract tags, which older versions stored as a list
+        let tags = match metadata.get("tags") {
+            Some(serde_json::Value::String(tags)) => serde_json::from_str(tags).unwrap_or_default(),
+            Some(serde_json::Value::Array(arr)) => arr.iter()
+                .filter_map(|v| v.as_str())
+                .map(|s| s.to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
         
         // Extract user metadata
         let mut user_metadata = HashMap::new();
@@ -181,83 +551,43 @@ impl ChromaMemoryStorage {
 
 #[async_trait]
 impl MemoryStorage for ChromaMemoryStorage {
-    async fn check_duplicate_exists(&self, content_hash: &str) -> Result<bool> {
-        let get_url = self.base_url.join(&format!("/api/v1/collections/{}/get", self.collection_name))?;
-        
-        let response = self.client.post(get_url)
-            .json(&serde_json::json!({
-                "ids": [content_hash]
-            }))
-            .send()
-            .await
-            .context("Failed to check for duplicate")?;
-            
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Failed to check for duplicate: {}", response.status()));
+    async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool> {
+        match self.queued(content_hash).await {
+            Some(queued) => Ok(queued.is_some()),
+            None => Ok(self.stored_content(content_hash).await?.is_some()),
         }
-        
-        let result: serde_json::Value = response.json().await
-            .context("Failed to parse duplicate check response")?;
-            
-        // Check if any documents were returned
-        let ids = result.get("ids").and_then(|ids| ids.as_array());
-        Ok(ids.map(|arr| !arr.is_empty()).unwrap_or(false))
     }
 
-    async fn store(&self, memory: &Memory) -> Result<(bool, String)> {
-        // Check for duplicates
-        if self.check_duplicate_exists(&memory.content_hash).await? {
-            return Ok((false, "Duplicate content detected".to_string()));
-        }
-        
-        // Generate embedding if not already present
-        let embedding = if let Some(ref emb) = memory.embedding {
-            emb.clone()
-        } else {
-            self.embedding_generator.generate_embedding(&memory.content).await?
+    async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+        let Some(queue) = &self.queue else {
+            return self.store_now(memory).await;
         };
-        
-        // Format metadata
-        let metadata = self.format_metadata(memory);
-        
-        // Add to ChromaDB
-        let add_url = self.base_url.join(&format!("/api/v1/collections/{}/add", self.collection_name))?;
-        
-        let response = self.client.post(add_url)
-            .json(&serde_json::json!({
-                "ids": [memory.content_hash],
-                "embeddings": [embedding],
-                "metadatas": [metadata],
-                "documents": [memory.content]
-            }))
-            .send()
-            .await
-            .context("Failed to store memory")?;
-            
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Failed to store memory: {}", response.status()));
+        let mut queue = queue.lock().await;
+        if self.replay(&mut queue).await.is_ok() {
+            match self.store_now(memory).await {
+                Err(StorageError::Unavailable(e)) => tracing::warn!("{}; queueing the memory until ChromaDB is back", e),
+                result => return result,
+            }
+        } else if let Some(WalEntry::Store { memory: queued, .. }) = queue.latest(&memory.content_hash) {
+            return duplicate_or_conflict(memory, &queued.content);
         }
-        
-        Ok((true, format!("Successfully stored memory with hash: {}", memory.content_hash)))
+
+        let queued = Memory { embedding: Some(self.embedding(memory).await?), ..memory.clone() };
+        queue.push(WalEntry::store(&queued))?;
+        Ok(StoreOutcome::Queued)
     }
 
-    async fn retrieve(&self, query_embedding: &Vec<f32>, n_results: usize) -> Result<Vec<MemoryQueryResult>> {
+    async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>> {
         // Query ChromaDB
-        let query_url = self.base_url.join(&format!("/api/v1/collections/{}/query", self.collection_name))?;
+        let query_url = self.collection_url("query")?;
         
-        let response = self.client.post(query_url)
-            .json(&serde_json::json!({
-                "query_embeddings": [query_embedding],
-                "n_results": n_results,
-                "include": ["metadatas", "documents", "embeddings", "distances"]
-            }))
-            .send()
-            .await
-            .context("Failed to query memories")?;
-            
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Failed to query memories: {}", response.status()));
-        }
+        self.replay_pending().await;
+        let body = serde_json::json!({
+            "query_embeddings": [query_embedding],
+            "n_results": n_results,
+            "include": ["metadatas", "documents", "embeddings", "distances"]
+        });
+        let response = self.post(query_url, &body, "Failed to query memories").await?;
         
         let result: serde_json::Value = response.json().await
             .context("Failed to parse query response")?;
@@ -292,8 +622,13 @@ impl MemoryStorage for ChromaMemoryStorage {
                     // Parse memory
                     let memory = self.parse_metadata(id, document, &metadata_map, embedding)?;
                     
-                    // Calculate relevance score (1 - distance for cosine similarity)
-                    let relevance_score = 1.0 - distance as f32;
+                    // Score with the cosine similarity, like the other backends, whatever
+                    // distance the collection was created with; 1 - distance is that
+                    // similarity only for collections using the cosine distance
+                    let relevance_score = match &memory.embedding {
+                        Some(embedding) => cosine_similarity(query_embedding, embedding),
+                        None => 1.0 - distance as f32,
+                    };
                     
                     results.push(MemoryQueryResult {
                         memory,
@@ -302,25 +637,28 @@ impl MemoryStorage for ChromaMemoryStorage {
                 }
             }
         }
-        
+
+        // Chroma ranks by the collection's distance, which can disagree with the scores
+        results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
         Ok(results)
     }
 
-    async fn search_by_tag(&self, tags: &[String]) -> Result<Vec<Memory>> {
+    async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>> {
+        if let Some(queued) = self.queued(content_hash).await {
+            return Ok(queued);
+        }
+        let memories = self.get_matching(serde_json::json!({ "ids": [content_hash] })).await?;
+        Ok(memories.into_iter().next())
+    }
+
+    async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>> {
         if tags.is_empty() {
             return Ok(Vec::new());
         }
         
-        // Build where filter for tags
+        // Build where filter for tags, matching the flag of each tag
         let tag_conditions: Vec<serde_json::Value> = tags.iter()
-            .map(|tag| {
-                serde_json::json!({
-                    "$contains": {
-                        "path": "tags",
-                        "value": tag
-                    }
-                })
-            })
+            .map(|tag| serde_json::json!({ format!("{}{}", TAG_PREFIX, tag): { "$eq": true } }))
             .collect();
             
         let where_filter = if tag_conditions.len() == 1 {
@@ -331,81 +669,508 @@ impl MemoryStorage for ChromaMemoryStorage {
             })
         };
         
-        // Query ChromaDB
-        let get_url = self.base_url.join(&format!("/api/v1/collections/{}/get", self.collection_name))?;
-        
-        let response = self.client.post(get_url)
-            .json(&serde_json::json!({
-                "where": where_filter,
-                "include": ["metadatas", "documents", "embeddings"]
-            }))
-            .send()
-            .await
-            .context("Failed to search by tags")?;
-            
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Failed to search by tags: {}", response.status()));
+        Ok(self.get_where(where_filter).await?)
+    }
+
+    async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>> {
+        check_time_range(start_seconds, end_seconds)?;
+        let where_filter = serde_json::json!({
+            "$and": [
+                { "timestamp_seconds": { "$gte": start_seconds } },
+                { "timestamp_seconds": { "$lte": end_seconds } }
+            ]
+        });
+
+        Ok(self.get_where(where_filter).await?)
+    }
+
+    async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+        let Some(queue) = &self.queue else {
+            return self.delete_now(content_hash).await;
+        };
+        let mut queue = queue.lock().await;
+        if self.replay(&mut queue).await.is_ok() {
+            match self.delete_now(content_hash).await {
+                Err(StorageError::Unavailable(e)) => tracing::warn!("{}; queueing the deletion until ChromaDB is back", e),
+                result => return result,
+            }
+        } else if matches!(queue.latest(content_hash), Some(WalEntry::Delete { .. })) {
+            return Err(StorageError::NotFound(content_hash.to_string()));
         }
-        
-        let result: serde_json::Value = response.json().await
-            .context("Failed to parse tag search response")?;
-            
-        // Process results
-        let ids = result.get("ids").and_then(|ids| ids.as_array());
-        let documents = result.get("documents").and_then(|docs| docs.as_array());
-        let metadatas = result.get("metadatas").and_then(|meta| meta.as_array());
-        let embeddings = result.get("embeddings").and_then(|emb| emb.as_array());
-        
-        let mut memories = Vec::new();
-        
-        if let (Some(ids), Some(documents), Some(metadatas)) = (ids, documents, metadatas) {
-            for i in 0..ids.len() {
-                if let (Some(id), Some(document), Some(metadata)) = (
-                    ids.get(i).and_then(|v| v.as_str()),
-                    documents.get(i).and_then(|v| v.as_str()),
-                    metadatas.get(i).and_then(|v| v.as_object()),
-                ) {
-                    // Convert metadata to HashMap
-                    let metadata_map: HashMap<String, serde_json::Value> = metadata.iter()
-                        .map(|(k, v)| (k.clone(), v.clone()))
-                        .collect();
-                    
-                    // Extract embedding if available
-                    let embedding = embeddings.and_then(|embs| embs.get(i))
-                        .and_then(|emb| emb.as_array())
-                        .map(|arr| arr.iter().filter_map(|v| v.as_f64().map(|d| d as f32)).collect::<Vec<f32>>());
-                    
-                    // Parse memory
-                    let memory = self.parse_metadata(id, document, &metadata_map, embedding)?;
-                    memories.push(memory);
+
+        queue.push(WalEntry::Delete { content_hash: content_hash.to_string() })?;
+        Ok(DeleteOutcome::Queued)
+    }
+
+    async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage> {
+        // Chroma pages by offset, which stays stable as long as the collection isn't modified
+        let offset: usize = match cursor {
+            Some(cursor) => cursor.parse().map_err(|_| StorageError::InvalidFilter(format!("Invalid ChromaDB list cursor: {}", cursor)))?,
+            None => 0,
+        };
+        let memories = self.get_matching(serde_json::json!({ "limit": limit, "offset": offset })).await?;
+        let next_cursor = (memories.len() >= limit && limit > 0).then(|| (offset + memories.len()).to_string());
+        Ok(MemoryPage { memories, next_cursor })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::storage::conformance::{check_conformance, memory, EMBEDDING_SIZE};
+    use axum::{
+        extract::{Path, Request, State},
+        http::{HeaderMap, StatusCode},
+        middleware::{self, Next},
+        response::{IntoResponse, Response},
+        routing::{get, post},
+        Json, Router,
+    };
+    use serde_json::{json, Map, Value};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    /// Minimal in-process stand-in for the parts of the Chroma v2 and v1 REST
+    /// APIs the storage uses. Like Chroma, it only accepts scalar metadata
+    /// values and the documented `where` operators, and ranks by the
+    /// collection's distance.
+    #[derive(Default)]
+    struct FakeChroma {
+        collections: Vec<FakeCollection>,
+        // While set, every request fails as if the server were overloaded
+        down: bool,
+        // While set, the v2 API is missing, as on servers older than Chroma 0.6
+        v1_only: bool,
+        // How long every request takes to be answered
+        delay: Duration,
+        // Headers of every request received, oldest first
+        requests: Vec<HeaderMap>,
+    }
+
+    struct FakeCollection {
+        id: String,
+        name: String,
+        metadata: Value,
+        // Records in insertion order, which is how Chroma pages them
+        records: Vec<Record>,
+    }
+
+    struct Record {
+        id: String,
+        embedding: Vec<f32>,
+        metadata: Map<String, Value>,
+        document: String,
+    }
+
+    type Shared = Arc<Mutex<FakeChroma>>;
+    type Reply = (StatusCode, Json<Value>);
+
+    fn bad_request(message: String) -> Reply {
+        (StatusCode::BAD_REQUEST, Json(json!({ "error": "InvalidArgumentError", "message": message })))
+    }
+
+    fn is_scalar(value: &Value) -> bool {
+        matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_))
+    }
+
+    // Whether a record's metadata matches a `where` filter, or why the filter is invalid
+    fn matches(filter: &Value, metadata: &Map<String, Value>) -> Result<bool, String> {
+        let Some((key, condition)) = filter.as_object().filter(|filter| filter.len() == 1).and_then(|filter| filter.iter().next()) else {
+            return Err(format!("Expected a where filter with a single key, got {}", filter));
+        };
+        if key == "$and" || key == "$or" {
+            let operands = condition.as_array().filter(|operands| operands.len() >= 2)
+                .ok_or_else(|| format!("Expected {} to hold a list of at least two filters", key))?;
+            let results = operands.iter().map(|operand| matches(operand, metadata)).collect::<Result<Vec<_>, _>>()?;
+            return Ok(if key == "$and" { results.iter().all(|&r| r) } else { results.iter().any(|&r| r) });
+        }
+        if key.starts_with('$') {
+            return Err(format!("Unknown where operator {}", key));
+        }
+
+        let (operator, operand) = match condition.as_object() {
+            Some(condition) if condition.len() == 1 => condition.iter().next().map(|(operator, operand)| (operator.as_str(), operand)).unwrap(),
+            Some(_) => return Err(format!("Expected a single operator for {}", key)),
+            None => ("$eq", condition),
+        };
+        let Some(value) = metadata.get(key) else {
+            return Ok(false);
+        };
+        match operator {
+            "$eq" | "$ne" if is_scalar(operand) => Ok((value == operand) == (operator == "$eq")),
+            "$gt" | "$gte" | "$lt" | "$lte" => {
+                let operand = operand.as_f64().ok_or_else(|| format!("{} needs a number", operator))?;
+                let Some(value) = value.as_f64() else {
+                    return Ok(false);
+                };
+                Ok(match operator {
+                    "$gt" => value > operand,
+                    "$gte" => value >= operand,
+                    "$lt" => value < operand,
+                    _ => value <= operand,
+                })
+            }
+            "$in" | "$nin" if operand.as_array().is_some_and(|operands| operands.iter().all(is_scalar)) => {
+                Ok(operand.as_array().unwrap().contains(value) == (operator == "$in"))
+            }
+            _ => Err(format!("Invalid where condition {} on {}", condition, key)),
+        }
+    }
+
+    fn distance(space: &str, a: &[f32], b: &[f32]) -> f32 {
+        match space {
+            "cosine" => 1.0 - cosine_similarity(a, b),
+            "ip" => 1.0 - a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>(),
+            _ => a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum(),
+        }
+    }
+
+    // The requested fields of some records, each in a list, with the fields not asked for null
+    fn columns(records: &[&Record], include: &Value) -> Map<String, Value> {
+        let included = |field: &str| include.as_array().is_some_and(|include| include.contains(&json!(field)));
+        let column = |field: &str, value: fn(&Record) -> Value| match included(field) {
+            true => Value::Array(records.iter().map(|record| value(record)).collect()),
+            false => Value::Null,
+        };
+        let mut columns = Map::new();
+        columns.insert("ids".to_string(), records.iter().map(|record| json!(record.id)).collect());
+        columns.insert("documents".to_string(), column("documents", |record| json!(record.document)));
+        columns.insert("metadatas".to_string(), column("metadatas", |record| Value::Object(record.metadata.clone())));
+        columns.insert("embeddings".to_string(), column("embeddings", |record| json!(record.embedding)));
+        columns
+    }
+
+    fn with_collection(state: &Shared, id: &str, operation: impl FnOnce(&mut FakeCollection) -> Result<Value, Reply>) -> Reply {
+        let mut state = state.lock().unwrap();
+        let Some(collection) = state.collections.iter_mut().find(|collection| collection.id == id) else {
+            return (StatusCode::NOT_FOUND, Json(json!({ "error": "NotFoundError", "message": format!("Collection {} does not exist", id) })));
+        };
+        match operation(collection) {
+            Ok(body) => (StatusCode::OK, Json(body)),
+            Err(reply) => reply,
+        }
+    }
+
+    async fn heartbeat() -> Json<Value> {
+        Json(json!({ "nanosecond heartbeat": 1 }))
+    }
+
+    async fn create_collection(State(state): State<Shared>, Json(body): Json<Value>) -> Reply {
+        let mut state = state.lock().unwrap();
+        let name = body["name"].as_str().unwrap().to_string();
+        let collection = match state.collections.iter().position(|collection| collection.name == name) {
+            Some(_) if body["get_or_create"] != json!(true) => {
+                return (StatusCode::CONFLICT, Json(json!({ "error": "UniqueConstraintError", "message": format!("Collection {} already exists", name) })));
+            }
+            // The metadata of an existing collection is left as it is
+            Some(index) => &state.collections[index],
+            None => {
+                let id = format!("00000000-0000-0000-0000-{:012}", state.collections.len());
+                state.collections.push(FakeCollection { id, name, metadata: body["metadata"].clone(), records: Vec::new() });
+                state.collections.last().unwrap()
+            }
+        };
+        (StatusCode::OK, Json(json!({ "id": collection.id, "name": collection.name, "metadata": collection.metadata })))
+    }
+
+    async fn upsert(State(state): State<Shared>, Path(params): Path<HashMap<String, String>>, Json(body): Json<Value>) -> Reply {
+        with_collection(&state, &params["id"], |collection| {
+            let ids = body["ids"].as_array().unwrap();
+            for (i, id) in ids.iter().enumerate() {
+                let metadata = body["metadatas"][i].as_object().cloned().unwrap_or_default();
+                if let Some((key, _)) = metadata.iter().find(|(_, value)| !is_scalar(value)) {
+                    return Err(bad_request(format!("Expected metadata value of {} to be a str, int, float or bool", key)));
+                }
+                let record = Record {
+                    id: id.as_str().unwrap().to_string(),
+                    embedding: serde_json::from_value(body["embeddings"][i].clone()).unwrap(),
+                    metadata,
+                    document: body["documents"][i].as_str().unwrap_or_default().to_string(),
+                };
+                if let Some(other) = collection.records.iter().find(|other| other.embedding.len() != record.embedding.len()) {
+                    return Err(bad_request(format!("Embedding dimension {} does not match collection dimensionality {}", record.embedding.len(), other.embedding.len())));
+                }
+                match collection.records.iter_mut().find(|existing| existing.id == record.id) {
+                    Some(existing) => *existing = record,
+                    None => collection.records.push(record),
                 }
             }
+            Ok(Value::Null)
+        })
+    }
+
+    async fn get_records(State(state): State<Shared>, Path(params): Path<HashMap<String, String>>, Json(body): Json<Value>) -> Reply {
+        with_collection(&state, &params["id"], |collection| {
+            let mut selected = Vec::new();
+            for record in &collection.records {
+                let selected_id = body["ids"].as_array().is_none_or(|ids| ids.contains(&json!(record.id)));
+                if selected_id && (body["where"].is_null() || matches(&body["where"], &record.metadata).map_err(bad_request)?) {
+                    selected.push(record);
+                }
+            }
+            let offset = body["offset"].as_u64().unwrap_or(0) as usize;
+            let limit = body["limit"].as_u64().map_or(usize::MAX, |limit| limit as usize);
+            let page: Vec<&Record> = selected.into_iter().skip(offset).take(limit).collect();
+            let include = body.get("include").cloned().unwrap_or(json!(["metadatas", "documents"]));
+            Ok(Value::Object(columns(&page, &include)))
+        })
+    }
+
+    async fn query(State(state): State<Shared>, Path(params): Path<HashMap<String, String>>, Json(body): Json<Value>) -> Reply {
+        with_collection(&state, &params["id"], |collection| {
+            let query: Vec<f32> = serde_json::from_value(body["query_embeddings"][0].clone()).unwrap();
+            let space = collection.metadata["hnsw:space"].as_str().unwrap_or("l2");
+            let mut nearest: Vec<(f32, &Record)> = collection.records.iter()
+                .map(|record| (distance(space, &query, &record.embedding), record))
+                .collect();
+            nearest.sort_by(|a, b| a.0.total_cmp(&b.0));
+            nearest.truncate(body["n_results"].as_u64().unwrap_or(10) as usize);
+
+            let include = body.get("include").cloned().unwrap_or(json!(["metadatas", "documents", "distances"]));
+            let records: Vec<&Record> = nearest.iter().map(|(_, record)| *record).collect();
+            let mut result = columns(&records, &include);
+            if include.as_array().is_some_and(|include| include.contains(&json!("distances"))) {
+                result.insert("distances".to_string(), nearest.iter().map(|(distance, _)| json!(distance)).collect());
+            }
+            // Query results come in one list per query embedding
+            for column in result.values_mut().filter(|column| !column.is_null()) {
+                *column = json!([column]);
+            }
+            Ok(Value::Object(result))
+        })
+    }
+
+    async fn delete(State(state): State<Shared>, Path(params): Path<HashMap<String, String>>, Json(body): Json<Value>) -> Reply {
+        with_collection(&state, &params["id"], |collection| {
+            let ids = body["ids"].as_array().unwrap();
+            collection.records.retain(|record| !ids.contains(&json!(record.id)));
+            Ok(Value::Null)
+        })
+    }
+
+    // Record each request, then stage slow answers and outages, and hide the
+    // v2 API from servers pretending to be older
+    async fn stage(State(state): State<Shared>, request: Request, next: Next) -> Response {
+        let (down, v1_only, delay) = {
+            let mut state = state.lock().unwrap();
+            state.requests.push(request.headers().clone());
+            (state.down, state.v1_only, state.delay)
+        };
+        tokio::time::sleep(delay).await;
+        if down {
+            return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({ "error": "Service Unavailable" }))).into_response();
         }
-        
-        Ok(memories)
+        if v1_only && request.uri().path().starts_with("/api/v2/") {
+            return (StatusCode::NOT_FOUND, Json(json!({ "detail": "Not Found" }))).into_response();
+        }
+        next.run(request).await
     }
 
-    async fn delete(&self, content_hash: &str) -> Result<(bool, String)> {
-        // Check if memory exists
-        if !self.check_duplicate_exists(content_hash).await? {
-            return Ok((false, format!("No memory found with hash: {}", content_hash)));
+    async fn start_fake_chroma() -> (Url, Shared) {
+        let state = Shared::default();
+        let mut router = Router::new();
+        // v1 takes the tenant and database as query parameters, which the fake ignores like the v2 path segments
+        for (heartbeat_path, collections) in [
+            ("/api/v2/heartbeat", "/api/v2/tenants/{tenant}/databases/{database}/collections"),
+            ("/api/v1/heartbeat", "/api/v1/collections"),
+        ] {
+            router = router
+                .route(heartbeat_path, get(heartbeat))
+                .route(collections, post(create_collection))
+                .route(&format!("{}/{{id}}/upsert", collections), post(upsert))
+                .route(&format!("{}/{{id}}/get", collections), post(get_records))
+                .route(&format!("{}/{{id}}/query", collections), post(query))
+                .route(&format!("{}/{{id}}/delete", collections), post(delete));
         }
-        
-        // Delete from ChromaDB
-        let delete_url = self.base_url.join(&format!("/api/v1/collections/{}/delete", self.collection_name))?;
-        
-        let response = self.client.post(delete_url)
-            .json(&serde_json::json!({
-                "ids": [content_hash]
-            }))
-            .send()
-            .await
-            .context("Failed to delete memory")?;
-            
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!("Failed to delete memory: {}", response.status()));
+        let router = router
+            .layer(middleware::from_fn_with_state(state.clone(), stage))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        (Url::parse(&format!("http://{}", addr)).unwrap(), state)
+    }
+
+    fn options(base_url: &Url, collection_name: &str) -> ChromaOptions {
+        ChromaOptions {
+            base_url: base_url.clone(),
+            collection_name: collection_name.to_string(),
+            tenant: DEFAULT_TENANT.to_string(),
+            database: DEFAULT_DATABASE.to_string(),
+            auth: ChromaAuth::None,
+            tls: ChromaTls::default(),
+            timeout: DEFAULT_TIMEOUT,
+            // Fail fast, without the breaker getting in the way of outages staged by the tests
+            retry: RetryPolicy { max_retries: 0, breaker_threshold: 0, ..Default::default() },
+            queue_dir: None,
         }
-        
-        Ok((true, format!("Successfully deleted memory with hash: {}", content_hash)))
+    }
+
+    async fn storage(options: ChromaOptions) -> ChromaMemoryStorage {
+        ChromaMemoryStorage::new(options, Arc::new(DummyEmbeddingGenerator::new(EMBEDDING_SIZE))).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn conforms() {
+        let (url, _state) = start_fake_chroma().await;
+        let opened = AtomicUsize::new(0);
+        check_conformance(|| async { storage(options(&url, &format!("conformance-{}", opened.fetch_add(1, Ordering::SeqCst)))).await }).await;
+    }
+
+    #[tokio::test]
+    async fn conforms_over_the_v1_api() {
+        let (url, state) = start_fake_chroma().await;
+        state.lock().unwrap().v1_only = true;
+        let opened = AtomicUsize::new(0);
+        check_conformance(|| async {
+            let storage = storage(options(&url, &format!("conformance-{}", opened.fetch_add(1, Ordering::SeqCst)))).await;
+            assert_eq!(storage.api_version, ApiVersion::V1);
+            storage
+        })
+        .await;
+    }
+
+    // Headers of the requests sent while opening a storage and storing a memory with the given credentials
+    async fn headers_sent(auth: ChromaAuth) -> Vec<HeaderMap> {
+        let (url, state) = start_fake_chroma().await;
+        let storage = storage(ChromaOptions { auth, ..options(&url, "auth") }).await;
+        storage.store(&memory("authenticated", &[], 1, &[1.0, 0.0, 0.0, 0.0])).await.unwrap();
+        let requests = std::mem::take(&mut state.lock().unwrap().requests);
+        assert!(requests.len() >= 3);
+        requests
+    }
+
+    fn header<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+        headers.get(name).map(|value| value.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn sends_no_credentials_by_default() {
+        for headers in headers_sent(ChromaAuth::None).await {
+            assert_eq!(header(&headers, "authorization"), None);
+            assert_eq!(header(&headers, "x-chroma-token"), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_a_bearer_token() {
+        for headers in headers_sent(ChromaAuth::Bearer(Secret::new("s3cret".to_string()))).await {
+            assert_eq!(header(&headers, "authorization"), Some("Bearer s3cret"));
+            assert_eq!(header(&headers, "x-chroma-token"), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_a_chroma_token() {
+        for headers in headers_sent(ChromaAuth::ChromaToken(Secret::new("s3cret".to_string()))).await {
+            assert_eq!(header(&headers, "x-chroma-token"), Some("s3cret"));
+            assert_eq!(header(&headers, "authorization"), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_basic_credentials() {
+        let auth = ChromaAuth::Basic { username: "admin".to_string(), password: Secret::new("s3cret".to_string()) };
+        for headers in headers_sent(auth).await {
+            // base64 of "admin:s3cret"
+            assert_eq!(header(&headers, "authorization"), Some("Basic YWRtaW46czNjcmV0"));
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_time_out() {
+        let (url, state) = start_fake_chroma().await;
+        let storage = storage(ChromaOptions { timeout: Duration::from_millis(100), ..options(&url, "slow") }).await;
+        state.lock().unwrap().delay = Duration::from_secs(5);
+        let started = std::time::Instant::now();
+        assert!(matches!(storage.store(&memory("slow", &[], 1, &[1.0, 0.0, 0.0, 0.0])).await, Err(StorageError::Unavailable(_))));
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn tls_files_are_checked_when_the_client_is_built() {
+        let url = Url::parse("https://localhost:8000").unwrap();
+        let missing = std::env::temp_dir().join(format!("chroma-missing-{}.pem", std::process::id()));
+        let with_tls = |tls: ChromaTls| ChromaOptions { tls, ..options(&url, "tls") };
+
+        let error = ChromaMemoryStorage::build_client(&with_tls(ChromaTls { ca_cert: Some(missing.clone()), ..Default::default() })).unwrap_err();
+        assert!(error.to_string().contains("CA certificate"), "{}", error);
+        // A client certificate is useless without its key
+        let error = ChromaMemoryStorage::build_client(&with_tls(ChromaTls { client_cert: Some(missing), ..Default::default() })).unwrap_err();
+        assert!(error.to_string().contains("needs both"), "{}", error);
+        assert!(ChromaMemoryStorage::build_client(&with_tls(ChromaTls::default())).is_ok());
+    }
+
+    #[tokio::test]
+    async fn scores_are_cosine_similarity_whatever_the_collection_distance() {
+        let (url, state) = start_fake_chroma().await;
+        for space in ["l2", "ip"] {
+            // A collection created elsewhere, which keeps its distance
+            state.lock().unwrap().collections.push(FakeCollection {
+                id: format!("collection-{}", space),
+                name: space.to_string(),
+                metadata: json!({ "hnsw:space": space }),
+                records: Vec::new(),
+            });
+            let storage = storage(options(&url, space)).await;
+            storage.store(&memory("long", &[], 1, &[4.0, 0.0, 0.0, 0.0])).await.unwrap();
+            storage.store(&memory("short", &[], 1, &[0.0, 0.5, 0.0, 0.0])).await.unwrap();
+            storage.store(&memory("both", &[], 1, &[1.0, 1.0, 0.0, 0.0])).await.unwrap();
+
+            let query = vec![1.0, 0.2, 0.0, 0.0];
+            let results = storage.retrieve(&query, 3).await.unwrap();
+            let contents: Vec<&str> = results.iter().map(|result| result.memory.content.as_str()).collect();
+            assert_eq!(contents, ["long", "both", "short"], "{}", space);
+            for result in &results {
+                let expected = cosine_similarity(&query, result.memory.embedding.as_ref().unwrap());
+                assert!((result.relevance_score - expected).abs() < 1e-6, "{}", space);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn tags_are_stored_as_scalar_metadata() {
+        let (url, state) = start_fake_chroma().await;
+        let storage = storage(options(&url, "tags")).await;
+        storage.store(&memory("tagged", &["a,b", "c"], 1, &[1.0, 0.0, 0.0, 0.0])).await.unwrap();
+
+        let state = state.lock().unwrap();
+        let metadata = &state.collections[0].records[0].metadata;
+        assert_eq!(metadata["tags"], json!(r#"["a,b","c"]"#));
+        assert_eq!(metadata["tag_a,b"], json!(true));
+        assert_eq!(metadata["tag_c"], json!(true));
+    }
+
+    #[tokio::test]
+    async fn queues_writes_while_chroma_is_down_and_replays_them() {
+        let (url, state) = start_fake_chroma().await;
+        let queue_dir = std::env::temp_dir().join(format!("chroma-queue-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&queue_dir);
+        let storage = storage(ChromaOptions { queue_dir: Some(queue_dir.clone()), ..options(&url, "queued") }).await;
+        let kept = memory("kept", &["a"], 1, &[1.0, 0.0, 0.0, 0.0]);
+        let added = memory("added", &["a"], 1, &[0.0, 1.0, 0.0, 0.0]);
+        storage.store(&kept).await.unwrap();
+
+        state.lock().unwrap().down = true;
+        assert_eq!(storage.store(&added).await.unwrap(), StoreOutcome::Queued);
+        assert_eq!(storage.store(&added).await.unwrap(), StoreOutcome::Duplicate);
+        assert_eq!(storage.delete(&kept.content_hash).await.unwrap(), DeleteOutcome::Queued);
+        assert!(matches!(storage.delete(&kept.content_hash).await, Err(StorageError::NotFound(_))));
+        // Reads of single memories see the queued writes
+        assert!(storage.get(&added.content_hash).await.unwrap().is_some());
+        assert!(!storage.check_duplicate_exists(&kept.content_hash).await.unwrap());
+
+        // The next read once the server is back replays them, in order
+        state.lock().unwrap().down = false;
+        let found = storage.search_by_tag(&["a".to_string()]).await.unwrap();
+        assert_eq!(found.iter().map(|memory| memory.content.as_str()).collect::<Vec<_>>(), ["added"]);
+        let ids: Vec<String> = state.lock().unwrap().collections[0].records.iter().map(|record| record.id.clone()).collect();
+        assert_eq!(ids, [added.content_hash]);
+        std::fs::remove_dir_all(&queue_dir).unwrap();
     }
 }
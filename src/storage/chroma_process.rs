@@ -0,0 +1,320 @@
+use anyhow::{Context, Result};
+use reqwest::Client;
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+use url::Url;
+
+/// How long a freshly started Chroma server gets to answer its heartbeat
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(250);
+/// How long Chroma gets to exit after SIGTERM before it is killed
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+// Ports tried at startup, in case another process takes the free port first
+const PORT_ATTEMPTS: usize = 3;
+// Delay before restarting a crashed server, doubled after every failed restart
+const INITIAL_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(30);
+
+/// A local Chroma server (`chroma run --path <path>`) running as a supervised
+/// child process. It is restarted on the same port if it crashes, since its
+/// URL is already in use, and must be stopped with [`ChromaProcess::shutdown`].
+pub struct ChromaProcess {
+    url: Url,
+    ct: CancellationToken,
+    supervisor: JoinHandle<()>,
+}
+
+impl ChromaProcess {
+    /// Start Chroma on a free local port and wait until it answers its heartbeat.
+    /// If another process takes the port before Chroma binds it, another port
+    /// is tried.
+    pub async fn start(command: &str, path: &Path) -> Result<Self> {
+        let mut attempt = 1;
+        let (launcher, child) = loop {
+            let port = free_port()?;
+            let launcher = Launcher {
+                command: command.to_string(),
+                path: path.to_path_buf(),
+                port,
+                url: Url::parse(&format!("http://127.0.0.1:{}", port))?,
+                client: Client::builder().timeout(HEARTBEAT_INTERVAL * 4).build()?,
+            };
+            match launcher.start().await {
+                Ok(child) => break (launcher, child),
+                Err(e) if attempt < PORT_ATTEMPTS && port_in_use(port) => {
+                    tracing::warn!("Port {} was taken before ChromaDB could listen on it ({}); trying another", port, e);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        tracing::info!("Started local ChromaDB at {} with data in {:?}", launcher.url, launcher.path);
+
+        let url = launcher.url.clone();
+        let ct = CancellationToken::new();
+        let supervisor = tokio::spawn(supervise(launcher, child, ct.clone()));
+
+        Ok(Self { url, ct, supervisor })
+    }
+
+    /// URL the server listens on
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Stop the server, giving it time to shut down cleanly
+    pub async fn shutdown(self) {
+        self.ct.cancel();
+        let _ = self.supervisor.await;
+    }
+}
+
+// Ask the OS for a port nobody is listening on. It is free again once this
+// returns, so another process may take it before Chroma does.
+fn free_port() -> Result<u16> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").context("Failed to find a free port for ChromaDB")?;
+    Ok(listener.local_addr()?.port())
+}
+
+fn port_in_use(port: u16) -> bool {
+    matches!(std::net::TcpListener::bind(("127.0.0.1", port)), Err(e) if e.kind() == std::io::ErrorKind::AddrInUse)
+}
+
+struct Launcher {
+    command: String,
+    path: PathBuf,
+    port: u16,
+    url: Url,
+    client: Client,
+}
+
+impl Launcher {
+    /// Spawn the server and wait until it is ready
+    async fn start(&self) -> Result<Child> {
+        let mut child = Command::new(&self.command)
+            .arg("run")
+            .arg("--path")
+            .arg(&self.path)
+            .arg("--host")
+            .arg("127.0.0.1")
+            .arg("--port")
+            .arg(self.port.to_string())
+            // Stdout may be the MCP transport, so Chroma's output goes to the log instead
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .context(format!("Failed to start '{} run'; is ChromaDB installed?", self.command))?;
+
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(forward_output(stdout));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(forward_output(stderr));
+        }
+
+        if let Err(e) = self.wait_until_ready(&mut child).await {
+            let _ = child.kill().await;
+            return Err(e);
+        }
+        Ok(child)
+    }
+
+    // Ready once the heartbeat has been answered twice in a row with the child
+    // still running in between. Another process listening on the port would
+    // answer too, while the child exits as it fails to bind.
+    async fn wait_until_ready(&self, child: &mut Child) -> Result<()> {
+        let started = Instant::now();
+        let mut answered = false;
+        while started.elapsed() < STARTUP_TIMEOUT {
+            if let Some(status) = child.try_wait()? {
+                anyhow::bail!("ChromaDB exited with {} before it became ready", status);
+            }
+            if self.heartbeat().await {
+                if answered {
+                    return Ok(());
+                }
+                answered = true;
+            } else {
+                answered = false;
+            }
+            tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+        }
+        anyhow::bail!("ChromaDB did not answer its heartbeat within {:?}", STARTUP_TIMEOUT)
+    }
+
+    async fn heartbeat(&self) -> bool {
+        for path in ["/api/v2/heartbeat", "/api/v1/heartbeat"] {
+            let Ok(url) = self.url.join(path) else { continue };
+            if let Ok(response) = self.client.get(url).send().await
+                && response.status().is_success()
+            {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+async fn forward_output(output: impl AsyncRead + Unpin) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        tracing::debug!("chroma: {}", line);
+    }
+}
+
+/// Restart the server whenever it exits, until cancelled
+async fn supervise(launcher: Launcher, mut child: Child, ct: CancellationToken) {
+    loop {
+        tokio::select! {
+            status = child.wait() => {
+                match status {
+                    Ok(status) => tracing::warn!("Local ChromaDB exited with {}; restarting", status),
+                    Err(e) => tracing::warn!("Lost track of local ChromaDB process: {}; restarting", e),
+                }
+
+                let mut delay = INITIAL_RESTART_DELAY;
+                loop {
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = ct.cancelled() => return,
+                    }
+                    match launcher.start().await {
+                        Ok(restarted) => {
+                            tracing::info!("Restarted local ChromaDB at {}", launcher.url);
+                            child = restarted;
+                            break;
+                        }
+                        Err(e) => {
+                            delay = (delay * 2).min(MAX_RESTART_DELAY);
+                            tracing::error!("Failed to restart local ChromaDB: {}; retrying in {:?}", e, delay);
+                        }
+                    }
+                }
+            }
+            _ = ct.cancelled() => {
+                terminate(&mut child).await;
+                tracing::info!("Stopped local ChromaDB");
+                return;
+            }
+        }
+    }
+}
+
+/// Ask the server to exit, killing it if it doesn't within the timeout
+async fn terminate(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: sending a signal to our own child process has no memory safety implications
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGTERM);
+        }
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, child.wait()).await.is_ok() {
+            return;
+        }
+        tracing::warn!("Local ChromaDB did not exit within {:?}; killing it", SHUTDOWN_TIMEOUT);
+    }
+    let _ = child.kill().await;
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use std::os::unix::fs::PermissionsExt;
+
+    // Set for the test binary when it runs as the fake Chroma server
+    const FAKE_PORT_VAR: &str = "FAKE_CHROMA_PORT";
+
+    /// The fake Chroma server: answers heartbeats on the given port until it
+    /// is killed. Only does anything when started by `fake_chroma`.
+    #[tokio::test]
+    #[ignore]
+    async fn fake_chroma_server() {
+        let Ok(port) = std::env::var(FAKE_PORT_VAR) else { return };
+        let router = Router::new().route("/api/v2/heartbeat", get(|| async { "{}" }));
+        let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", port)).await.unwrap();
+        axum::serve(listener, router).await.unwrap();
+    }
+
+    // A `chroma` command that starts this test binary as the fake server,
+    // recording the pid of every server started in `pids`
+    fn fake_chroma(dir: &Path) -> PathBuf {
+        let command = dir.join("chroma");
+        let script = format!(
+            "#!/bin/sh\n# run --path <path> --host <host> --port <port>\necho $$ >> {:?}\n{}=\"$7\" exec {:?} storage::chroma_process::tests::fake_chroma_server --exact --ignored\n",
+            dir.join("pids"),
+            FAKE_PORT_VAR,
+            std::env::current_exe().unwrap()
+        );
+        std::fs::write(&command, script).unwrap();
+        std::fs::set_permissions(&command, std::fs::Permissions::from_mode(0o755)).unwrap();
+        command
+    }
+
+    fn pids(dir: &Path) -> Vec<i32> {
+        std::fs::read_to_string(dir.join("pids")).unwrap_or_default().lines().filter_map(|pid| pid.parse().ok()).collect()
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chroma-process-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn answers(url: &Url) -> bool {
+        let client = Client::builder().timeout(HEARTBEAT_INTERVAL * 4).build().unwrap();
+        client.get(url.join("/api/v2/heartbeat").unwrap()).send().await.is_ok_and(|response| response.status().is_success())
+    }
+
+    #[tokio::test]
+    async fn restarts_a_crashed_server_on_the_same_port() {
+        let dir = test_dir("restart");
+        let process = ChromaProcess::start(fake_chroma(&dir).to_str().unwrap(), &dir).await.unwrap();
+        let url = process.url().clone();
+        assert!(answers(&url).await);
+
+        let first = pids(&dir)[0];
+        // SAFETY: sending a signal to our own child process has no memory safety implications
+        unsafe {
+            libc::kill(first, libc::SIGKILL);
+        }
+        let restarted = async {
+            while pids(&dir).len() < 2 || !answers(&url).await {
+                tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(30), restarted).await.expect("server was not restarted");
+
+        process.shutdown().await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_the_server() {
+        let dir = test_dir("shutdown");
+        let process = ChromaProcess::start(fake_chroma(&dir).to_str().unwrap(), &dir).await.unwrap();
+        let url = process.url().clone();
+        assert!(answers(&url).await);
+
+        process.shutdown().await;
+        assert!(!answers(&url).await);
+        // The server has exited and been reaped, so its pid is gone
+        let pid = pids(&dir)[0];
+        // SAFETY: signal 0 only checks whether the process exists
+        assert_eq!(unsafe { libc::kill(pid, 0) }, -1);
+        assert_eq!(pids(&dir).len(), 1, "server was restarted after shutdown");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
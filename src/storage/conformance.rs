@@ -0,0 +1,260 @@
+//! Behaviour every `MemoryStorage` must share, so that switching backends
+//! never changes what the server's tools return. Each backend's tests run
+//! `check_conformance` with a function opening an empty storage.
+
+use super::{cosine_similarity, DeleteOutcome, MemoryStorage, StorageError, StoreOutcome};
+use crate::models::Memory;
+use std::{collections::HashMap, future::Future};
+
+/// Dimensions of the embeddings used by the checks
+pub(super) const EMBEDDING_SIZE: usize = 4;
+
+/// A memory hashed from its content, for tests. Unembedded memories are
+/// written `Memory { embedding: None, ..memory(...) }`.
+pub(crate) fn memory(content: &str, tags: &[&str], timestamp_seconds: i64, embedding: &[f32]) -> Memory {
+    Memory {
+        content: content.to_string(),
+        content_hash: format!("hash-{}", content),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        memory_type: Some("note".to_string()),
+        timestamp_seconds,
+        metadata: HashMap::from([("source".to_string(), "conformance".to_string())]),
+        embedding: Some(embedding.to_vec()),
+    }
+}
+
+fn sorted_contents(memories: &[Memory]) -> Vec<&str> {
+    let mut contents: Vec<&str> = memories.iter().map(|memory| memory.content.as_str()).collect();
+    contents.sort_unstable();
+    contents
+}
+
+/// Run every check, each against a fresh storage from `open`
+pub(super) async fn check_conformance<S, F, Fut>(open: F)
+where
+    S: MemoryStorage,
+    F: Fn() -> Fut,
+    Fut: Future<Output = S>,
+{
+    stores_and_gets(&open().await).await;
+    rejects_duplicates(&open().await).await;
+    retrieves_by_cosine_similarity(&open().await).await;
+    searches_by_exact_tag(&open().await).await;
+    searches_by_time_range(&open().await).await;
+    deletes(&open().await).await;
+    lists_every_memory_once(&open().await).await;
+}
+
+async fn stores_and_gets(storage: &dyn MemoryStorage) {
+    let stored = memory("alpha", &["b-tag", "a-tag"], 100, &[1.0, 0.0, 0.0, 0.0]);
+    assert!(!storage.check_duplicate_exists(&stored.content_hash).await.unwrap());
+
+    assert_eq!(storage.store(&stored).await.unwrap(), StoreOutcome::Stored);
+    assert!(storage.check_duplicate_exists(&stored.content_hash).await.unwrap());
+
+    let fetched = storage.get(&stored.content_hash).await.unwrap().expect("stored memory not found");
+    assert_eq!(fetched.content, stored.content);
+    assert_eq!(fetched.content_hash, stored.content_hash);
+    // Tags keep their order
+    assert_eq!(fetched.tags, stored.tags);
+    assert_eq!(fetched.memory_type, stored.memory_type);
+    assert_eq!(fetched.timestamp_seconds, stored.timestamp_seconds);
+    assert_eq!(fetched.metadata, stored.metadata);
+    assert_eq!(fetched.embedding, stored.embedding);
+
+    assert!(storage.get("hash-missing").await.unwrap().is_none());
+    assert!(!storage.check_duplicate_exists("hash-missing").await.unwrap());
+
+    // Memories stored without an embedding get one from the embedding generator
+    let unembedded = Memory { embedding: None, ..memory("beta", &[], 100, &[0.0; EMBEDDING_SIZE]) };
+    assert_eq!(storage.store(&unembedded).await.unwrap(), StoreOutcome::Stored);
+    let fetched = storage.get(&unembedded.content_hash).await.unwrap().unwrap();
+    assert_eq!(fetched.embedding.map(|embedding| embedding.len()), Some(EMBEDDING_SIZE));
+}
+
+async fn rejects_duplicates(storage: &dyn MemoryStorage) {
+    let original = memory("original", &["a"], 1, &[1.0, 0.0, 0.0, 0.0]);
+    assert_eq!(storage.store(&original).await.unwrap(), StoreOutcome::Stored);
+    assert_eq!(storage.store(&original).await.unwrap(), StoreOutcome::Duplicate);
+
+    // Other content under the same hash is a conflict, and the first memory stored is kept
+    let clash = Memory { content: "other".to_string(), ..original.clone() };
+    assert!(matches!(storage.store(&clash).await, Err(StorageError::Conflict(_))));
+    assert_eq!(storage.get(&original.content_hash).await.unwrap().unwrap().content, "original");
+}
+
+async fn retrieves_by_cosine_similarity(storage: &dyn MemoryStorage) {
+    let stored = [
+        memory("x", &[], 1, &[1.0, 0.0, 0.0, 0.0]),
+        memory("y", &[], 2, &[0.0, 1.0, 0.0, 0.0]),
+        memory("xy", &[], 3, &[0.6, 0.8, 0.0, 0.0]),
+        memory("opposite", &[], 4, &[-1.0, 0.0, 0.0, 0.0]),
+    ];
+    for memory in &stored {
+        assert_eq!(storage.store(memory).await.unwrap(), StoreOutcome::Stored);
+    }
+
+    let query = vec![0.9, 0.3, 0.0, 0.3];
+    let results = storage.retrieve(&query, 3).await.unwrap();
+    let contents: Vec<&str> = results.iter().map(|result| result.memory.content.as_str()).collect();
+    assert_eq!(contents, ["x", "xy", "y"]);
+
+    // Scores are the cosine similarity of the query and the memory's embedding
+    for result in &results {
+        let expected = cosine_similarity(&query, result.memory.embedding.as_ref().unwrap());
+        assert!(
+            (result.relevance_score - expected).abs() < 1e-3,
+            "{}: score {} instead of {}",
+            result.memory.content,
+            result.relevance_score,
+            expected
+        );
+    }
+
+    // Asking for more results than there are memories returns them all, worst last
+    let results = storage.retrieve(&query, 10).await.unwrap();
+    assert_eq!(results.len(), stored.len());
+    assert_eq!(results.last().unwrap().memory.content, "opposite");
+    assert!(results.last().unwrap().relevance_score < 0.0);
+}
+
+async fn searches_by_exact_tag(storage: &dyn MemoryStorage) {
+    for memory in [
+        memory("work-urgent", &["work", "urgent"], 1, &[1.0, 0.0, 0.0, 0.0]),
+        memory("work", &["work"], 2, &[0.0, 1.0, 0.0, 0.0]),
+        memory("personal", &["personal"], 3, &[0.0, 0.0, 1.0, 0.0]),
+        // Tags only match whole, whatever their case
+        memory("workshop", &["workshop", "Work"], 4, &[0.0, 0.0, 0.0, 1.0]),
+        memory("untagged", &[], 5, &[0.5, 0.5, 0.5, 0.5]),
+    ] {
+        assert_eq!(storage.store(&memory).await.unwrap(), StoreOutcome::Stored);
+    }
+
+    let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+    let found = storage.search_by_tag(&tags(&["work"])).await.unwrap();
+    assert_eq!(sorted_contents(&found), ["work", "work-urgent"]);
+
+    // Several tags match memories with any of them, each memory once
+    let found = storage.search_by_tag(&tags(&["urgent", "personal", "work"])).await.unwrap();
+    assert_eq!(sorted_contents(&found), ["personal", "work", "work-urgent"]);
+
+    assert!(storage.search_by_tag(&tags(&["missing"])).await.unwrap().is_empty());
+    assert!(storage.search_by_tag(&[]).await.unwrap().is_empty());
+}
+
+async fn searches_by_time_range(storage: &dyn MemoryStorage) {
+    for (i, timestamp) in [10, 20, 30, 40].into_iter().enumerate() {
+        assert_eq!(storage.store(&memory(&format!("t{}", timestamp), &[], timestamp, &[1.0, i as f32, 0.0, 0.0])).await.unwrap(), StoreOutcome::Stored);
+    }
+
+    // Both bounds are inclusive
+    let found = storage.search_by_time_range(20, 30).await.unwrap();
+    assert_eq!(sorted_contents(&found), ["t20", "t30"]);
+    assert!(storage.search_by_time_range(41, 50).await.unwrap().is_empty());
+    assert!(matches!(storage.search_by_time_range(30, 20).await, Err(StorageError::InvalidFilter(_))));
+}
+
+async fn deletes(storage: &dyn MemoryStorage) {
+    let kept = memory("kept", &["shared"], 1, &[1.0, 0.0, 0.0, 0.0]);
+    let deleted = memory("deleted", &["shared"], 1, &[0.9, 0.1, 0.0, 0.0]);
+    assert_eq!(storage.store(&kept).await.unwrap(), StoreOutcome::Stored);
+    assert_eq!(storage.store(&deleted).await.unwrap(), StoreOutcome::Stored);
+
+    assert_eq!(storage.delete(&deleted.content_hash).await.unwrap(), DeleteOutcome::Deleted);
+    assert!(storage.get(&deleted.content_hash).await.unwrap().is_none());
+    assert!(!storage.check_duplicate_exists(&deleted.content_hash).await.unwrap());
+
+    // A deleted memory no longer turns up in any search
+    let found = storage.search_by_tag(&["shared".to_string()]).await.unwrap();
+    assert_eq!(sorted_contents(&found), ["kept"]);
+    let results = storage.retrieve(&deleted.embedding.clone().unwrap(), 10).await.unwrap();
+    assert!(results.iter().all(|result| result.memory.content_hash != deleted.content_hash));
+    assert!(storage.search_by_time_range(0, 10).await.unwrap().iter().all(|memory| memory.content != "deleted"));
+
+    assert!(matches!(storage.delete(&deleted.content_hash).await, Err(StorageError::NotFound(_))));
+
+    // Its content can be stored again
+    assert_eq!(storage.store(&deleted).await.unwrap(), StoreOutcome::Stored);
+    assert!(storage.get(&deleted.content_hash).await.unwrap().is_some());
+}
+
+async fn lists_every_memory_once(storage: &dyn MemoryStorage) {
+    let mut stored: Vec<String> = Vec::new();
+    for i in 0..25 {
+        let memory = memory(&format!("m{:02}", i), &[], i, &[1.0, i as f32, 0.0, 0.0]);
+        assert_eq!(storage.store(&memory).await.unwrap(), StoreOutcome::Stored);
+        stored.push(memory.content_hash);
+    }
+
+    let mut listed = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = storage.list(cursor.as_deref(), 10).await.unwrap();
+        assert!(page.memories.len() <= 10);
+        listed.extend(page.memories.into_iter().map(|memory| memory.content_hash));
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    listed.sort_unstable();
+    stored.sort_unstable();
+    assert_eq!(listed, stored);
+}
+
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::storage::{CacheConfig, CachedStorage, HnswConfig, InMemoryStorage, ReplicatedStorage, SqliteMemoryStorage, DEFAULT_RETRY_INTERVAL};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    fn in_memory() -> InMemoryStorage {
+        InMemoryStorage::new(Arc::new(DummyEmbeddingGenerator::new(EMBEDDING_SIZE)), None, None, None, false).unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_memory_storage_conforms() {
+        check_conformance(|| async { in_memory() }).await;
+    }
+
+    #[tokio::test]
+    async fn in_memory_storage_with_hnsw_index_conforms() {
+        // Search the graph even in the checks' small stores
+        let config = HnswConfig { exact_threshold: 0, ..Default::default() };
+        check_conformance(|| async {
+            InMemoryStorage::new(Arc::new(DummyEmbeddingGenerator::new(EMBEDDING_SIZE)), None, Some(config.clone()), None, false).unwrap()
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn sqlite_storage_conforms() {
+        let dir = std::env::temp_dir().join(format!("conformance-sqlite-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let opened = AtomicUsize::new(0);
+        check_conformance(|| async {
+            let path = dir.join(format!("{}.db", opened.fetch_add(1, Ordering::SeqCst)));
+            SqliteMemoryStorage::new(path, Arc::new(DummyEmbeddingGenerator::new(EMBEDDING_SIZE)), None, false).unwrap()
+        })
+        .await;
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cached_storage_conforms() {
+        check_conformance(|| async { CachedStorage::new(in_memory(), CacheConfig::default()) }).await;
+    }
+
+    #[tokio::test]
+    async fn replicated_storage_conforms() {
+        check_conformance(|| async {
+            let replicas: Vec<(String, Arc<dyn MemoryStorage>)> =
+                vec![("primary".to_string(), Arc::new(in_memory())), ("secondary".to_string(), Arc::new(in_memory()))];
+            ReplicatedStorage::new(replicas, DEFAULT_RETRY_INTERVAL)
+        })
+        .await;
+    }
+}
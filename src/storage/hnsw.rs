@@ -0,0 +1,561 @@
+use super::{
+    quantize::{Quantization, Query, Vectors},
+    vector::{normalize, Scored},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+/// Default number of neighbours per node on the upper layers
+pub const DEFAULT_M: usize = 16;
+/// Default candidate list size while inserting
+pub const DEFAULT_EF_CONSTRUCTION: usize = 200;
+/// Default candidate list size while searching
+pub const DEFAULT_EF_SEARCH: usize = 128;
+/// Stores up to this size are searched exactly by default
+pub const DEFAULT_EXACT_THRESHOLD: usize = 10_000;
+
+// Levels are capped so a freak draw can't build a very tall, empty hierarchy
+const MAX_LEVEL: usize = 16;
+
+/// Parameters of the HNSW index kept by `InMemoryStorage`
+#[derive(Debug, Clone)]
+pub struct HnswConfig {
+    /// Neighbours per node on the upper layers; the bottom layer keeps twice as many
+    pub m: usize,
+    /// Candidate list size while inserting. Higher builds a better graph, more slowly
+    pub ef_construction: usize,
+    /// Candidate list size while searching. Higher improves recall, more slowly
+    pub ef_search: usize,
+    /// Stores with at most this many indexed memories are searched exactly
+    pub exact_threshold: usize,
+}
+
+impl Default for HnswConfig {
+    fn default() -> Self {
+        Self {
+            m: DEFAULT_M,
+            ef_construction: DEFAULT_EF_CONSTRUCTION,
+            ef_search: DEFAULT_EF_SEARCH,
+            exact_threshold: DEFAULT_EXACT_THRESHOLD,
+        }
+    }
+}
+
+struct Node {
+    content_hash: String,
+    // Neighbour lists for layers 0 up to the node's level
+    neighbours: Vec<Vec<usize>>,
+    deleted: bool,
+}
+
+/// Hierarchical navigable small world graph over memory embeddings, for
+/// approximate cosine similarity search (Malkov & Yashunin, 2016).
+///
+/// Deleted memories stay in the graph as tombstones that searches pass
+/// through but never return; once they outnumber the live memories, the
+/// index is due to be rebuilt with [`HnswIndex::build`].
+pub(super) struct HnswIndex {
+    config: HnswConfig,
+    nodes: Vec<Node>,
+    // Normalised node vectors, by node id
+    vectors: Vectors,
+    ids: HashMap<String, usize>,
+    entry_point: Option<usize>,
+    deleted: usize,
+}
+
+impl HnswIndex {
+    pub(super) fn new(config: HnswConfig, quantization: Option<Quantization>) -> Self {
+        Self {
+            config: HnswConfig { m: config.m.max(2), ..config },
+            nodes: Vec::new(),
+            vectors: Vectors::new(quantization),
+            ids: HashMap::new(),
+            entry_point: None,
+            deleted: 0,
+        }
+    }
+
+    /// Index over the given normalised vectors, by content hash. Slow for
+    /// large stores, so run it away from the async workers.
+    pub(super) fn build(config: HnswConfig, quantization: Option<Quantization>, vectors: Vec<(String, Vec<f32>)>) -> Self {
+        let mut index = Self::new(config, quantization);
+        for (content_hash, vector) in &vectors {
+            index.insert(content_hash, vector);
+        }
+        index
+    }
+
+    /// Number of live memories in the index
+    pub(super) fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Whether searches should go through the graph rather than a linear scan
+    pub(super) fn is_worth_searching(&self) -> bool {
+        self.len() > self.config.exact_threshold
+    }
+
+    // Draw a node's level from the exponentially decaying distribution of the
+    // paper. The draw is derived from the content hash so rebuilding the index,
+    // e.g. after replaying the write-ahead log, gives the same hierarchy.
+    fn level_for(&self, content_hash: &str) -> usize {
+        let digest = Sha256::digest(content_hash.as_bytes());
+        let bits = u64::from_le_bytes(digest[..8].try_into().unwrap_or_default());
+        let uniform = (bits as f64 + 1.0) / (u64::MAX as f64 + 2.0);
+        let level_multiplier = 1.0 / (self.config.m as f64).ln();
+        ((-uniform.ln() * level_multiplier) as usize).min(MAX_LEVEL)
+    }
+
+    fn max_neighbours(&self, layer: usize) -> usize {
+        if layer == 0 { self.config.m * 2 } else { self.config.m }
+    }
+
+    fn similarity(&self, a: usize, b: usize) -> f32 {
+        self.vectors.similarity(a, b)
+    }
+
+    /// Bytes held by the node vectors
+    pub(super) fn bytes(&self) -> usize {
+        self.vectors.bytes()
+    }
+
+    /// Add a memory's embedding, replacing any earlier one for the same hash
+    pub(super) fn insert(&mut self, content_hash: &str, embedding: &[f32]) {
+        self.remove(content_hash);
+
+        let level = self.level_for(content_hash);
+        let id = self.nodes.len();
+        let vector = normalize(embedding);
+        self.vectors.push(&vector);
+        self.nodes.push(Node {
+            content_hash: content_hash.to_string(),
+            neighbours: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.ids.insert(content_hash.to_string(), id);
+
+        let Some(entry_point) = self.entry_point else {
+            self.entry_point = Some(id);
+            return;
+        };
+        let query = self.vectors.prepare(vector);
+        let top_level = self.nodes[entry_point].neighbours.len() - 1;
+
+        // Greedily descend the layers above the new node's level
+        let mut entry = vec![Scored { similarity: self.vectors.score(&query, entry_point), node: entry_point }];
+        for layer in (level + 1..=top_level).rev() {
+            entry = self.search_layer(&query, &entry, 1, layer);
+        }
+
+        // Connect the node on each of its layers
+        for layer in (0..=level.min(top_level)).rev() {
+            let candidates = self.search_layer(&query, &entry, self.config.ef_construction, layer);
+            let neighbours = self.select_neighbours(&candidates, self.config.m);
+            self.nodes[id].neighbours[layer] = neighbours.clone();
+
+            for neighbour in neighbours {
+                self.nodes[neighbour].neighbours[layer].push(id);
+                if self.nodes[neighbour].neighbours[layer].len() > self.max_neighbours(layer) {
+                    self.shrink_neighbours(neighbour, layer);
+                }
+            }
+            entry = candidates;
+        }
+
+        if level > top_level {
+            self.entry_point = Some(id);
+        }
+    }
+
+    /// Drop a memory from the results of future searches
+    pub(super) fn remove(&mut self, content_hash: &str) {
+        let Some(id) = self.ids.remove(content_hash) else { return };
+        self.nodes[id].deleted = true;
+        self.deleted += 1;
+    }
+
+    /// Whether tombstones outnumber the live memories, so that searches
+    /// would be faster through a graph rebuilt without them
+    pub(super) fn needs_rebuild(&self) -> bool {
+        self.deleted > self.ids.len()
+    }
+
+    /// The `k` indexed memories most similar to the query, best first, as
+    /// content hashes with their cosine similarity, estimated if the index is
+    /// quantised
+    pub(super) fn search(&self, query: &[f32], k: usize) -> Vec<(String, f32)> {
+        let Some(entry_point) = self.entry_point else { return Vec::new() };
+        let query = self.vectors.prepare(normalize(query));
+        let top_level = self.nodes[entry_point].neighbours.len() - 1;
+
+        let mut entry = vec![Scored { similarity: self.vectors.score(&query, entry_point), node: entry_point }];
+        for layer in (1..=top_level).rev() {
+            entry = self.search_layer(&query, &entry, 1, layer);
+        }
+        // Tombstones are filtered out afterwards, so look a little wider to make up for them
+        let ef = self.config.ef_search.max(k) + self.deleted.min(k);
+
+        self.search_layer(&query, &entry, ef, 0)
+            .into_iter()
+            .filter(|scored| !self.nodes[scored.node].deleted)
+            .take(k)
+            .map(|scored| (self.nodes[scored.node].content_hash.clone(), scored.similarity))
+            .collect()
+    }
+
+    // Best-first search of one layer, returning up to `ef` nodes, best first
+    fn search_layer(&self, query: &Query, entry: &[Scored], ef: usize, layer: usize) -> Vec<Scored> {
+        let mut visited: HashSet<usize> = entry.iter().map(|scored| scored.node).collect();
+        let mut candidates: BinaryHeap<Scored> = entry.iter().copied().collect();
+        let mut results: BinaryHeap<Reverse<Scored>> = entry.iter().copied().map(Reverse).collect();
+        while results.len() > ef {
+            results.pop();
+        }
+
+        while let Some(candidate) = candidates.pop() {
+            let worst = results.peek().map_or(f32::NEG_INFINITY, |Reverse(worst)| worst.similarity);
+            if candidate.similarity < worst && results.len() >= ef {
+                break;
+            }
+
+            for &neighbour in &self.nodes[candidate.node].neighbours[layer] {
+                if !visited.insert(neighbour) {
+                    continue;
+                }
+                let scored = Scored { similarity: self.vectors.score(query, neighbour), node: neighbour };
+                let worst = results.peek().map_or(f32::NEG_INFINITY, |Reverse(worst)| worst.similarity);
+                if results.len() < ef || scored.similarity > worst {
+                    candidates.push(scored);
+                    results.push(Reverse(scored));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        let mut results: Vec<Scored> = results.into_iter().map(|Reverse(scored)| scored).collect();
+        results.sort_unstable_by(|a, b| b.cmp(a));
+        results
+    }
+
+    // Pick up to `m` neighbours from candidates sorted best first, preferring
+    // ones that aren't closer to an already picked neighbour than to the base
+    // node, so the graph keeps links between clusters. Slots left over are
+    // filled with the closest of the rest.
+    fn select_neighbours(&self, candidates: &[Scored], m: usize) -> Vec<usize> {
+        let mut selected: Vec<usize> = Vec::with_capacity(m);
+        let mut pruned = Vec::new();
+        for candidate in candidates {
+            if selected.len() >= m {
+                break;
+            }
+            if selected.iter().all(|&chosen| self.similarity(candidate.node, chosen) < candidate.similarity) {
+                selected.push(candidate.node);
+            } else {
+                pruned.push(candidate.node);
+            }
+        }
+        selected.extend(pruned.into_iter().take(m.saturating_sub(selected.len())));
+        selected
+    }
+
+    fn shrink_neighbours(&mut self, node: usize, layer: usize) {
+        let mut candidates: Vec<Scored> = self.nodes[node].neighbours[layer]
+            .iter()
+            .map(|&neighbour| Scored { similarity: self.similarity(node, neighbour), node: neighbour })
+            .collect();
+        candidates.sort_unstable_by(|a, b| b.cmp(a));
+        self.nodes[node].neighbours[layer] = self.select_neighbours(&candidates, self.max_neighbours(layer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::{DummyEmbeddingGenerator, EmbeddingError, EmbeddingGenerator};
+    use crate::storage::{conformance::memory, cosine_similarity, InMemoryStorage, MemoryStorage, MemoryStore, QuantizationConfig};
+    use async_trait::async_trait;
+    use std::{sync::Arc, time::{Duration, Instant}};
+
+    // xorshift64*, so the tests need no RNG dependency and are reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next_f32(&mut self) -> f32 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 40) as f32 / (1u64 << 24) as f32 * 2.0 - 1.0
+        }
+
+        fn vector(&mut self, dimensions: usize) -> Vec<f32> {
+            (0..dimensions).map(|_| self.next_f32()).collect()
+        }
+    }
+
+    // Embeddings of real memories cluster by topic, so test on clustered data
+    fn clustered_vectors(count: usize, dimensions: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = Rng(seed);
+        let centres: Vec<Vec<f32>> = (0..(count / 50).max(1)).map(|_| rng.vector(dimensions)).collect();
+        (0..count)
+            .map(|i| {
+                let centre = &centres[i % centres.len()];
+                centre.iter().map(|x| x + rng.next_f32() * 0.5).collect()
+            })
+            .collect()
+    }
+
+    fn exact_top_k(vectors: &[Vec<f32>], query: &[f32], k: usize) -> Vec<String> {
+        let mut scored: Vec<(usize, f32)> = vectors.iter().enumerate().map(|(i, v)| (i, cosine_similarity(query, v))).collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+        scored.into_iter().take(k).map(|(i, _)| i.to_string()).collect()
+    }
+
+    fn recall(expected: &[String], found: &[String]) -> f64 {
+        expected.iter().filter(|hash| found.contains(hash)).count() as f64 / expected.len() as f64
+    }
+
+    #[test]
+    fn finds_nearest_neighbours() {
+        let vectors = clustered_vectors(2000, 32, 1);
+        let mut index = HnswIndex::new(HnswConfig { m: 8, ef_construction: 100, ef_search: 50, exact_threshold: 0 }, None);
+        for (i, vector) in vectors.iter().enumerate() {
+            index.insert(&i.to_string(), vector);
+        }
+        assert_eq!(index.len(), 2000);
+
+        let queries = clustered_vectors(50, 32, 2);
+        let mut total = 0.0;
+        for query in &queries {
+            let found: Vec<String> = index.search(query, 10).into_iter().map(|(hash, _)| hash).collect();
+            assert_eq!(found.len(), 10);
+            total += recall(&exact_top_k(&vectors, query, 10), &found);
+        }
+        assert!(total / queries.len() as f64 >= 0.9, "recall {}", total / queries.len() as f64);
+    }
+
+    #[test]
+    fn never_returns_removed_memories() {
+        let vectors = clustered_vectors(300, 16, 3);
+        let mut index = HnswIndex::new(HnswConfig { m: 8, ..HnswConfig::default() }, None);
+        for (i, vector) in vectors.iter().enumerate() {
+            index.insert(&i.to_string(), vector);
+        }
+        for i in 0..100 {
+            index.remove(&i.to_string());
+        }
+        assert_eq!(index.len(), 200);
+
+        let results = index.search(&vectors[0], 50);
+        assert_eq!(results.len(), 50);
+        assert!(results.iter().all(|(hash, _)| hash.parse::<usize>().unwrap() >= 100));
+
+        // Removing most of the rest makes the graph due for a rebuild without the tombstones
+        assert!(!index.needs_rebuild());
+        for i in 100..280 {
+            index.remove(&i.to_string());
+        }
+        assert!(index.needs_rebuild());
+        assert!(index.search(&vectors[0], 50).iter().all(|(hash, _)| hash.parse::<usize>().unwrap() >= 280));
+        let live = (280..300).map(|i| (i.to_string(), normalize(&vectors[i]))).collect();
+        let rebuilt = HnswIndex::build(index.config.clone(), None, live);
+        assert_eq!(rebuilt.search(&vectors[0], 50).len(), 20);
+    }
+
+    #[test]
+    fn reinserting_replaces_the_embedding() {
+        let mut index = HnswIndex::new(HnswConfig::default(), None);
+        index.insert("a", &[1.0, 0.0]);
+        index.insert("b", &[0.0, 1.0]);
+        index.insert("a", &[0.0, -1.0]);
+        assert_eq!(index.len(), 2);
+        let results = index.search(&[1.0, 0.0], 2);
+        assert!(results.iter().all(|(_, similarity)| *similarity <= 0.0));
+    }
+
+    // The store once any index built in the background has been swapped in
+    async fn settled(storage: &InMemoryStorage) -> tokio::sync::RwLockReadGuard<'_, MemoryStore> {
+        loop {
+            let store = storage.store.read().await;
+            if store.building.is_none() {
+                return store;
+            }
+            drop(store);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn storage_only_keeps_the_index_while_it_is_large() {
+        let config = HnswConfig { exact_threshold: 10, ..HnswConfig::default() };
+        let storage = InMemoryStorage::new(Arc::new(DummyEmbeddingGenerator::new(8)), None, Some(config), None, false).unwrap();
+        let vectors = clustered_vectors(11, 8, 4);
+        for (i, vector) in vectors.iter().enumerate() {
+            storage.store(&memory(&i.to_string(), &[], 0, vector)).await.unwrap();
+            assert_eq!(settled(&storage).await.index.is_some(), i >= 10);
+        }
+        assert_eq!(storage.store.read().await.index.as_ref().map(HnswIndex::len), Some(11));
+        assert_eq!(storage.retrieve(&vectors[3], 1).await.unwrap()[0].memory.content_hash, "hash-3");
+
+        // Kept until the store has shrunk to half the threshold
+        for i in 0..6 {
+            storage.delete(&format!("hash-{}", i)).await.unwrap();
+        }
+        assert!(storage.store.read().await.index.is_none());
+    }
+
+    #[tokio::test]
+    async fn builds_catch_up_with_changes_made_meanwhile() {
+        let config = HnswConfig { exact_threshold: 10, ..HnswConfig::default() };
+        let storage = InMemoryStorage::new(Arc::new(DummyEmbeddingGenerator::new(8)), None, Some(config), None, false).unwrap();
+        let vectors = clustered_vectors(12, 8, 6);
+        let mut store = storage.store.write().await;
+        for (i, vector) in vectors.iter().enumerate().take(11) {
+            store.insert(memory(&i.to_string(), &[], 0, vector));
+        }
+        let build = store.index_build().unwrap();
+        // One build at a time, and searches stay exact until it is swapped in
+        assert!(store.index_build().is_none());
+        assert_eq!(store.retrieve(&vectors[3], 1)[0].memory.content_hash, "hash-3");
+
+        store.insert(memory("11", &[], 0, &vectors[11]));
+        store.remove("hash-0");
+        let (number, index) = build.run();
+        store.install_index(number, index);
+        let index = store.index.as_ref().unwrap();
+        assert_eq!(index.len(), 11);
+        assert_eq!(index.search(&vectors[11], 1)[0].0, "hash-11");
+        assert!(index.search(&vectors[0], 11).iter().all(|(hash, _)| hash != "hash-0"));
+    }
+
+    #[tokio::test]
+    async fn storage_rebuilds_the_index_without_tombstones() {
+        let config = HnswConfig { exact_threshold: 10, ..HnswConfig::default() };
+        let storage = InMemoryStorage::new(Arc::new(DummyEmbeddingGenerator::new(8)), None, Some(config), None, false).unwrap();
+        let vectors = clustered_vectors(30, 8, 7);
+        for (i, vector) in vectors.iter().enumerate() {
+            storage.store(&memory(&i.to_string(), &[], 0, vector)).await.unwrap();
+        }
+        assert_eq!(settled(&storage).await.index.as_ref().map(|index| index.nodes.len()), Some(30));
+
+        for i in 0..16 {
+            storage.delete(&format!("hash-{}", i)).await.unwrap();
+        }
+        let store = settled(&storage).await;
+        let index = store.index.as_ref().unwrap();
+        assert_eq!((index.len(), index.nodes.len()), (14, 14));
+        assert_eq!(index.search(&vectors[20], 1)[0].0, "hash-20");
+    }
+
+    // Generates the benchmark's vectors again, as quantised stores do to rescore their candidates
+    struct BenchmarkEmbeddings(Vec<Vec<f32>>);
+
+    #[async_trait]
+    impl EmbeddingGenerator for BenchmarkEmbeddings {
+        async fn generate_embedding(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+            let i: usize = text.parse().expect("content of a benchmark memory");
+            Ok(self.0[i].clone())
+        }
+        fn get_embedding_size(&self) -> usize {
+            self.0.first().map_or(0, Vec::len)
+        }
+        fn name(&self) -> &'static str {
+            "Benchmark"
+        }
+    }
+
+    /// Recall and latency of `InMemoryStorage::retrieve` with the index
+    /// against the linear scan. Run with
+    /// `cargo test --release hnsw_benchmark -- --ignored --nocapture`,
+    /// setting `HNSW_BENCHMARK_SIZE` to change the number of memories and
+    /// `HNSW_BENCHMARK_QUANTIZATION` to `int8` or `binary` to quantise.
+    #[tokio::test]
+    #[ignore]
+    async fn hnsw_benchmark() {
+        const DIMENSIONS: usize = 384;
+        const QUERIES: usize = 100;
+        const K: usize = 10;
+        let size: usize = std::env::var("HNSW_BENCHMARK_SIZE").ok().and_then(|size| size.parse().ok()).unwrap_or(50_000);
+        let quantization = std::env::var("HNSW_BENCHMARK_QUANTIZATION").ok().map(|kind| QuantizationConfig::new(kind.parse().unwrap()));
+
+        let vectors = clustered_vectors(size, DIMENSIONS, 4);
+        let generator = Arc::new(BenchmarkEmbeddings(vectors.clone()));
+        let linear = InMemoryStorage::new(generator.clone(), None, None, None, false).unwrap();
+        let quantized = InMemoryStorage::new(generator.clone(), None, None, quantization.clone(), false).unwrap();
+        let indexed = InMemoryStorage::new(generator, None, Some(HnswConfig { exact_threshold: 0, ..HnswConfig::default() }), quantization.clone(), false).unwrap();
+
+        let mut build_time = Duration::ZERO;
+        for (i, vector) in vectors.iter().enumerate() {
+            let memory = memory(&i.to_string(), &[], 0, vector);
+            linear.store(&memory).await.unwrap();
+            if quantization.is_some() {
+                quantized.store(&memory).await.unwrap();
+            }
+            let started = Instant::now();
+            indexed.store(&memory).await.unwrap();
+            build_time += started.elapsed();
+        }
+
+        // Includes the last build in the background, if any
+        let started = Instant::now();
+        drop(settled(&indexed).await);
+        build_time += started.elapsed();
+
+        let queries = clustered_vectors(QUERIES, DIMENSIONS, 5);
+        let mut linear_time = Duration::ZERO;
+        let mut expected = Vec::with_capacity(QUERIES);
+        for query in &queries {
+            let started = Instant::now();
+            let results = linear.retrieve(query, K).await.unwrap();
+            linear_time += started.elapsed();
+            expected.push(results.into_iter().map(|result| result.memory.content_hash).collect::<Vec<_>>());
+        }
+
+        println!("{} memories of {} dimensions, {} queries for the top {}", size, DIMENSIONS, QUERIES, K);
+        println!("index build: {:?} ({:?} per memory)", build_time, build_time / size as u32);
+        println!("linear scan: {:?} per query", linear_time / QUERIES as u32);
+        if let Some(quantization) = &quantization {
+            let (mut quantized_time, mut total_recall) = (Duration::ZERO, 0.0);
+            for (query, expected) in queries.iter().zip(&expected) {
+                let started = Instant::now();
+                let results = quantized.retrieve(query, K).await.unwrap();
+                quantized_time += started.elapsed();
+                let found: Vec<String> = results.into_iter().map(|result| result.memory.content_hash).collect();
+                total_recall += recall(expected, &found);
+            }
+            println!(
+                "{:?} linear scan, {}x oversampling: {:?} per query, recall@{} {:.3}, {} bytes of embeddings instead of {}",
+                quantization.quantization,
+                quantization.oversampling,
+                quantized_time / QUERIES as u32,
+                K,
+                total_recall / QUERIES as f64,
+                quantized.store.read().await.search_bytes(),
+                linear.store.read().await.search_bytes()
+            );
+        }
+        for ef_search in [16, 32, 64, 128, 256] {
+            indexed.store.write().await.index.as_mut().unwrap().config.ef_search = ef_search;
+            let (mut indexed_time, mut total_recall) = (Duration::ZERO, 0.0);
+            for (query, expected) in queries.iter().zip(&expected) {
+                let started = Instant::now();
+                let results = indexed.retrieve(query, K).await.unwrap();
+                indexed_time += started.elapsed();
+                let found: Vec<String> = results.into_iter().map(|result| result.memory.content_hash).collect();
+                total_recall += recall(expected, &found);
+            }
+            println!(
+                "hnsw ef_search={:<3}: {:?} per query, recall@{} {:.3}",
+                ef_search,
+                indexed_time / QUERIES as u32,
+                K,
+                total_recall / QUERIES as f64
+            );
+        }
+    }
+}
@@ -1,120 +1,572 @@
-use crate::models::{Memory, MemoryQueryResult};
-use crate::embeddings::EmbeddingGenerator;
+use crate::models::{Memory, MemoryPage, MemoryQueryResult};
+use crate::embeddings::{EmbeddingError, EmbeddingGenerator};
 use async_trait::async_trait;
 use anyhow::Result;
-use std::{collections::HashMap, sync::Arc};
-use tokio::sync::Mutex;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, PoisonError,
+    },
+};
+use tokio::sync::RwLock;
 
 // Export ChromaDB storage implementation
 mod chroma;
-pub use chroma::ChromaMemoryStorage;
+pub use chroma::{ChromaAuth, ChromaMemoryStorage, ChromaOptions, ChromaTls, DEFAULT_DATABASE, DEFAULT_TENANT, DEFAULT_TIMEOUT};
 
+// Supervised local Chroma server
+mod chroma_process;
+pub use chroma_process::ChromaProcess;
+
+// Retries, backoff and circuit breaking for remote backends
+mod resilience;
+pub use resilience::RetryPolicy;
+
+// Durable queue of writes waiting for an unreachable backend
+mod write_queue;
+
+// Export Qdrant storage implementation
+mod qdrant;
+pub use qdrant::QdrantMemoryStorage;
+
+// Export SQLite storage implementation
+mod sqlite;
+pub use sqlite::SqliteMemoryStorage;
+
+// Approximate nearest neighbour index for InMemoryStorage
+mod hnsw;
+pub use hnsw::HnswConfig;
+use hnsw::HnswIndex;
+
+// Normalised embedding matrix and vectorised scoring for InMemoryStorage
+mod vector;
+use vector::EmbeddingMatrix;
+
+// Int8 and binary codes for the embeddings searched by the in-process backends
+mod quantize;
+pub use quantize::{Quantization, QuantizationConfig};
+
+// Caching decorator for any storage
+mod cache;
+pub use cache::{CacheConfig, CachedStorage};
+
+// Replication with failover over several storages
+mod replicated;
+pub use replicated::{DeferredStorage, ReplicatedStorage, StorageOpener, DEFAULT_RETRY_INTERVAL};
+
+// Separate storages for the namespaces of a server
+mod namespaces;
+pub use namespaces::{registry_path, validate_namespace, NamespaceOpener, Namespaces, DEFAULT_NAMESPACE};
+
+// Write-ahead log making InMemoryStorage durable
+mod wal;
+pub use wal::{WalConfig, DEFAULT_SNAPSHOT_INTERVAL};
+use wal::{WalEntry, WriteAheadLog};
+
+// Behaviour every storage must share, checked by each backend's tests, and
+// the memories the tests store
+#[cfg(test)]
+pub(crate) mod conformance;
+
+/// Why a storage operation failed. `NotFound`, `InvalidFilter` and
+/// `Conflict` are the storage's answer to the request; `Unavailable` and
+/// `Other` mean the storage itself failed.
 #[derive(thiserror::Error, Debug)]
 pub enum StorageError {
-    #[error("ChromaDB client error: {0}")]
-    ClientError(String),
-    #[error("Duplicate content hash: {0}")]
-    DuplicateError(String),
-    #[error("Memory not found: {0}")]
-    NotFoundError(String),
-    #[error("Metadata serialization error: {0}")]
-    SerializationError(#[from] serde_json::Error),
-    #[error("Database operation failed: {0}")]
-    OperationFailed(String),
+    /// No memory has the given content hash
+    #[error("No memory found with hash: {0}")]
+    NotFound(String),
+    /// The backend couldn't be reached; the same request may work later
+    #[error("Storage backend unavailable: {0}")]
+    Unavailable(String),
+    /// A search or listing was given a filter or cursor that can't be used
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(String),
+    /// The write clashes with what the storage holds, e.g. a different
+    /// memory stored under the same content hash
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    /// The storage was opened read-only and refuses every change
+    #[error("{0} is read-only")]
+    ReadOnly(String),
     #[error(transparent)]
-    Other(#[from] anyhow::Error),
+    Other(anyhow::Error),
+}
+
+impl StorageError {
+    /// Whether the storage answered the request by refusing it, rather than
+    /// failing to handle it
+    pub fn is_rejection(&self) -> bool {
+        matches!(self, Self::NotFound(_) | Self::InvalidFilter(_) | Self::Conflict(_) | Self::ReadOnly(_))
+    }
+}
+
+// Storage errors passed along as `anyhow` errors keep their kind
+impl From<anyhow::Error> for StorageError {
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<StorageError>() {
+            Ok(error) => error,
+            Err(error) => Self::Other(error),
+        }
+    }
+}
+
+impl From<EmbeddingError> for StorageError {
+    fn from(error: EmbeddingError) -> Self {
+        Self::Other(error.into())
+    }
+}
+
+pub type StorageResult<T> = std::result::Result<T, StorageError>;
+
+/// What became of a memory given to `MemoryStorage::store`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoreOutcome {
+    Stored,
+    /// Accepted while the backend is unreachable, and stored once it is back
+    Queued,
+    /// The same memory is already stored, so nothing changed
+    Duplicate,
+}
+
+impl StoreOutcome {
+    /// Whether the storage has taken the memory, now or once it is reachable
+    pub fn is_stored(self) -> bool {
+        self != Self::Duplicate
+    }
+}
+
+/// What became of a memory given to `MemoryStorage::delete`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteOutcome {
+    Deleted,
+    /// Accepted while the backend is unreachable, and deleted once it is back
+    Queued,
 }
 
 #[async_trait]
 pub trait MemoryStorage: Send + Sync {
-    async fn store(&self, memory: &Memory) -> Result<(bool, String)>; // success, message
-    async fn retrieve(&self, query_embedding: &Vec<f32>, n_results: usize) -> Result<Vec<MemoryQueryResult>>;
-    async fn search_by_tag(&self, tags: &[String]) -> Result<Vec<Memory>>;
-    async fn delete(&self, content_hash: &str) -> Result<(bool, String)>; // success, message
-    async fn check_duplicate_exists(&self, content_hash: &str) -> Result<bool>;
+    async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome>;
+    async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>>;
+    async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>>;
+    async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>>;
+    async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>>; // inclusive bounds
+    async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome>; // NotFound if there is no such memory
+    async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool>;
+    async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage>; // cursor from the previous page, None to start
+}
+
+// Lets decorators wrap storages chosen at runtime
+#[async_trait]
+impl<T: MemoryStorage + ?Sized> MemoryStorage for Arc<T> {
+    async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+        (**self).store(memory).await
+    }
+    async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>> {
+        (**self).retrieve(query_embedding, n_results).await
+    }
+    async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>> {
+        (**self).get(content_hash).await
+    }
+    async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>> {
+        (**self).search_by_tag(tags).await
+    }
+    async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>> {
+        (**self).search_by_time_range(start_seconds, end_seconds).await
+    }
+    async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+        (**self).delete(content_hash).await
+    }
+    async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool> {
+        (**self).check_duplicate_exists(content_hash).await
+    }
+    async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage> {
+        (**self).list(cursor, limit).await
+    }
+}
+
+// Outcome of storing a memory under a content hash already holding `existing_content`
+fn duplicate_or_conflict(memory: &Memory, existing_content: &str) -> StorageResult<StoreOutcome> {
+    if existing_content == memory.content {
+        Ok(StoreOutcome::Duplicate)
+    } else {
+        Err(StorageError::Conflict(format!("A different memory is already stored with hash: {}", memory.content_hash)))
+    }
+}
+
+// A time range ending before it starts can't match anything, and is most likely a mistake
+fn check_time_range(start_seconds: i64, end_seconds: i64) -> StorageResult<()> {
+    if start_seconds > end_seconds {
+        return Err(StorageError::InvalidFilter(format!("Time range starts at {} after it ends at {}", start_seconds, end_seconds)));
+    }
+    Ok(())
+}
+
+// Helper function to calculate cosine similarity between two embeddings
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot_product = vector::dot(a, b);
+    let magnitude_a = vector::dot(a, a).sqrt();
+    let magnitude_b = vector::dot(b, b).sqrt();
+    
+    if magnitude_a > 0.0 && magnitude_b > 0.0 {
+        dot_product / (magnitude_a * magnitude_b)
+    } else {
+        0.0
+    }
+}
+
+// Everything InMemoryStorage keeps behind its lock
+struct MemoryStore {
+    memories: HashMap<String, Memory>,
+    // Normalised embeddings of `memories`, for exact search
+    matrix: EmbeddingMatrix,
+    // The index is only kept while the store is large enough to search it,
+    // since it holds another copy of the embeddings
+    hnsw: Option<HnswConfig>,
+    index: Option<HnswIndex>,
+    // Set while an index is built in the background: the build's number, and
+    // the changes made since its snapshot, to be applied before it is swapped in
+    building: Option<(u64, Vec<IndexChange>)>,
+    builds: u64,
+}
+
+// A change to the indexed embeddings
+enum IndexChange {
+    Insert(String, Vec<f32>),
+    Remove(String),
+}
+
+// An index to build from a snapshot of the matrix, away from the store's lock
+struct IndexBuild {
+    number: u64,
+    config: HnswConfig,
+    quantization: Option<Quantization>,
+    vectors: Vec<(String, Vec<f32>)>,
+}
+
+impl IndexBuild {
+    fn run(self) -> (u64, HnswIndex) {
+        let rows = self.vectors.len();
+        let index = HnswIndex::build(self.config, self.quantization, self.vectors);
+        tracing::info!("Built HNSW index over {} memories", rows);
+        (self.number, index)
+    }
+}
+
+impl MemoryStore {
+    fn insert(&mut self, mut memory: Memory) {
+        if let Some(embedding) = &memory.embedding
+            && self.matrix.insert(&memory.content_hash, embedding)
+        {
+            if let Some(index) = &mut self.index {
+                index.insert(&memory.content_hash, embedding);
+            }
+            if let Some((_, changes)) = &mut self.building {
+                changes.push(IndexChange::Insert(memory.content_hash.clone(), embedding.clone()));
+            }
+        }
+        // A quantised matrix only keeps codes; candidates are rescored against re-derived embeddings instead
+        if self.matrix.quantization().is_some() {
+            memory.embedding = None;
+        }
+        self.memories.insert(memory.content_hash.clone(), memory);
+    }
+
+    fn remove(&mut self, content_hash: &str) {
+        self.memories.remove(content_hash);
+        self.matrix.remove(content_hash);
+        if let Some(index) = &mut self.index {
+            index.remove(content_hash);
+        }
+        if let Some((_, changes)) = &mut self.building {
+            changes.push(IndexChange::Remove(content_hash.to_string()));
+        }
+    }
+
+    // An index to build once the store outgrows exact search, or once the
+    // index has more tombstones than memories. The index is dropped once the
+    // store has shrunk well below the threshold, so it isn't rebuilt back
+    // and forth.
+    fn index_build(&mut self) -> Option<IndexBuild> {
+        let config = self.hnsw.as_ref()?;
+        let rows = self.matrix.len();
+        if rows <= config.exact_threshold / 2 {
+            self.index = None;
+            self.building = None;
+            return None;
+        }
+        let due = match &self.index {
+            Some(index) => index.needs_rebuild(),
+            None => rows > config.exact_threshold,
+        };
+        if !due || self.building.is_some() {
+            return None;
+        }
+
+        self.builds += 1;
+        self.building = Some((self.builds, Vec::new()));
+        Some(IndexBuild {
+            number: self.builds,
+            config: config.clone(),
+            quantization: self.matrix.quantization(),
+            vectors: self.matrix.rows(),
+        })
+    }
+
+    // Swap in a built index, bringing it up to date first. Builds that were
+    // given up on, because the store shrank in the meantime, are dropped.
+    fn install_index(&mut self, number: u64, mut index: HnswIndex) {
+        let Some((_, changes)) = self.building.take_if(|(building, _)| *building == number) else { return };
+        for change in changes {
+            match change {
+                IndexChange::Insert(content_hash, embedding) => index.insert(&content_hash, &embedding),
+                IndexChange::Remove(content_hash) => index.remove(&content_hash),
+            }
+        }
+        self.index = Some(index);
+    }
+
+    // The best `candidates` memories, scored with the codes if the matrix is quantised
+    fn retrieve(&self, query_embedding: &[f32], candidates: usize) -> Vec<MemoryQueryResult> {
+        let best = match &self.index {
+            Some(index) if index.is_worth_searching() => index.search(query_embedding, candidates),
+            // Small stores are scanned without the graph
+            _ => self.matrix.top_k(query_embedding, candidates),
+        };
+        best.into_iter()
+            .filter_map(|(content_hash, relevance_score)| {
+                self.memories.get(&content_hash).map(|memory| MemoryQueryResult { memory: memory.clone(), relevance_score })
+            })
+            .collect()
+    }
+
+    // Bytes held by the search structures' copies of the embeddings
+    fn search_bytes(&self) -> usize {
+        self.matrix.bytes() + self.index.as_ref().map_or(0, HnswIndex::bytes)
+    }
 }
 
-// A simple in-memory implementation for the MVP
+// A simple in-memory implementation for the MVP. Reads share the lock, so
+// retrievals run concurrently with each other and only wait for writes.
 pub struct InMemoryStorage {
-    memories: Arc<Mutex<HashMap<String, Memory>>>,
+    store: Arc<RwLock<MemoryStore>>,
     embedding_generator: Arc<dyn EmbeddingGenerator>,
+    // Only locked while holding `store` for writing, so log order matches the order changes are applied.
+    // Its file I/O runs on the blocking pool.
+    wal: Option<Arc<std::sync::Mutex<WriteAheadLog>>>,
+    // Set while a snapshot is being written in the background
+    compacting: Arc<AtomicBool>,
+    // With quantised search, candidates found with the codes are rescored
+    // against embeddings generated again from their content
+    quantization: Option<QuantizationConfig>,
+    read_only: bool,
 }
 
 impl InMemoryStorage {
-    pub fn new(embedding_generator: Arc<dyn EmbeddingGenerator>) -> Self {
-        Self {
-            memories: Arc::new(Mutex::new(HashMap::new())),
+    /// Create the store. With a write-ahead log configured, the memories in
+    /// its snapshot and log are recovered and every change is logged. With an
+    /// HNSW configuration, an index is built once the store holds more than
+    /// its exact search threshold, and similarity searches use it.
+    /// With quantisation, the matrix and index hold compressed embeddings and
+    /// the memories keep none at full precision. A read-only store rejects changes, and only recovers from its log
+    /// without opening it for writing.
+    pub fn new(
+        embedding_generator: Arc<dyn EmbeddingGenerator>,
+        wal_config: Option<WalConfig>,
+        hnsw_config: Option<HnswConfig>,
+        quantization: Option<QuantizationConfig>,
+        read_only: bool,
+    ) -> Result<Self> {
+        let (wal, memories) = match wal_config {
+            Some(config) if read_only => (None, WriteAheadLog::recover(&config)?.0),
+            Some(config) => {
+                let (wal, memories) = WriteAheadLog::open(&config)?;
+                (Some(Arc::new(std::sync::Mutex::new(wal))), memories)
+            }
+            None => (None, HashMap::new()),
+        };
+
+        let codes = quantization.as_ref().map(|quantization| quantization.quantization);
+        let mut store = MemoryStore {
+            memories: HashMap::with_capacity(memories.len()),
+            matrix: EmbeddingMatrix::new(codes),
+            hnsw: hnsw_config,
+            index: None,
+            building: None,
+            builds: 0,
+        };
+        for memory in memories.into_values() {
+            store.insert(memory);
+        }
+        // Nothing searches the store yet, so the index is built right away
+        if let Some(build) = store.index_build() {
+            let (number, index) = build.run();
+            store.install_index(number, index);
+        }
+        if let Some(quantization) = codes
+            && !store.memories.is_empty()
+        {
+            tracing::info!(
+                "Search structures hold {} bytes of {:?} embeddings for {} memories",
+                store.search_bytes(),
+                quantization,
+                store.memories.len()
+            );
+        }
+
+        Ok(Self {
+            store: Arc::new(RwLock::new(store)),
             embedding_generator,
+            wal,
+            compacting: Arc::new(AtomicBool::new(false)),
+            quantization,
+            read_only,
+        })
+    }
+
+    // Build an index on the blocking pool and swap it in once it is built.
+    // Searches carry on with the store as it is until then.
+    fn build_index(&self, build: IndexBuild) {
+        let store = self.store.clone();
+        tokio::spawn(async move {
+            let number = build.number;
+            match tokio::task::spawn_blocking(move || build.run()).await {
+                Ok((number, index)) => store.write().await.install_index(number, index),
+                Err(e) => {
+                    tracing::warn!("Failed to build HNSW index: {}", e);
+                    store.write().await.building.take_if(|(building, _)| *building == number);
+                }
+            }
+        });
+    }
+
+    // Log a change before it is applied
+    async fn log(&self, entry: WalEntry) -> Result<()> {
+        if let Some(wal) = &self.wal {
+            let wal = wal.clone();
+            tokio::task::spawn_blocking(move || wal.lock().unwrap_or_else(PoisonError::into_inner).append(&entry)).await??;
         }
+        Ok(())
     }
 
-    // Helper function to calculate cosine similarity between two embeddings
-    fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
-        let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-        let magnitude_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-        let magnitude_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-        
-        if magnitude_a > 0.0 && magnitude_b > 0.0 {
-            dot_product / (magnitude_a * magnitude_b)
-        } else {
-            0.0
+    // Compact the log once enough changes have been applied since the last
+    // snapshot. Called while holding `store` for writing; the log is set aside
+    // straight away, and the snapshot is written in the background.
+    async fn snapshot_if_due(&self) {
+        let Some(wal) = &self.wal else { return };
+        if !wal.lock().unwrap_or_else(PoisonError::into_inner).should_snapshot() || self.compacting.swap(true, Ordering::AcqRel) {
+            return;
         }
+
+        let rotating = wal.clone();
+        let rotated = tokio::task::spawn_blocking(move || {
+            let mut wal = rotating.lock().unwrap_or_else(PoisonError::into_inner);
+            wal.rotate().map(|segment| (wal.dir().to_path_buf(), segment))
+        })
+        .await;
+        let (dir, segment) = match rotated.map_err(anyhow::Error::from).and_then(|rotated| rotated) {
+            Ok(rotated) => rotated,
+            Err(e) => {
+                tracing::warn!("Failed to snapshot in-memory storage: {}", e);
+                self.compacting.store(false, Ordering::Release);
+                return;
+            }
+        };
+
+        let store = self.store.clone();
+        let compacting = self.compacting.clone();
+        let quantized = self.quantization.is_some();
+        tokio::spawn(async move {
+            // Waits for the caller to release the write lock, then only holds the read lock while copying
+            let store = store.read_owned().await;
+            let result = tokio::task::spawn_blocking(move || {
+                let memories = if quantized {
+                    // The store doesn't keep the embeddings the snapshot needs, but the logs do
+                    drop(store);
+                    wal::replay_segments(&dir, segment)?
+                } else {
+                    let memories = store.memories.clone();
+                    drop(store);
+                    memories
+                };
+                wal::compact(&dir, &memories, segment)
+            })
+            .await;
+            if let Err(e) = result.map_err(anyhow::Error::from).and_then(|compacted| compacted) {
+                tracing::warn!("Failed to snapshot in-memory storage: {}", e);
+            }
+            compacting.store(false, Ordering::Release);
+        });
     }
 }
 
 #[async_trait]
 impl MemoryStorage for InMemoryStorage {
-    async fn check_duplicate_exists(&self, content_hash: &str) -> Result<bool> {
-        let memories = self.memories.lock().await;
-        Ok(memories.contains_key(content_hash))
+    async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool> {
+        let store = self.store.read().await;
+        Ok(store.memories.contains_key(content_hash))
     }
 
-    async fn store(&self, memory: &Memory) -> Result<(bool, String)> {
-        if self.check_duplicate_exists(&memory.content_hash).await? {
-            return Ok((false, "Duplicate content detected".to_string()));
+    async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+        if self.read_only {
+            return Err(StorageError::ReadOnly("The in-memory storage".to_string()));
+        }
+        if let Some(existing) = self.store.read().await.memories.get(&memory.content_hash) {
+            return duplicate_or_conflict(memory, &existing.content);
         }
 
-        let content_hash = memory.content_hash.clone();
-        
-        // Generate embedding if not already present
+        // Generate embedding if not already present, before taking the write lock
         let mut memory_to_store = memory.clone();
         if memory_to_store.embedding.is_none() {
             memory_to_store.embedding = Some(self.embedding_generator.generate_embedding(&memory_to_store.content).await?);
         }
         
         // Store memory
-        let mut memories = self.memories.lock().await;
-        memories.insert(content_hash.clone(), memory_to_store);
+        let mut store = self.store.write().await;
+        // Lost a race against a concurrent store under the same hash
+        if let Some(existing) = store.memories.get(&memory.content_hash) {
+            return duplicate_or_conflict(memory, &existing.content);
+        }
+        self.log(WalEntry::store(&memory_to_store)).await?;
+        store.insert(memory_to_store);
+        if let Some(build) = store.index_build() {
+            self.build_index(build);
+        }
+        self.snapshot_if_due().await;
 
-        Ok((true, format!("Successfully stored memory with hash: {}", content_hash)))
+        Ok(StoreOutcome::Stored)
     }
 
-    async fn retrieve(&self, query_embedding: &Vec<f32>, n_results: usize) -> Result<Vec<MemoryQueryResult>> {
-        let memories = self.memories.lock().await;
-        
-        // Calculate similarity scores for all memories
-        let mut results: Vec<MemoryQueryResult> = Vec::new();
-        for memory in memories.values() {
-            if let Some(memory_embedding) = &memory.embedding {
-                let score = Self::cosine_similarity(query_embedding, memory_embedding);
-                results.push(MemoryQueryResult {
-                    memory: memory.clone(),
-                    relevance_score: score,
-                });
+    async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>> {
+        // Scoring is CPU-bound, and may fan out over several threads, so keep it off the async workers
+        let store = self.store.clone().read_owned().await;
+        let query = query_embedding.to_vec();
+        let candidates = self.quantization.as_ref().map_or(n_results, |quantization| quantization.candidates(n_results));
+        let mut results = tokio::task::spawn_blocking(move || store.retrieve(&query, candidates)).await.map_err(anyhow::Error::from)?;
+
+        // Rescore the candidates against their embeddings, generated again as only codes are kept
+        if self.quantization.is_some() {
+            for result in &mut results {
+                let embedding = self.embedding_generator.generate_embedding(&result.memory.content).await?;
+                result.relevance_score = cosine_similarity(query_embedding, &embedding);
             }
+            results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+            results.truncate(n_results);
         }
+        Ok(results)
+    }
 
-        // Sort by relevance score (descending)
-        results.sort_by(|a, b| b.relevance_score.partial_cmp(&a.relevance_score).unwrap());
-
-        // Return top n results
-        Ok(results.into_iter().take(n_results).collect())
+    async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>> {
+        let store = self.store.read().await;
+        Ok(store.memories.get(content_hash).cloned())
     }
 
-    async fn search_by_tag(&self, tags: &[String]) -> Result<Vec<Memory>> {
-        let memories = self.memories.lock().await;
+    async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>> {
+        let store = self.store.read().await;
         
-        let matching_memories: Vec<Memory> = memories
+        let matching_memories: Vec<Memory> = store
+            .memories
             .values()
             .filter(|memory| memory.tags.iter().any(|tag| tags.contains(tag)))
             .cloned()
@@ -123,13 +575,52 @@ impl MemoryStorage for InMemoryStorage {
         Ok(matching_memories)
     }
 
-    async fn delete(&self, content_hash: &str) -> Result<(bool, String)> {
-        let mut memories = self.memories.lock().await;
+    async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>> {
+        check_time_range(start_seconds, end_seconds)?;
+        let store = self.store.read().await;
+
+        let matching_memories: Vec<Memory> = store
+            .memories
+            .values()
+            .filter(|memory| memory.timestamp_seconds >= start_seconds && memory.timestamp_seconds <= end_seconds)
+            .cloned()
+            .collect();
+
+        Ok(matching_memories)
+    }
+
+    async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+        if self.read_only {
+            return Err(StorageError::ReadOnly("The in-memory storage".to_string()));
+        }
+        let mut store = self.store.write().await;
         
-        if memories.remove(content_hash).is_some() {
-            Ok((true, format!("Successfully deleted memory with hash: {}", content_hash)))
+        if store.memories.contains_key(content_hash) {
+            self.log(WalEntry::Delete { content_hash: content_hash.to_string() }).await?;
+            store.remove(content_hash);
+            if let Some(build) = store.index_build() {
+                self.build_index(build);
+            }
+            self.snapshot_if_due().await;
+            Ok(DeleteOutcome::Deleted)
         } else {
-            Ok((false, format!("No memory found with hash: {}", content_hash)))
+            Err(StorageError::NotFound(content_hash.to_string()))
         }
     }
+
+    async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage> {
+        let store = self.store.read().await;
+
+        // Pages are ordered by content hash, so the cursor is the last hash returned
+        let mut hashes: Vec<&String> = store
+            .memories
+            .keys()
+            .filter(|hash| cursor.is_none_or(|cursor| hash.as_str() > cursor))
+            .collect();
+        hashes.sort_unstable();
+
+        let page: Vec<Memory> = hashes.iter().take(limit).map(|hash| store.memories[*hash].clone()).collect();
+        let next_cursor = if hashes.len() > limit { page.last().map(|memory| memory.content_hash.clone()) } else { None };
+        Ok(MemoryPage { memories: page, next_cursor })
+    }
 }
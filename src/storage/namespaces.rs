@@ -0,0 +1,257 @@
+use super::{MemoryStorage, StorageError};
+use crate::models::Memory;
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+};
+use tokio::sync::OnceCell;
+
+/// Namespace held by the configured storage itself, which has all memories
+/// stored before namespaces existed
+pub const DEFAULT_NAMESPACE: &str = "default";
+// Namespace names become part of collection names, which Chroma limits to 63 characters
+const MAX_NAMESPACE_LEN: usize = 32;
+// Page size while listing the memories of a namespace
+const PAGE_SIZE: usize = 256;
+
+/// Check that a namespace name can be used in collection names and file
+/// names: letters, digits, `-` and `_`, starting and ending with a letter or
+/// digit
+pub fn validate_namespace(name: &str) -> Result<()> {
+    let alphanumeric = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric());
+    let valid = name.len() <= MAX_NAMESPACE_LEN
+        && alphanumeric(name.chars().next())
+        && alphanumeric(name.chars().last())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        anyhow::bail!(
+            "Invalid namespace '{}': use up to {} letters, digits, '-' and '_', starting and ending with a letter or digit",
+            name,
+            MAX_NAMESPACE_LEN
+        );
+    }
+    Ok(())
+}
+
+/// File listing the namespaces of the described storage
+pub fn registry_path(dir: &Path, storage_description: &str) -> PathBuf {
+    let digest = Sha256::digest(storage_description.as_bytes());
+    dir.join(format!("{}.json", hex::encode(&digest[..8])))
+}
+
+/// Opens the storage holding a namespace other than the default one
+pub type NamespaceOpener = Box<dyn Fn(String) -> Pin<Box<dyn Future<Output = Result<Arc<dyn MemoryStorage>>> + Send>> + Send + Sync>;
+
+// Storage of a namespace, set once it has been opened
+type NamespaceStorage = Arc<OnceCell<Arc<dyn MemoryStorage>>>;
+
+/// The namespaces of a server, each with a storage of its own. Storages are
+/// opened on first use, and the names are kept in a registry file so that
+/// namespaces outlive the process.
+pub struct Namespaces {
+    opener: NamespaceOpener,
+    registry_path: Option<PathBuf>,
+    // Every namespace. The lock is never held while a storage is opened, so
+    // a slow backend only holds up calls to its own namespace.
+    namespaces: Mutex<BTreeMap<String, NamespaceStorage>>,
+}
+
+impl Namespaces {
+    pub fn new(default_storage: Arc<dyn MemoryStorage>, opener: NamespaceOpener, registry_path: Option<PathBuf>) -> Result<Self> {
+        let mut namespaces = BTreeMap::new();
+        if let Some(path) = &registry_path
+            && path.exists()
+        {
+            let contents = std::fs::read_to_string(path).context(format!("Failed to read namespace registry {:?}", path))?;
+            let names: Vec<String> = serde_json::from_str(&contents).context(format!("Invalid namespace registry {:?}", path))?;
+            namespaces.extend(names.into_iter().map(|name| (name, NamespaceStorage::default())));
+        }
+        namespaces.insert(DEFAULT_NAMESPACE.to_string(), Arc::new(OnceCell::from(default_storage)));
+        Ok(Self { opener, registry_path, namespaces: Mutex::new(namespaces) })
+    }
+
+    fn namespaces(&self) -> MutexGuard<'_, BTreeMap<String, NamespaceStorage>> {
+        self.namespaces.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Names of every namespace, sorted
+    pub async fn list(&self) -> Vec<String> {
+        self.namespaces().keys().cloned().collect()
+    }
+
+    /// The storage holding a namespace, or None if there is no such namespace
+    pub async fn storage(&self, name: &str) -> Result<Option<Arc<dyn MemoryStorage>>> {
+        let Some(storage) = self.namespaces().get(name).cloned() else {
+            return Ok(None);
+        };
+        // Concurrent callers wait for the same opening, which a failure leaves to the next caller
+        let storage = storage
+            .get_or_try_init(|| (self.opener)(name.to_string()))
+            .await
+            .context(format!("Failed to open namespace '{}'", name))?;
+        Ok(Some(storage.clone()))
+    }
+
+    /// Every memory in a namespace, or None if there is no such namespace
+    pub async fn memories(&self, name: &str) -> Result<Option<Vec<Memory>>> {
+        let Some(storage) = self.storage(name).await? else {
+            return Ok(None);
+        };
+        let mut memories = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = storage.list(cursor.as_deref(), PAGE_SIZE).await?;
+            memories.extend(page.memories);
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Ok(Some(memories))
+    }
+
+    /// Create a namespace, opening its storage. Returns false if it already exists.
+    pub async fn create(&self, name: &str) -> Result<bool> {
+        validate_namespace(name)?;
+        if self.namespaces().contains_key(name) {
+            return Ok(false);
+        }
+        let storage = (self.opener)(name.to_string()).await.context(format!("Failed to open namespace '{}'", name))?;
+
+        // Another call may have created it while the storage was opened
+        let mut namespaces = self.namespaces();
+        if namespaces.contains_key(name) {
+            return Ok(false);
+        }
+        namespaces.insert(name.to_string(), Arc::new(OnceCell::from(storage)));
+        self.save(&namespaces)?;
+        tracing::info!("Created namespace '{}'", name);
+        Ok(true)
+    }
+
+    /// Delete every memory in a namespace and forget it, returning how many
+    /// memories were deleted, or None if there is no such namespace. The
+    /// default namespace can't be dropped.
+    pub async fn remove(&self, name: &str) -> Result<Option<usize>> {
+        if name == DEFAULT_NAMESPACE {
+            anyhow::bail!("The default namespace can't be dropped");
+        }
+        // Collect first, since deleting while paging would shift the pages
+        let (Some(storage), Some(memories)) = (self.storage(name).await?, self.memories(name).await?) else {
+            return Ok(None);
+        };
+        for memory in &memories {
+            match storage.delete(&memory.content_hash).await {
+                Ok(_) | Err(StorageError::NotFound(_)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let mut namespaces = self.namespaces();
+        namespaces.remove(name);
+        self.save(&namespaces)?;
+        tracing::info!("Dropped namespace '{}' with {} memories", name, memories.len());
+        Ok(Some(memories.len()))
+    }
+
+    // Write the registry under a temporary name first, so a crash never leaves a partial one behind
+    fn save(&self, namespaces: &BTreeMap<String, NamespaceStorage>) -> Result<()> {
+        let Some(path) = &self.registry_path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!("Failed to create directory: {:?}", dir))?;
+        }
+        let names: Vec<&String> = namespaces.keys().filter(|name| name.as_str() != DEFAULT_NAMESPACE).collect();
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(&names)?).context(format!("Failed to write {:?}", temp_path))?;
+        std::fs::rename(&temp_path, path).context(format!("Failed to move namespace registry to {:?}", path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::storage::{conformance::memory, InMemoryStorage};
+
+    fn in_memory() -> Arc<dyn MemoryStorage> {
+        Arc::new(InMemoryStorage::new(Arc::new(DummyEmbeddingGenerator::new(8)), None, None, None, false).unwrap())
+    }
+
+    #[test]
+    fn names_must_fit_collection_names() {
+        for valid in ["a", "team-a", "project_2", "X9"] {
+            assert!(validate_namespace(valid).is_ok(), "{}", valid);
+        }
+        for invalid in ["", "-a", "a-", "a b", "a/b", "ä", &"a".repeat(MAX_NAMESPACE_LEN + 1)] {
+            assert!(validate_namespace(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn namespaces_are_isolated_and_registered() {
+        let path = std::env::temp_dir().join(format!("namespaces-test-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let opener = || -> NamespaceOpener { Box::new(|_| Box::pin(async { Ok(in_memory()) })) };
+
+        let namespaces = Namespaces::new(in_memory(), opener(), Some(path.clone())).unwrap();
+        assert!(namespaces.create("team-a").await.unwrap());
+        assert!(!namespaces.create("team-a").await.unwrap());
+        assert!(namespaces.create("not valid").await.is_err());
+
+        let team = namespaces.storage("team-a").await.unwrap().unwrap();
+        team.store(&memory("one", &[], 0, &[1.0, 0.0])).await.unwrap();
+        team.store(&memory("two", &[], 0, &[1.0, 0.0])).await.unwrap();
+        let default = namespaces.storage(DEFAULT_NAMESPACE).await.unwrap().unwrap();
+        assert!(default.get("hash-one").await.unwrap().is_none());
+        assert!(namespaces.storage("missing").await.unwrap().is_none());
+
+        // The registry survives a restart, though in-memory storages don't keep their memories
+        let namespaces = Namespaces::new(in_memory(), opener(), Some(path.clone())).unwrap();
+        assert_eq!(namespaces.list().await, ["default", "team-a"]);
+
+        let team = namespaces.storage("team-a").await.unwrap().unwrap();
+        team.store(&memory("three", &[], 0, &[1.0, 0.0])).await.unwrap();
+        assert_eq!(namespaces.remove("team-a").await.unwrap(), Some(1));
+        assert_eq!(namespaces.remove("team-a").await.unwrap(), None);
+        assert!(namespaces.remove(DEFAULT_NAMESPACE).await.is_err());
+        assert_eq!(namespaces.list().await, ["default"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_namespace_being_opened_does_not_hold_up_the_others() {
+        // Opening "slow" never finishes
+        let opener: NamespaceOpener = Box::new(|name| {
+            Box::pin(async move {
+                if name == "slow" {
+                    std::future::pending::<()>().await;
+                }
+                Ok(in_memory())
+            })
+        });
+        let namespaces = Arc::new(Namespaces::new(in_memory(), opener, None).unwrap());
+        assert!(namespaces.create("fast").await.unwrap());
+
+        let creating = tokio::spawn({
+            let namespaces = namespaces.clone();
+            async move { namespaces.create("slow").await }
+        });
+        tokio::task::yield_now().await;
+
+        let timeout = std::time::Duration::from_secs(1);
+        let default = tokio::time::timeout(timeout, namespaces.storage(DEFAULT_NAMESPACE)).await.expect("default namespace blocked");
+        assert!(default.unwrap().is_some());
+        let fast = tokio::time::timeout(timeout, namespaces.storage("fast")).await.expect("other namespace blocked");
+        assert!(fast.unwrap().is_some());
+        assert_eq!(namespaces.list().await, ["default", "fast"]);
+        creating.abort();
+    }
+}
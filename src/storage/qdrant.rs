@@ -0,0 +1,623 @@
+use crate::models::{Memory, MemoryPage, MemoryQueryResult};
+use crate::embeddings::EmbeddingGenerator;
+use super::{check_time_range, duplicate_or_conflict, DeleteOutcome, MemoryStorage, StorageError, StorageResult, StoreOutcome};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, Response};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc};
+use url::Url;
+
+// Payload fields that get an index, with their Qdrant schema type
+const PAYLOAD_INDEXES: &[(&str, &str)] = &[
+    ("content_hash", "keyword"),
+    ("tags", "keyword"),
+    ("memory_type", "keyword"),
+    ("timestamp_seconds", "integer"),
+];
+
+// Page size when scrolling through filter results
+const SCROLL_PAGE_SIZE: usize = 256;
+
+/// Qdrant storage implementation over the REST API
+pub struct QdrantMemoryStorage {
+    client: Client,
+    base_url: Url,
+    collection_name: String,
+    api_key: Option<String>,
+    embedding_generator: Arc<dyn EmbeddingGenerator>,
+}
+
+impl QdrantMemoryStorage {
+    /// Create a new Qdrant storage instance, creating the collection if needed
+    pub async fn new(
+        base_url: Url,
+        collection_name: String,
+        api_key: Option<String>,
+        embedding_generator: Arc<dyn EmbeddingGenerator>,
+    ) -> Result<Self> {
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .build()
+            .context("Failed to create HTTP client")?;
+
+        let storage = Self {
+            client,
+            base_url,
+            collection_name,
+            api_key,
+            embedding_generator,
+        };
+
+        storage.ensure_collection_exists().await?;
+
+        Ok(storage)
+    }
+
+    /// Point id for a memory. Qdrant only accepts integers and UUIDs, so the
+    /// content hash is mapped to a UUID; the hash itself is kept in the payload.
+    fn point_id(content_hash: &str) -> String {
+        let digest = hex::encode(&Sha256::digest(content_hash.as_bytes())[..16]);
+        format!("{}-{}-{}-{}-{}", &digest[0..8], &digest[8..12], &digest[12..16], &digest[16..20], &digest[20..32])
+    }
+
+    fn collection_url(&self, path: &str) -> Result<Url> {
+        Ok(self.base_url.join(&format!("/collections/{}{}", self.collection_name, path))?)
+    }
+
+    fn with_api_key(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.api_key {
+            Some(key) => request.header("api-key", key),
+            None => request,
+        }
+    }
+
+    /// Send a request, turning error statuses into errors. Failures to reach
+    /// the server and server errors are `Unavailable`.
+    async fn send(&self, request: RequestBuilder, action: &str) -> Result<Response> {
+        let response = self.with_api_key(request)
+            .send()
+            .await
+            .map_err(|e| StorageError::Unavailable(format!("Failed to {}: {}", action, e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            let message = format!("Failed to {}: {} {}", action, status, body);
+            if status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                return Err(StorageError::Unavailable(message).into());
+            }
+            return Err(anyhow::anyhow!(message));
+        }
+
+        Ok(response)
+    }
+
+    /// Ensure the collection and its payload indexes exist, creating them if necessary
+    async fn ensure_collection_exists(&self) -> Result<()> {
+        let response = self.with_api_key(self.client.get(self.collection_url("")?))
+            .send()
+            .await
+            .context("Failed to get collection")?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            self.send(
+                self.client.put(self.collection_url("")?).json(&json!({
+                    "vectors": {
+                        "size": self.embedding_generator.get_embedding_size(),
+                        "distance": "Cosine"
+                    }
+                })),
+                "create collection",
+            ).await?;
+
+            for (field_name, field_schema) in PAYLOAD_INDEXES {
+                self.send(
+                    self.client.put(self.collection_url("/index?wait=true")?).json(&json!({
+                        "field_name": field_name,
+                        "field_schema": field_schema
+                    })),
+                    "create payload index",
+                ).await?;
+            }
+        } else if !response.status().is_success() {
+            return Err(anyhow::anyhow!("Failed to get collection: {}", response.status()));
+        }
+
+        Ok(())
+    }
+
+    /// Format a memory as a Qdrant payload
+    fn format_payload(memory: &Memory) -> serde_json::Value {
+        json!({
+            "content": memory.content,
+            "content_hash": memory.content_hash,
+            "tags": memory.tags,
+            "memory_type": memory.memory_type,
+            "timestamp_seconds": memory.timestamp_seconds,
+            "metadata": memory.metadata,
+        })
+    }
+
+    /// Parse a point (with payload and vector) back to a Memory
+    fn parse_point(point: &serde_json::Value) -> Option<Memory> {
+        let payload = point.get("payload")?;
+        let metadata: HashMap<String, String> = payload.get("metadata")
+            .and_then(|v| serde_json::from_value(v.clone()).ok())
+            .unwrap_or_default();
+        let embedding = point.get("vector")
+            .and_then(|v| v.as_array())
+            .map(|arr| arr.iter().filter_map(|v| v.as_f64().map(|d| d as f32)).collect::<Vec<f32>>());
+
+        Some(Memory {
+            content: payload.get("content")?.as_str()?.to_string(),
+            content_hash: payload.get("content_hash")?.as_str()?.to_string(),
+            tags: payload.get("tags")
+                .and_then(|v| serde_json::from_value(v.clone()).ok())
+                .unwrap_or_default(),
+            memory_type: payload.get("memory_type").and_then(|v| v.as_str()).map(|s| s.to_string()),
+            timestamp_seconds: payload.get("timestamp_seconds")
+                .and_then(|v| v.as_i64())
+                .unwrap_or_else(|| chrono::Utc::now().timestamp()),
+            metadata,
+            embedding,
+        })
+    }
+
+    /// Fetch one scroll page of memories matching a payload filter, with the
+    /// offset of the next page (null after the last one)
+    async fn scroll_page(&self, filter: &serde_json::Value, offset: serde_json::Value, limit: usize) -> Result<(Vec<Memory>, serde_json::Value)> {
+        let response = self.send(
+            self.client.post(self.collection_url("/points/scroll")?).json(&json!({
+                "filter": filter,
+                "limit": limit,
+                "offset": offset,
+                "with_payload": true,
+                "with_vector": true
+            })),
+            "scroll memories",
+        ).await?;
+
+        let result: serde_json::Value = response.json().await
+            .context("Failed to parse scroll response")?;
+
+        let memories = result["result"]["points"].as_array()
+            .map(|points| points.iter().filter_map(Self::parse_point).collect())
+            .unwrap_or_default();
+        Ok((memories, result["result"]["next_page_offset"].clone()))
+    }
+
+    /// Fetch all memories matching a payload filter, following scroll pages
+    async fn scroll(&self, filter: serde_json::Value) -> Result<Vec<Memory>> {
+        let mut memories = Vec::new();
+        let mut offset = serde_json::Value::Null;
+
+        loop {
+            let (page, next_offset) = self.scroll_page(&filter, offset, SCROLL_PAGE_SIZE).await?;
+            memories.extend(page);
+
+            offset = next_offset;
+            if offset.is_null() {
+                break;
+            }
+        }
+
+        Ok(memories)
+    }
+}
+
+#[async_trait]
+impl MemoryStorage for QdrantMemoryStorage {
+    async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool> {
+        Ok(self.get(content_hash).await?.is_some())
+    }
+
+    async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+        if let Some(existing) = self.get(&memory.content_hash).await? {
+            return duplicate_or_conflict(memory, &existing.content);
+        }
+
+        // Generate embedding if not already present
+        let embedding = if let Some(ref emb) = memory.embedding {
+            emb.clone()
+        } else {
+            self.embedding_generator.generate_embedding(&memory.content).await?
+        };
+
+        self.send(
+            self.client.put(self.collection_url("/points?wait=true")?).json(&json!({
+                "points": [{
+                    "id": Self::point_id(&memory.content_hash),
+                    "vector": embedding,
+                    "payload": Self::format_payload(memory)
+                }]
+            })),
+            "store memory",
+        ).await?;
+
+        Ok(StoreOutcome::Stored)
+    }
+
+    async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>> {
+        let response = self.send(
+            self.client.post(self.collection_url("/points/search")?).json(&json!({
+                "vector": query_embedding,
+                "limit": n_results,
+                "with_payload": true,
+                "with_vector": true
+            })),
+            "query memories",
+        ).await?;
+
+        let result: serde_json::Value = response.json().await
+            .context("Failed to parse query response")?;
+
+        // With cosine distance, Qdrant's score is already the similarity
+        let results = result["result"].as_array()
+            .map(|points| {
+                points.iter()
+                    .filter_map(|point| {
+                        Some(MemoryQueryResult {
+                            memory: Self::parse_point(point)?,
+                            relevance_score: point.get("score")?.as_f64()? as f32,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(results)
+    }
+
+    async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>> {
+        let response = self.send(
+            self.client.post(self.collection_url("/points")?).json(&json!({
+                "ids": [Self::point_id(content_hash)],
+                "with_payload": true,
+                "with_vector": true
+            })),
+            "get memory",
+        ).await?;
+
+        let result: serde_json::Value = response.json().await
+            .context("Failed to parse get response")?;
+
+        Ok(result["result"].as_array()
+            .and_then(|points| points.first())
+            .and_then(Self::parse_point))
+    }
+
+    async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>> {
+        if tags.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(self.scroll(json!({
+            "must": [{ "key": "tags", "match": { "any": tags } }]
+        })).await?)
+    }
+
+    async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>> {
+        check_time_range(start_seconds, end_seconds)?;
+        Ok(self.scroll(json!({
+            "must": [{ "key": "timestamp_seconds", "range": { "gte": start_seconds, "lte": end_seconds } }]
+        })).await?)
+    }
+
+    async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+        if !self.check_duplicate_exists(content_hash).await? {
+            return Err(StorageError::NotFound(content_hash.to_string()));
+        }
+
+        self.send(
+            self.client.post(self.collection_url("/points/delete?wait=true")?).json(&json!({
+                "points": [Self::point_id(content_hash)]
+            })),
+            "delete memory",
+        ).await?;
+
+        Ok(DeleteOutcome::Deleted)
+    }
+
+    async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage> {
+        // The cursor is Qdrant's next page offset (a point id) as JSON
+        let offset = match cursor {
+            Some(cursor) => serde_json::from_str(cursor)
+                .map_err(|_| StorageError::InvalidFilter(format!("Invalid Qdrant list cursor: {}", cursor)))?,
+            None => serde_json::Value::Null,
+        };
+        let (memories, next_offset) = self.scroll_page(&serde_json::Value::Null, offset, limit).await?;
+        let next_cursor = (!next_offset.is_null()).then(|| next_offset.to_string());
+        Ok(MemoryPage { memories, next_cursor })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::storage::conformance::{memory, EMBEDDING_SIZE};
+    use axum::{
+        extract::{Path, State},
+        http::StatusCode,
+        routing::{get, post, put},
+        Json, Router,
+    };
+    use std::sync::Mutex;
+
+    /// Minimal in-process stand-in for the parts of the Qdrant REST API the storage uses
+    #[derive(Default)]
+    struct FakeQdrant {
+        collections: HashMap<String, FakeCollection>,
+    }
+
+    #[derive(Default)]
+    struct FakeCollection {
+        vector_size: usize,
+        indexes: HashMap<String, String>,
+        points: HashMap<String, (Vec<f32>, serde_json::Value)>,
+    }
+
+    type Shared = Arc<Mutex<FakeQdrant>>;
+
+    fn matches(filter: &serde_json::Value, payload: &serde_json::Value) -> bool {
+        filter["must"].as_array().into_iter().flatten().all(|condition| {
+            let value = &payload[condition["key"].as_str().unwrap()];
+            if let Some(any) = condition["match"]["any"].as_array() {
+                match value.as_array() {
+                    Some(values) => values.iter().any(|v| any.contains(v)),
+                    None => any.contains(value),
+                }
+            } else if let Some(range) = condition.get("range") {
+                let value = value.as_f64().unwrap_or(f64::NAN);
+                range["gte"].as_f64().is_none_or(|gte| value >= gte)
+                    && range["lte"].as_f64().is_none_or(|lte| value <= lte)
+            } else {
+                false
+            }
+        })
+    }
+
+    fn point_json(id: &str, vector: &[f32], payload: &serde_json::Value) -> serde_json::Value {
+        json!({ "id": id, "vector": vector, "payload": payload })
+    }
+
+    async fn get_collection(State(state): State<Shared>, Path(name): Path<String>) -> (StatusCode, Json<serde_json::Value>) {
+        match state.lock().unwrap().collections.get(&name) {
+            Some(collection) => (StatusCode::OK, Json(json!({ "result": { "config": { "params": { "vectors": { "size": collection.vector_size } } } } }))),
+            None => (StatusCode::NOT_FOUND, Json(json!({ "status": { "error": "Not found" } }))),
+        }
+    }
+
+    async fn create_collection(State(state): State<Shared>, Path(name): Path<String>, Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        let collection = FakeCollection {
+            vector_size: body["vectors"]["size"].as_u64().unwrap() as usize,
+            ..Default::default()
+        };
+        state.lock().unwrap().collections.insert(name, collection);
+        Json(json!({ "result": true }))
+    }
+
+    async fn create_index(State(state): State<Shared>, Path(name): Path<String>, Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        let mut state = state.lock().unwrap();
+        let collection = state.collections.get_mut(&name).unwrap();
+        collection.indexes.insert(
+            body["field_name"].as_str().unwrap().to_string(),
+            body["field_schema"].as_str().unwrap().to_string(),
+        );
+        Json(json!({ "result": { "status": "completed" } }))
+    }
+
+    async fn upsert_points(State(state): State<Shared>, Path(name): Path<String>, Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        let mut state = state.lock().unwrap();
+        let collection = state.collections.get_mut(&name).unwrap();
+        for point in body["points"].as_array().unwrap() {
+            let vector: Vec<f32> = serde_json::from_value(point["vector"].clone()).unwrap();
+            assert_eq!(vector.len(), collection.vector_size);
+            collection.points.insert(point["id"].as_str().unwrap().to_string(), (vector, point["payload"].clone()));
+        }
+        Json(json!({ "result": { "status": "completed" } }))
+    }
+
+    async fn retrieve_points(State(state): State<Shared>, Path(name): Path<String>, Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        let state = state.lock().unwrap();
+        let collection = &state.collections[&name];
+        let points: Vec<_> = body["ids"].as_array().unwrap().iter()
+            .filter_map(|id| {
+                let id = id.as_str().unwrap();
+                collection.points.get(id).map(|(vector, payload)| point_json(id, vector, payload))
+            })
+            .collect();
+        Json(json!({ "result": points }))
+    }
+
+    async fn search_points(State(state): State<Shared>, Path(name): Path<String>, Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        let state = state.lock().unwrap();
+        let collection = &state.collections[&name];
+        let query: Vec<f32> = serde_json::from_value(body["vector"].clone()).unwrap();
+        let mut scored: Vec<_> = collection.points.iter()
+            .map(|(id, (vector, payload))| {
+                let mut point = point_json(id, vector, payload);
+                point["score"] = json!(crate::storage::cosine_similarity(&query, vector));
+                point
+            })
+            .collect();
+        scored.sort_by(|a, b| b["score"].as_f64().partial_cmp(&a["score"].as_f64()).unwrap());
+        scored.truncate(body["limit"].as_u64().unwrap() as usize);
+        Json(json!({ "result": scored }))
+    }
+
+    async fn scroll_points(State(state): State<Shared>, Path(name): Path<String>, Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        let state = state.lock().unwrap();
+        let collection = &state.collections[&name];
+        let mut ids: Vec<&String> = collection.points.keys().collect();
+        ids.sort();
+
+        // Offsets are point ids, as in Qdrant
+        let start = body["offset"].as_str()
+            .and_then(|offset| ids.iter().position(|id| id.as_str() == offset))
+            .unwrap_or(0);
+        let limit = body["limit"].as_u64().unwrap() as usize;
+        let matching: Vec<&String> = ids[start..].iter()
+            .copied()
+            .filter(|id| matches(&body["filter"], &collection.points[*id].1))
+            .collect();
+
+        let points: Vec<_> = matching.iter().take(limit)
+            .map(|id| point_json(id, &collection.points[*id].0, &collection.points[*id].1))
+            .collect();
+        let next_page_offset = matching.get(limit).map(|id| json!(id)).unwrap_or(serde_json::Value::Null);
+        Json(json!({ "result": { "points": points, "next_page_offset": next_page_offset } }))
+    }
+
+    async fn delete_points(State(state): State<Shared>, Path(name): Path<String>, Json(body): Json<serde_json::Value>) -> Json<serde_json::Value> {
+        let mut state = state.lock().unwrap();
+        let collection = state.collections.get_mut(&name).unwrap();
+        for id in body["points"].as_array().unwrap() {
+            collection.points.remove(id.as_str().unwrap());
+        }
+        Json(json!({ "result": { "status": "completed" } }))
+    }
+
+    async fn start_fake_qdrant() -> (Url, Shared) {
+        let state = Shared::default();
+        let router = Router::new()
+            .route("/collections/{name}", get(get_collection).put(create_collection))
+            .route("/collections/{name}/index", put(create_index))
+            .route("/collections/{name}/points", put(upsert_points).post(retrieve_points))
+            .route("/collections/{name}/points/search", post(search_points))
+            .route("/collections/{name}/points/scroll", post(scroll_points))
+            .route("/collections/{name}/points/delete", post(delete_points))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        (Url::parse(&format!("http://{}", addr)).unwrap(), state)
+    }
+
+    async fn storage() -> (QdrantMemoryStorage, Shared) {
+        let (url, state) = start_fake_qdrant().await;
+        let storage = QdrantMemoryStorage::new(
+            url,
+            "memories".to_string(),
+            None,
+            Arc::new(DummyEmbeddingGenerator::new(EMBEDDING_SIZE)),
+        ).await.unwrap();
+        (storage, state)
+    }
+
+    #[tokio::test]
+    async fn creates_collection_with_payload_indexes() {
+        let (_storage, state) = storage().await;
+        let state = state.lock().unwrap();
+        let collection = &state.collections["memories"];
+        assert_eq!(collection.vector_size, EMBEDDING_SIZE);
+        for (field_name, field_schema) in PAYLOAD_INDEXES {
+            assert_eq!(collection.indexes.get(*field_name).map(String::as_str), Some(*field_schema));
+        }
+    }
+
+    #[tokio::test]
+    async fn stores_and_gets_memories() {
+        let (storage, _state) = storage().await;
+        let stored = memory("alpha", &["a"], 100, &[1.0, 0.0, 0.0, 0.0]);
+
+        assert_eq!(storage.store(&stored).await.unwrap(), StoreOutcome::Stored);
+        assert_eq!(storage.store(&stored).await.unwrap(), StoreOutcome::Duplicate);
+
+        let fetched = storage.get(&stored.content_hash).await.unwrap().unwrap();
+        assert_eq!(fetched.content, "alpha");
+        assert_eq!(fetched.tags, vec!["a".to_string()]);
+        assert_eq!(fetched.memory_type.as_deref(), Some("note"));
+        assert_eq!(fetched.timestamp_seconds, 100);
+        assert_eq!(fetched.metadata.get("source").map(String::as_str), Some("conformance"));
+        assert_eq!(fetched.embedding, stored.embedding);
+        assert!(storage.get("hash-missing").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn retrieves_by_similarity() {
+        let (storage, _state) = storage().await;
+        storage.store(&memory("x", &[], 1, &[1.0, 0.0, 0.0, 0.0])).await.unwrap();
+        storage.store(&memory("y", &[], 2, &[0.0, 1.0, 0.0, 0.0])).await.unwrap();
+        storage.store(&memory("xy", &[], 3, &[0.7, 0.7, 0.0, 0.0])).await.unwrap();
+
+        let results = storage.retrieve(&[1.0, 0.1, 0.0, 0.0], 2).await.unwrap();
+        let contents: Vec<&str> = results.iter().map(|result| result.memory.content.as_str()).collect();
+        assert_eq!(contents, vec!["x", "xy"]);
+        assert!(results[0].relevance_score > results[1].relevance_score);
+    }
+
+    #[tokio::test]
+    async fn searches_by_tag_and_time_range() {
+        let (storage, _state) = storage().await;
+        // More memories than fit on one scroll page
+        for i in 0..(SCROLL_PAGE_SIZE + 10) {
+            let tags: &[&str] = if i % 2 == 0 { &["even"] } else { &["odd"] };
+            storage.store(&memory(&format!("m{}", i), tags, i as i64, &[1.0, 0.0, 0.0, 0.0])).await.unwrap();
+        }
+
+        let even = storage.search_by_tag(&["even".to_string()]).await.unwrap();
+        assert_eq!(even.len(), (SCROLL_PAGE_SIZE + 10) / 2);
+        assert!(even.iter().all(|memory| memory.tags == vec!["even".to_string()]));
+
+        let both = storage.search_by_tag(&["even".to_string(), "odd".to_string()]).await.unwrap();
+        assert_eq!(both.len(), SCROLL_PAGE_SIZE + 10);
+
+        let window = storage.search_by_time_range(10, 19).await.unwrap();
+        assert_eq!(window.len(), 10);
+        assert!(window.iter().all(|memory| (10..=19).contains(&memory.timestamp_seconds)));
+    }
+
+    #[tokio::test]
+    async fn lists_every_memory_in_pages() {
+        let (storage, _state) = storage().await;
+        for i in 0..25 {
+            storage.store(&memory(&format!("m{}", i), &["a"], i, &[1.0, 0.0, 0.0, 0.0])).await.unwrap();
+        }
+
+        let mut listed = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = storage.list(cursor.as_deref(), 10).await.unwrap();
+            assert!(page.memories.len() <= 10);
+            assert!(page.memories.iter().all(|memory| memory.embedding.is_some()));
+            listed.extend(page.memories.into_iter().map(|memory| memory.content_hash));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        listed.sort();
+        listed.dedup();
+        assert_eq!(listed.len(), 25);
+    }
+
+    #[tokio::test]
+    async fn deletes_memories() {
+        let (storage, _state) = storage().await;
+        let stored = memory("gone", &["a"], 1, &[0.0, 0.0, 1.0, 0.0]);
+        storage.store(&stored).await.unwrap();
+
+        assert_eq!(storage.delete(&stored.content_hash).await.unwrap(), DeleteOutcome::Deleted);
+        assert!(storage.get(&stored.content_hash).await.unwrap().is_none());
+        assert!(matches!(storage.delete(&stored.content_hash).await, Err(StorageError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn conforms() {
+        let (url, _state) = start_fake_qdrant().await;
+        let opened = std::sync::atomic::AtomicUsize::new(0);
+        crate::storage::conformance::check_conformance(|| async {
+            let collection = format!("conformance-{}", opened.fetch_add(1, std::sync::atomic::Ordering::SeqCst));
+            QdrantMemoryStorage::new(url.clone(), collection, None, Arc::new(DummyEmbeddingGenerator::new(EMBEDDING_SIZE)))
+                .await
+                .unwrap()
+        })
+        .await;
+    }
+}
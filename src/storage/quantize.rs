@@ -0,0 +1,394 @@
+use super::vector::dot;
+use std::str::FromStr;
+
+/// Candidates fetched per requested result with int8 codes by default
+pub const DEFAULT_INT8_OVERSAMPLING: usize = 3;
+/// Candidates fetched per requested result with binary codes by default
+pub const DEFAULT_BINARY_OVERSAMPLING: usize = 10;
+
+// Accumulators in `dot_int8`, as in `vector::dot`
+const LANES: usize = 8;
+
+/// Compressed form of the embeddings searched by the in-process backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantization {
+    /// One signed byte per dimension plus a scale per vector, a quarter of the size
+    Int8,
+    /// One bit per dimension, a thirty-second of the size
+    Binary,
+}
+
+impl FromStr for Quantization {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "int8" => Ok(Self::Int8),
+            "binary" => Ok(Self::Binary),
+            other => anyhow::bail!("Unknown quantization {:?}, expected int8 or binary", other),
+        }
+    }
+}
+
+/// Quantised search settings. Candidates are found with the compressed
+/// vectors and rescored against the full-precision ones.
+#[derive(Debug, Clone)]
+pub struct QuantizationConfig {
+    pub quantization: Quantization,
+    /// Candidates fetched per requested result before rescoring. Higher
+    /// recovers more of the exact ranking, at the cost of more rescoring
+    pub oversampling: usize,
+}
+
+impl QuantizationConfig {
+    pub fn new(quantization: Quantization) -> Self {
+        let oversampling = match quantization {
+            Quantization::Int8 => DEFAULT_INT8_OVERSAMPLING,
+            Quantization::Binary => DEFAULT_BINARY_OVERSAMPLING,
+        };
+        Self { quantization, oversampling }
+    }
+
+    /// Number of candidates to fetch for `n_results` results
+    pub(super) fn candidates(&self, n_results: usize) -> usize {
+        n_results.saturating_mul(self.oversampling.max(1))
+    }
+}
+
+/// A normalised query prepared for scoring against codes
+pub(super) struct QueryCode {
+    vector: Vec<f32>,
+    // Sign bits, only for binary codes
+    bits: Vec<u8>,
+}
+
+impl Quantization {
+    /// Tag stored ahead of codes persisted by the SQLite backend, so codes of
+    /// another kind are recognised after the setting changes
+    pub(super) fn tag(self) -> u8 {
+        match self {
+            Self::Int8 => 1,
+            Self::Binary => 2,
+        }
+    }
+
+    /// Bytes taken by the code of a vector with this many dimensions
+    pub fn code_len(self, dimensions: usize) -> usize {
+        match self {
+            Self::Int8 => 4 + dimensions,
+            Self::Binary => dimensions.div_ceil(8),
+        }
+    }
+
+    /// Append the code of a normalised vector
+    pub(super) fn encode(self, vector: &[f32], out: &mut Vec<u8>) {
+        match self {
+            Self::Int8 => {
+                // Symmetric scale, so the largest component maps to ±127
+                let max = vector.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+                let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+                out.extend_from_slice(&scale.to_le_bytes());
+                out.extend(vector.iter().map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8 as u8));
+            }
+            Self::Binary => out.extend(sign_bits(vector)),
+        }
+    }
+
+    /// Approximate vector back from its code
+    pub(super) fn decode(self, code: &[u8], dimensions: usize) -> Vec<f32> {
+        match self {
+            Self::Int8 => {
+                let scale = f32::from_le_bytes([code[0], code[1], code[2], code[3]]);
+                code[4..].iter().map(|&byte| byte as i8 as f32 * scale).collect()
+            }
+            Self::Binary => {
+                let magnitude = 1.0 / (dimensions.max(1) as f32).sqrt();
+                (0..dimensions)
+                    .map(|i| if code[i / 8] & (1 << (i % 8)) != 0 { magnitude } else { -magnitude })
+                    .collect()
+            }
+        }
+    }
+
+    pub(super) fn prepare(self, vector: Vec<f32>) -> QueryCode {
+        let bits = match self {
+            Self::Int8 => Vec::new(),
+            Self::Binary => sign_bits(&vector).collect(),
+        };
+        QueryCode { vector, bits }
+    }
+
+    /// Estimated cosine similarity between a prepared query and a code
+    pub(super) fn score(self, query: &QueryCode, code: &[u8]) -> f32 {
+        match self {
+            // Only the stored side is quantised, which keeps more precision than comparing two codes
+            Self::Int8 => f32::from_le_bytes([code[0], code[1], code[2], code[3]]) * dot_int8(&query.vector, &code[4..]),
+            Self::Binary => hamming_similarity(&query.bits, code, query.vector.len()),
+        }
+    }
+
+    /// Estimated cosine similarity between two codes
+    pub(super) fn similarity(self, a: &[u8], b: &[u8], dimensions: usize) -> f32 {
+        match self {
+            Self::Int8 => {
+                let scale_a = f32::from_le_bytes([a[0], a[1], a[2], a[3]]);
+                let scale_b = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+                let sum: i32 = a[4..].iter().zip(&b[4..]).map(|(&x, &y)| x as i8 as i32 * y as i8 as i32).sum();
+                sum as f32 * scale_a * scale_b
+            }
+            Self::Binary => hamming_similarity(a, b, dimensions),
+        }
+    }
+}
+
+fn sign_bits(vector: &[f32]) -> impl Iterator<Item = u8> + '_ {
+    vector
+        .chunks(8)
+        .map(|chunk| chunk.iter().enumerate().fold(0u8, |bits, (i, x)| if *x > 0.0 { bits | (1 << i) } else { bits }))
+}
+
+// Agreement of sign bits, scaled to [-1, 1] like a cosine similarity
+fn hamming_similarity(a: &[u8], b: &[u8], dimensions: usize) -> f32 {
+    let (words_a, words_b) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail: u32 = words_a.remainder().iter().zip(words_b.remainder()).map(|(x, y)| (x ^ y).count_ones()).sum();
+    let differing: u32 = words_a
+        .zip(words_b)
+        .map(|(x, y)| (u64::from_le_bytes(x.try_into().unwrap_or_default()) ^ u64::from_le_bytes(y.try_into().unwrap_or_default())).count_ones())
+        .sum::<u32>()
+        + tail;
+    1.0 - 2.0 * differing as f32 / dimensions.max(1) as f32
+}
+
+// Dot product of a float vector with signed byte codes
+fn dot_int8(a: &[f32], codes: &[u8]) -> f32 {
+    let len = a.len().min(codes.len());
+    let (a, codes) = (&a[..len], &codes[..len]);
+
+    let mut sums = [0.0f32; LANES];
+    let (chunks_a, chunks_codes) = (a.chunks_exact(LANES), codes.chunks_exact(LANES));
+    let tail: f32 = chunks_a.remainder().iter().zip(chunks_codes.remainder()).map(|(x, &y)| x * y as i8 as f32).sum();
+    for (chunk_a, chunk_codes) in chunks_a.zip(chunks_codes) {
+        for ((sum, x), &y) in sums.iter_mut().zip(chunk_a).zip(chunk_codes) {
+            *sum += x * y as i8 as f32;
+        }
+    }
+    sums.iter().sum::<f32>() + tail
+}
+
+/// Normalised vectors stored contiguously, either at full precision or as
+/// codes of one fixed length
+#[derive(Default)]
+pub(super) struct Vectors {
+    quantization: Option<Quantization>,
+    dimensions: usize,
+    full: Vec<f32>,
+    codes: Vec<u8>,
+    len: usize,
+}
+
+/// A query prepared for scoring against `Vectors`
+pub(super) enum Query {
+    Full(Vec<f32>),
+    Code(QueryCode),
+}
+
+impl Vectors {
+    pub(super) fn new(quantization: Option<Quantization>) -> Self {
+        Self { quantization, ..Default::default() }
+    }
+
+    pub(super) fn quantization(&self) -> Option<Quantization> {
+        self.quantization
+    }
+
+    /// Dimensions of every vector, set by the first one pushed
+    pub(super) fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// Bytes held by the vectors
+    pub(super) fn bytes(&self) -> usize {
+        self.full.len() * size_of::<f32>() + self.codes.len()
+    }
+
+    fn stride(&self) -> usize {
+        match self.quantization {
+            Some(quantization) => quantization.code_len(self.dimensions),
+            None => self.dimensions,
+        }
+    }
+
+    /// Append a normalised vector. The caller checks it has `dimensions()`
+    /// values once any vector has been pushed.
+    pub(super) fn push(&mut self, vector: &[f32]) {
+        if self.len == 0 {
+            self.dimensions = vector.len();
+        }
+        match self.quantization {
+            Some(quantization) => quantization.encode(vector, &mut self.codes),
+            None => self.full.extend_from_slice(vector),
+        }
+        self.len += 1;
+    }
+
+    /// Replace the vector at `index`
+    pub(super) fn set(&mut self, index: usize, vector: &[f32]) {
+        let stride = self.stride();
+        match self.quantization {
+            Some(quantization) => {
+                let mut code = Vec::with_capacity(stride);
+                quantization.encode(vector, &mut code);
+                self.codes[index * stride..(index + 1) * stride].copy_from_slice(&code);
+            }
+            None => self.full[index * stride..(index + 1) * stride].copy_from_slice(vector),
+        }
+    }
+
+    /// Remove the vector at `index` by moving the last one into its place
+    pub(super) fn swap_remove(&mut self, index: usize) {
+        let stride = self.stride();
+        let last = self.len - 1;
+        match self.quantization {
+            Some(_) => {
+                self.codes.copy_within(last * stride..(last + 1) * stride, index * stride);
+                self.codes.truncate(last * stride);
+            }
+            None => {
+                self.full.copy_within(last * stride..(last + 1) * stride, index * stride);
+                self.full.truncate(last * stride);
+            }
+        }
+        self.len = last;
+    }
+
+    /// The vector at `index`, approximated if it is stored as a code
+    pub(super) fn get(&self, index: usize) -> Vec<f32> {
+        let stride = self.stride();
+        match self.quantization {
+            Some(quantization) => quantization.decode(&self.codes[index * stride..(index + 1) * stride], self.dimensions),
+            None => self.full[index * stride..(index + 1) * stride].to_vec(),
+        }
+    }
+
+    pub(super) fn prepare(&self, query: Vec<f32>) -> Query {
+        match self.quantization {
+            Some(quantization) => Query::Code(quantization.prepare(query)),
+            None => Query::Full(query),
+        }
+    }
+
+    /// Similarity of a prepared query to the vector at `index`
+    pub(super) fn score(&self, query: &Query, index: usize) -> f32 {
+        let stride = self.stride();
+        match (query, self.quantization) {
+            (Query::Code(query), Some(quantization)) => quantization.score(query, &self.codes[index * stride..(index + 1) * stride]),
+            (Query::Full(query), _) => dot(query, &self.full[index * stride..(index + 1) * stride]),
+            (Query::Code(_), None) => unreachable!("query prepared for other vectors"),
+        }
+    }
+
+    /// Similarity between two stored vectors
+    pub(super) fn similarity(&self, a: usize, b: usize) -> f32 {
+        let stride = self.stride();
+        match self.quantization {
+            Some(quantization) => quantization.similarity(
+                &self.codes[a * stride..(a + 1) * stride],
+                &self.codes[b * stride..(b + 1) * stride],
+                self.dimensions,
+            ),
+            None => dot(&self.full[a * stride..(a + 1) * stride], &self.full[b * stride..(b + 1) * stride]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::models::Memory;
+    use crate::storage::{conformance::memory, cosine_similarity, vector::normalize, InMemoryStorage, MemoryStorage, SqliteMemoryStorage};
+    use std::sync::Arc;
+
+    fn vector(seed: usize, dimensions: usize) -> Vec<f32> {
+        normalize(&(0..dimensions).map(|i| (((seed * 31 + i * 17) % 101) as f32 - 50.0) / 50.0).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn int8_codes_approximate_cosine_similarity() {
+        for dimensions in [3, 8, 384] {
+            let (a, b) = (vector(1, dimensions), vector(2, dimensions));
+            let mut vectors = Vectors::new(Some(Quantization::Int8));
+            vectors.push(&a);
+            vectors.push(&b);
+            assert_eq!(vectors.bytes(), 2 * (4 + dimensions));
+
+            let exact = dot(&a, &b);
+            assert!((vectors.score(&vectors.prepare(a.clone()), 1) - exact).abs() < 0.02);
+            assert!((vectors.similarity(0, 1) - exact).abs() < 0.02);
+            assert!(vectors.get(0).iter().zip(&a).all(|(x, y)| (x - y).abs() < 0.01));
+        }
+    }
+
+    #[test]
+    fn binary_codes_count_matching_signs() {
+        let a = vec![0.5, -0.5, 0.5, -0.5, 0.5, -0.5, 0.5, -0.5, 0.5, 0.5];
+        let b = vec![0.5, 0.5, 0.5, -0.5, 0.5, -0.5, 0.5, -0.5, 0.5, -0.5];
+        let mut vectors = Vectors::new(Some(Quantization::Binary));
+        vectors.push(&a);
+        vectors.push(&b);
+        assert_eq!(vectors.bytes(), 4);
+
+        // Two of ten signs differ
+        assert!((vectors.similarity(0, 1) - 0.6).abs() < 1e-6);
+        assert!((vectors.score(&vectors.prepare(a), 0) - 1.0).abs() < 1e-6);
+        assert!(vectors.get(1).iter().zip(&b).all(|(x, y)| x.signum() == y.signum()));
+    }
+
+    #[test]
+    fn removing_moves_the_last_code() {
+        let mut vectors = Vectors::new(Some(Quantization::Int8));
+        for i in 0..4 {
+            vectors.push(&vector(i, 16));
+        }
+        vectors.swap_remove(1);
+        assert_eq!(vectors.bytes(), 3 * 20);
+        let query = vectors.prepare(vector(3, 16));
+        assert!((vectors.score(&query, 1) - 1.0).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    async fn rescoring_returns_full_precision_scores() {
+        let generator = Arc::new(DummyEmbeddingGenerator::new(64));
+        let path = std::env::temp_dir().join(format!("quantize-test-{}.db", std::process::id()));
+        let exact = InMemoryStorage::new(generator.clone(), None, None, None, false).unwrap();
+        let storages: Vec<Box<dyn MemoryStorage>> = vec![
+            Box::new(InMemoryStorage::new(generator.clone(), None, None, Some(QuantizationConfig::new(Quantization::Int8)), false).unwrap()),
+            Box::new(SqliteMemoryStorage::new(path.clone(), generator, Some(QuantizationConfig::new(Quantization::Int8)), false).unwrap()),
+        ];
+
+        // Without embeddings of their own, so that the in-memory store's re-derived ones match.
+        // The dummy generator's embeddings only depend on the length of the content.
+        for i in 0..300 {
+            let memory = Memory { embedding: None, ..memory(&"x".repeat(i + 1), &[], 0, &[]) };
+            exact.store(&memory).await.unwrap();
+            for storage in &storages {
+                storage.store(&memory).await.unwrap();
+            }
+        }
+
+        let query = vector(1000, 64);
+        let expected = exact.retrieve(&query, 10).await.unwrap();
+        for storage in &storages {
+            let found = storage.retrieve(&query, 10).await.unwrap();
+            assert_eq!(found.len(), 10);
+            for (found, expected) in found.iter().zip(&expected) {
+                assert!((found.relevance_score - expected.relevance_score).abs() < 1e-6);
+            }
+        }
+        // Only SQLite keeps the full-precision embeddings, on disk
+        let sqlite = storages[1].retrieve(&query, 1).await.unwrap();
+        assert_eq!(sqlite[0].relevance_score, cosine_similarity(&query, sqlite[0].memory.embedding.as_ref().unwrap()));
+        assert!(storages[0].get("hash-x").await.unwrap().unwrap().embedding.is_none());
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,419 @@
+use crate::models::{Memory, MemoryPage, MemoryQueryResult};
+use super::{DeleteOutcome, MemoryStorage, StorageError, StorageResult, StoreOutcome};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// How long a failed replica is left alone before it is tried again
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+// A write a replica missed while it was failing
+enum MissedWrite {
+    Store(String),
+    Delete(String),
+}
+
+impl MissedWrite {
+    fn content_hash(&self) -> &str {
+        match self {
+            Self::Store(content_hash) | Self::Delete(content_hash) => content_hash,
+        }
+    }
+}
+
+#[derive(Default)]
+struct ReplicaState {
+    // Time of the latest failure, while the replica is considered down
+    failed_at: Option<Instant>,
+    // Oldest first; at most one entry per content hash
+    missed: VecDeque<MissedWrite>,
+}
+
+struct Replica {
+    name: String,
+    storage: Arc<dyn MemoryStorage>,
+    state: Mutex<ReplicaState>,
+    // Held while missed writes are replayed, so only one caller does it
+    reconciling: tokio::sync::Mutex<()>,
+}
+
+impl Replica {
+    fn state(&self) -> std::sync::MutexGuard<'_, ReplicaState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn fail(&self, error: &StorageError) {
+        let mut state = self.state();
+        if state.failed_at.is_none() {
+            tracing::warn!("Storage replica {} failed, failing over: {:#}", self.name, error);
+        }
+        state.failed_at = Some(Instant::now());
+    }
+
+    fn record_missed(&self, write: MissedWrite) {
+        let mut state = self.state();
+        state.missed.retain(|missed| missed.content_hash() != write.content_hash());
+        state.missed.push_back(write);
+    }
+}
+
+/// Storage writing to a primary and one or more secondaries, and reading
+/// from the primary. When a replica errors it is skipped, with reads failing
+/// over to the next one, and the writes it misses are replayed to it once it
+/// answers again, which is tried at most every `retry_interval`.
+///
+/// Missed writes are only kept in memory, and list cursors are only
+/// meaningful to the replica that returned them.
+pub struct ReplicatedStorage {
+    replicas: Vec<Replica>,
+    retry_interval: Duration,
+}
+
+impl ReplicatedStorage {
+    /// Replicate over the named storages, the first being the primary
+    pub fn new(replicas: Vec<(String, Arc<dyn MemoryStorage>)>, retry_interval: Duration) -> Self {
+        let replicas = replicas
+            .into_iter()
+            .map(|(name, storage)| Replica {
+                name,
+                storage,
+                state: Mutex::new(ReplicaState::default()),
+                reconciling: tokio::sync::Mutex::new(()),
+            })
+            .collect();
+        Self { replicas, retry_interval }
+    }
+
+    // Whether the replica should be used now. A failed replica whose retry is
+    // due first gets the writes it missed, and is used if they all succeed.
+    async fn ready(&self, index: usize) -> bool {
+        let replica = &self.replicas[index];
+        match replica.state().failed_at {
+            None => return true,
+            Some(failed_at) if failed_at.elapsed() < self.retry_interval => return false,
+            Some(_) => {}
+        }
+
+        let Ok(_reconciling) = replica.reconciling.try_lock() else { return false };
+        let mut replayed = 0;
+        loop {
+            let write = {
+                let mut state = replica.state();
+                match state.missed.pop_front() {
+                    Some(write) => write,
+                    None => {
+                        if state.failed_at.take().is_some() {
+                            tracing::info!("Storage replica {} recovered after replaying {} missed writes", replica.name, replayed);
+                        }
+                        return true;
+                    }
+                }
+            };
+
+            let result = match &write {
+                MissedWrite::Store(content_hash) => match self.find(index, content_hash).await {
+                    Ok(Some(memory)) => replica.storage.store(&memory).await.map(|_| ()),
+                    // Deleted everywhere since; nothing to replay
+                    Ok(None) => Ok(()),
+                    Err(e) => Err(e),
+                },
+                MissedWrite::Delete(content_hash) => replica.storage.delete(content_hash).await.map(|_| ()),
+            };
+            // A replica refusing the write, e.g. deleting a memory it never got, already agrees with the others
+            if let Err(e) = result.or_else(|e| if e.is_rejection() { Ok(()) } else { Err(e) }) {
+                let mut state = replica.state();
+                state.failed_at = Some(Instant::now());
+                // Unless a newer write to the same memory came in meanwhile, retry this one first
+                if !state.missed.iter().any(|missed| missed.content_hash() == write.content_hash()) {
+                    state.missed.push_front(write);
+                }
+                tracing::debug!("Storage replica {} is still failing: {:#}", replica.name, e);
+                return false;
+            }
+            replayed += 1;
+        }
+    }
+
+    // A memory as held by the replicas other than `excluded`
+    async fn find(&self, excluded: usize, content_hash: &str) -> StorageResult<Option<Memory>> {
+        let mut last_error = None;
+        for (index, replica) in self.replicas.iter().enumerate() {
+            if index == excluded || replica.state().failed_at.is_some() {
+                continue;
+            }
+            match replica.storage.get(content_hash).await {
+                Ok(memory) => return Ok(memory),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| StorageError::Unavailable("No other storage replica is available".to_string())))
+    }
+
+    // Run a read against the first replica that answers. A rejection, such as
+    // an invalid filter, is an answer and isn't tried elsewhere.
+    async fn read<T, F, Fut>(&self, op: F) -> StorageResult<T>
+    where
+        F: Fn(Arc<dyn MemoryStorage>) -> Fut + Send + Sync,
+        Fut: Future<Output = StorageResult<T>> + Send,
+        T: Send,
+    {
+        let mut last_error = None;
+        for (index, replica) in self.replicas.iter().enumerate() {
+            if !self.ready(index).await {
+                continue;
+            }
+            match op(replica.storage.clone()).await {
+                Err(e) if !e.is_rejection() => {
+                    replica.fail(&e);
+                    last_error = Some(e);
+                }
+                answer => return answer,
+            }
+        }
+        Err(last_error.unwrap_or_else(|| StorageError::Unavailable("No storage replica is available".to_string())))
+    }
+
+    // Run a write against every replica, returning the first replica's
+    // answer, which may be a rejection. Replicas that are down or fail get
+    // the write replayed later, provided one of them answered.
+    async fn write<T, F, Fut>(&self, op: F, missed: impl Fn() -> MissedWrite + Send + Sync) -> StorageResult<T>
+    where
+        F: Fn(Arc<dyn MemoryStorage>) -> Fut + Send + Sync,
+        Fut: Future<Output = StorageResult<T>> + Send,
+        T: Send,
+    {
+        let mut result: Option<StorageResult<T>> = None;
+        let mut last_error = None;
+        let mut missed_by = Vec::new();
+        for (index, replica) in self.replicas.iter().enumerate() {
+            if !self.ready(index).await {
+                missed_by.push(index);
+                continue;
+            }
+            match op(replica.storage.clone()).await {
+                Err(e) if !e.is_rejection() => {
+                    replica.fail(&e);
+                    missed_by.push(index);
+                    last_error = Some(e);
+                }
+                answer => {
+                    result.get_or_insert(answer);
+                }
+            }
+        }
+
+        let Some(result) = result else {
+            return Err(last_error.unwrap_or_else(|| StorageError::Unavailable("No storage replica is available".to_string())));
+        };
+        for index in missed_by {
+            self.replicas[index].record_missed(missed());
+        }
+        result
+    }
+}
+
+#[async_trait]
+impl MemoryStorage for ReplicatedStorage {
+    async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+        self.write(
+            |storage| async move { storage.store(memory).await },
+            || MissedWrite::Store(memory.content_hash.clone()),
+        )
+        .await
+    }
+
+    async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>> {
+        self.read(|storage| async move { storage.retrieve(query_embedding, n_results).await }).await
+    }
+
+    async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>> {
+        self.read(|storage| async move { storage.get(content_hash).await }).await
+    }
+
+    async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>> {
+        self.read(|storage| async move { storage.search_by_tag(tags).await }).await
+    }
+
+    async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>> {
+        self.read(|storage| async move { storage.search_by_time_range(start_seconds, end_seconds).await }).await
+    }
+
+    async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+        self.write(
+            |storage| async move { storage.delete(content_hash).await },
+            || MissedWrite::Delete(content_hash.to_string()),
+        )
+        .await
+    }
+
+    async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool> {
+        self.read(|storage| async move { storage.check_duplicate_exists(content_hash).await }).await
+    }
+
+    async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage> {
+        self.read(|storage| async move { storage.list(cursor, limit).await }).await
+    }
+}
+
+/// Opens a storage backend
+pub type StorageOpener = Box<dyn Fn() -> Pin<Box<dyn Future<Output = Result<Arc<dyn MemoryStorage>>> + Send>> + Send + Sync>;
+
+/// Storage that is opened on first use, and again on every later use until
+/// opening succeeds, for backends that were unreachable at startup
+pub struct DeferredStorage {
+    opener: StorageOpener,
+    storage: tokio::sync::OnceCell<Arc<dyn MemoryStorage>>,
+}
+
+impl DeferredStorage {
+    pub fn new(opener: StorageOpener) -> Self {
+        Self { opener, storage: tokio::sync::OnceCell::new() }
+    }
+
+    // A backend that can't be opened yet is unavailable
+    async fn storage(&self) -> StorageResult<&Arc<dyn MemoryStorage>> {
+        self.storage.get_or_try_init(|| (self.opener)()).await.map_err(|e| StorageError::Unavailable(format!("{:#}", e)))
+    }
+}
+
+#[async_trait]
+impl MemoryStorage for DeferredStorage {
+    async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+        self.storage().await?.store(memory).await
+    }
+
+    async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>> {
+        self.storage().await?.retrieve(query_embedding, n_results).await
+    }
+
+    async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>> {
+        self.storage().await?.get(content_hash).await
+    }
+
+    async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>> {
+        self.storage().await?.search_by_tag(tags).await
+    }
+
+    async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>> {
+        self.storage().await?.search_by_time_range(start_seconds, end_seconds).await
+    }
+
+    async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+        self.storage().await?.delete(content_hash).await
+    }
+
+    async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool> {
+        self.storage().await?.check_duplicate_exists(content_hash).await
+    }
+
+    async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage> {
+        self.storage().await?.list(cursor, limit).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::storage::{conformance::memory, InMemoryStorage};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    // Storage that errors while switched off
+    struct Flaky {
+        inner: InMemoryStorage,
+        up: AtomicBool,
+    }
+
+    impl Flaky {
+        fn check(&self) -> StorageResult<()> {
+            if self.up.load(Ordering::SeqCst) { Ok(()) } else { Err(StorageError::Unavailable("connection refused".to_string())) }
+        }
+    }
+
+    #[async_trait]
+    impl MemoryStorage for Flaky {
+        async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+            self.check()?;
+            self.inner.store(memory).await
+        }
+        async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>> {
+            self.check()?;
+            self.inner.retrieve(query_embedding, n_results).await
+        }
+        async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>> {
+            self.check()?;
+            self.inner.get(content_hash).await
+        }
+        async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>> {
+            self.check()?;
+            self.inner.search_by_tag(tags).await
+        }
+        async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>> {
+            self.check()?;
+            self.inner.search_by_time_range(start_seconds, end_seconds).await
+        }
+        async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+            self.check()?;
+            self.inner.delete(content_hash).await
+        }
+        async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool> {
+            self.check()?;
+            self.inner.check_duplicate_exists(content_hash).await
+        }
+        async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage> {
+            self.check()?;
+            self.inner.list(cursor, limit).await
+        }
+    }
+
+    fn in_memory() -> InMemoryStorage {
+        InMemoryStorage::new(Arc::new(DummyEmbeddingGenerator::new(4)), None, None, None, false).unwrap()
+    }
+
+    #[tokio::test]
+    async fn fails_over_and_reconciles_the_primary() {
+        let primary = Arc::new(Flaky { inner: in_memory(), up: AtomicBool::new(true) });
+        let secondary = Arc::new(in_memory());
+        let storage = ReplicatedStorage::new(
+            vec![("primary".to_string(), primary.clone()), ("secondary".to_string(), secondary.clone())],
+            Duration::ZERO,
+        );
+
+        let (kept, deleted) = (memory("kept", &["replicated"], 0, &[1.0, 0.0, 0.0, 0.0]), memory("deleted", &["replicated"], 0, &[0.0, 1.0, 0.0, 0.0]));
+        storage.store(&kept).await.unwrap();
+        storage.store(&deleted).await.unwrap();
+        assert!(secondary.check_duplicate_exists(&kept.content_hash).await.unwrap());
+
+        primary.up.store(false, Ordering::SeqCst);
+        // The secondary generates its embedding, which reconciling copies to the primary
+        let added = Memory { embedding: None, ..memory("added while down", &["replicated"], 0, &[]) };
+        assert_eq!(storage.store(&added).await.unwrap(), StoreOutcome::Stored);
+        assert_eq!(storage.delete(&deleted.content_hash).await.unwrap(), DeleteOutcome::Deleted);
+        // Rejections are answers, not failures, so they don't fail over
+        assert!(matches!(storage.delete(&deleted.content_hash).await, Err(StorageError::NotFound(_))));
+        // Reads fail over to the secondary
+        assert_eq!(storage.search_by_tag(&["replicated".to_string()]).await.unwrap().len(), 2);
+
+        primary.up.store(true, Ordering::SeqCst);
+        assert!(storage.get(&added.content_hash).await.unwrap().is_some());
+        assert!(primary.inner.check_duplicate_exists(&added.content_hash).await.unwrap());
+        assert!(!primary.inner.check_duplicate_exists(&deleted.content_hash).await.unwrap());
+        assert!(primary.inner.get(&added.content_hash).await.unwrap().unwrap().embedding.is_some());
+    }
+
+    #[tokio::test]
+    async fn errors_when_every_replica_fails() {
+        let primary = Arc::new(Flaky { inner: in_memory(), up: AtomicBool::new(false) });
+        let storage = ReplicatedStorage::new(vec![("primary".to_string(), primary.clone())], Duration::from_secs(60));
+        assert!(matches!(storage.store(&memory("lost", &[], 0, &[1.0, 0.0, 0.0, 0.0])).await, Err(StorageError::Unavailable(_))));
+        // Within the retry interval the failed replica isn't tried again
+        primary.up.store(true, Ordering::SeqCst);
+        assert!(storage.get("anything").await.is_err());
+    }
+}
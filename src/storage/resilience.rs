@@ -0,0 +1,202 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Retries after the first attempt of a request by default
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+/// Wait before the first retry by default, doubled for each further retry
+pub const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(200);
+/// Longest wait between two retries by default
+pub const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(5);
+/// Failed requests in a row after which the circuit breaker opens by default
+pub const DEFAULT_BREAKER_THRESHOLD: u32 = 5;
+/// How long an open circuit breaker rejects requests by default
+pub const DEFAULT_BREAKER_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// How requests to a remote backend are retried, and when to stop trying
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Failed requests in a row after which requests are rejected without
+    /// being sent; zero disables the circuit breaker
+    pub breaker_threshold: u32,
+    pub breaker_cooldown: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            breaker_threshold: DEFAULT_BREAKER_THRESHOLD,
+            breaker_cooldown: DEFAULT_BREAKER_COOLDOWN,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retry number `retry` (counting from zero): exponential,
+    /// capped, with up to a quarter taken off at random so that clients
+    /// restarted together don't retry in lockstep
+    pub(super) fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self.initial_backoff.saturating_mul(2u32.saturating_pow(retry)).min(self.max_backoff);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.subsec_nanos());
+        backoff.mul_f64(1.0 - f64::from(nanos % 1000) / 4000.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    // The cooldown has passed and one request is probing the server
+    HalfOpen,
+}
+
+/// Stops requests to a server that keeps failing. After `threshold` failed
+/// requests in a row the breaker opens and rejects requests for the
+/// cooldown; then a single request is let through, and its outcome closes
+/// or reopens the breaker.
+pub(super) struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub(super) fn new(policy: &RetryPolicy) -> Self {
+        Self {
+            threshold: policy.breaker_threshold,
+            cooldown: policy.breaker_cooldown,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    /// Permission to send a request now, if any
+    pub(super) fn allow(&self) -> Option<BreakerPermit<'_>> {
+        let mut state = self.state.lock().unwrap();
+        let probe = match *state {
+            BreakerState::Closed { .. } => false,
+            BreakerState::Open { until } if Instant::now() >= until => {
+                *state = BreakerState::HalfOpen;
+                true
+            }
+            BreakerState::Open { .. } | BreakerState::HalfOpen => return None,
+        };
+        Some(BreakerPermit { breaker: self, probe, recorded: false })
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if *state == BreakerState::HalfOpen {
+            tracing::info!("Server is reachable again, closing the circuit breaker");
+        }
+        *state = BreakerState::Closed { failures: 0 };
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            BreakerState::Open { .. } => return,
+            BreakerState::HalfOpen => self.threshold,
+        };
+        if self.threshold > 0 && failures >= self.threshold {
+            tracing::warn!("{} requests failed in a row, rejecting requests for {:?}", failures, self.cooldown);
+            *state = BreakerState::Open { until: Instant::now() + self.cooldown };
+        } else {
+            *state = BreakerState::Closed { failures };
+        }
+    }
+}
+
+/// A request let through by the [`CircuitBreaker`], whose outcome is to be
+/// recorded. A probe dropped without an outcome, e.g. because the caller
+/// gave up on the request, counts as failed, so that the breaker isn't left
+/// waiting for it forever.
+pub(super) struct BreakerPermit<'a> {
+    breaker: &'a CircuitBreaker,
+    probe: bool,
+    recorded: bool,
+}
+
+impl BreakerPermit<'_> {
+    pub(super) fn record_success(mut self) {
+        self.recorded = true;
+        self.breaker.record_success();
+    }
+
+    pub(super) fn record_failure(mut self) {
+        self.recorded = true;
+        self.breaker.record_failure();
+    }
+}
+
+impl Drop for BreakerPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe && !self.recorded {
+            tracing::debug!("Request probing the server was abandoned");
+            self.breaker.record_failure();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy { initial_backoff: Duration::from_millis(100), max_backoff: Duration::from_millis(500), ..Default::default() };
+        for (retry, expected) in [(0, 100), (1, 200), (2, 400), (3, 500), (40, 500)] {
+            let backoff = policy.backoff(retry);
+            let expected = Duration::from_millis(expected);
+            assert!(backoff <= expected && backoff >= expected * 3 / 4, "retry {}: {:?}", retry, backoff);
+        }
+    }
+
+    #[test]
+    fn breaker_opens_after_repeated_failures_and_probes_after_the_cooldown() {
+        let policy = RetryPolicy { breaker_threshold: 2, breaker_cooldown: Duration::from_millis(50), ..Default::default() };
+        let breaker = CircuitBreaker::new(&policy);
+
+        breaker.allow().unwrap().record_failure();
+        breaker.allow().unwrap().record_failure();
+        assert!(breaker.allow().is_none());
+
+        std::thread::sleep(Duration::from_millis(60));
+        let probe = breaker.allow().unwrap();
+        // Only one probe at a time, and a failed probe reopens the breaker
+        assert!(breaker.allow().is_none());
+        probe.record_failure();
+        assert!(breaker.allow().is_none());
+
+        std::thread::sleep(Duration::from_millis(60));
+        breaker.allow().unwrap().record_success();
+        assert!(breaker.allow().is_some());
+    }
+
+    #[test]
+    fn abandoned_probes_reopen_the_breaker() {
+        let policy = RetryPolicy { breaker_threshold: 1, breaker_cooldown: Duration::from_millis(50), ..Default::default() };
+        let breaker = CircuitBreaker::new(&policy);
+        breaker.allow().unwrap().record_failure();
+
+        std::thread::sleep(Duration::from_millis(60));
+        drop(breaker.allow().unwrap());
+        assert!(breaker.allow().is_none());
+
+        // The next cooldown ends with another probe rather than rejecting requests forever
+        std::thread::sleep(Duration::from_millis(60));
+        breaker.allow().unwrap().record_success();
+        assert!(breaker.allow().is_some());
+
+        // Requests abandoned while the breaker is closed aren't failures
+        drop(breaker.allow().unwrap());
+        assert!(breaker.allow().is_some());
+    }
+}
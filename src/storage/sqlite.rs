@@ -0,0 +1,432 @@
+use crate::models::{Memory, MemoryPage, MemoryQueryResult};
+use crate::embeddings::EmbeddingGenerator;
+use super::{
+    check_time_range, cosine_similarity, duplicate_or_conflict, vector::normalize, DeleteOutcome, MemoryStorage, QuantizationConfig, StorageError,
+    StorageResult, StoreOutcome,
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use std::{path::PathBuf, sync::{Arc, Mutex}};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS memories (
+    content_hash TEXT PRIMARY KEY,
+    content TEXT NOT NULL,
+    tags TEXT NOT NULL,
+    memory_type TEXT,
+    timestamp_seconds INTEGER NOT NULL,
+    metadata TEXT NOT NULL,
+    embedding BLOB,
+    embedding_code BLOB
+);
+CREATE INDEX IF NOT EXISTS memories_timestamp ON memories (timestamp_seconds);
+CREATE TABLE IF NOT EXISTS memory_tags (
+    content_hash TEXT NOT NULL REFERENCES memories (content_hash) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (content_hash, tag)
+);
+CREATE INDEX IF NOT EXISTS memory_tags_tag ON memory_tags (tag);
+";
+
+const MEMORY_COLUMNS: &str = "content_hash, content, tags, memory_type, timestamp_seconds, metadata, embedding";
+
+/// SQLite storage implementation. Memories, tags and embeddings live in a
+/// single database file; similarity search is a brute-force cosine scan,
+/// optionally over quantised codes with the best candidates rescored.
+pub struct SqliteMemoryStorage {
+    connection: Arc<Mutex<Connection>>,
+    embedding_generator: Arc<dyn EmbeddingGenerator>,
+    quantization: Option<QuantizationConfig>,
+    read_only: bool,
+}
+
+impl SqliteMemoryStorage {
+    /// Open (or create) the database at the given path. With quantisation,
+    /// codes are computed for any embeddings that lack current ones.
+    ///
+    /// A read-only database must exist and is never written to, so writes
+    /// fail, and quantised search is turned off if codes are missing.
+    pub fn new(
+        path: PathBuf,
+        embedding_generator: Arc<dyn EmbeddingGenerator>,
+        quantization: Option<QuantizationConfig>,
+        read_only: bool,
+    ) -> Result<Self> {
+        if read_only {
+            return Self::open_read_only(path, embedding_generator, quantization);
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context(format!("Failed to create directory: {:?}", parent))?;
+        }
+
+        let mut connection = Connection::open(&path).context(format!("Failed to open SQLite database: {:?}", path))?;
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "foreign_keys", "ON")?;
+        connection.execute_batch(SCHEMA).context("Failed to create SQLite schema")?;
+        // Databases created before quantisation lack the code column
+        let has_codes = connection.prepare("SELECT embedding_code FROM memories LIMIT 0").is_ok();
+        if !has_codes {
+            connection.execute_batch("ALTER TABLE memories ADD COLUMN embedding_code BLOB")
+                .context("Failed to add the embedding code column")?;
+        }
+        if let Some(quantization) = &quantization {
+            Self::backfill_codes(&mut connection, quantization)?;
+        }
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            embedding_generator,
+            quantization,
+            read_only: false,
+        })
+    }
+
+    fn open_read_only(path: PathBuf, embedding_generator: Arc<dyn EmbeddingGenerator>, mut quantization: Option<QuantizationConfig>) -> Result<Self> {
+        let connection = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+            .context(format!("Failed to open SQLite database read-only: {:?}", path))?;
+        if let Some(config) = &quantization {
+            // Codes can't be backfilled, and memories without a current one would never be found
+            let stale: rusqlite::Result<i64> = connection.query_row(
+                "SELECT COUNT(*) FROM memories WHERE embedding IS NOT NULL
+                 AND (embedding_code IS NULL OR substr(embedding_code, 1, 1) != ?1)",
+                params![vec![config.quantization.tag()]],
+                |row| row.get(0),
+            );
+            if !matches!(stale, Ok(0)) {
+                tracing::warn!("{:?} lacks {:?} codes for some embeddings; searching at full precision", path, config.quantization);
+                quantization = None;
+            }
+        }
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            embedding_generator,
+            quantization,
+            read_only: true,
+        })
+    }
+
+    // Encode the embeddings that have no code, or a code of another kind
+    fn backfill_codes(connection: &mut Connection, quantization: &QuantizationConfig) -> Result<()> {
+        let transaction = connection.transaction()?;
+        let stale: Vec<(String, Vec<u8>)> = {
+            let mut statement = transaction.prepare(
+                "SELECT content_hash, embedding FROM memories WHERE embedding IS NOT NULL
+                 AND (embedding_code IS NULL OR substr(embedding_code, 1, 1) != ?1)",
+            )?;
+            let rows = statement.query_map(params![vec![quantization.quantization.tag()]], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        tracing::info!("Computing {:?} codes for {} embeddings", quantization.quantization, stale.len());
+        {
+            let mut statement = transaction.prepare("UPDATE memories SET embedding_code = ?1 WHERE content_hash = ?2")?;
+            for (content_hash, blob) in stale {
+                statement.execute(params![Self::embedding_to_code(quantization, &Self::blob_to_embedding(&blob)), content_hash])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Run a closure against the connection on the blocking thread pool.
+    /// Storage errors raised by the closure keep their kind.
+    async fn with_connection<T, F>(&self, f: F) -> StorageResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T> + Send + 'static,
+    {
+        let connection = self.connection.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().map_err(|_| anyhow::anyhow!("SQLite connection lock poisoned"))?;
+            f(&mut connection)
+        })
+        .await
+        .context("SQLite task failed")?;
+        Ok(result?)
+    }
+
+    fn embedding_to_blob(embedding: &[f32]) -> Vec<u8> {
+        embedding.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    fn blob_to_embedding(blob: &[u8]) -> Vec<f32> {
+        blob.chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect()
+    }
+
+    // The kind's tag followed by the code of the normalised embedding
+    fn embedding_to_code(quantization: &QuantizationConfig, embedding: &[f32]) -> Vec<u8> {
+        let mut code = vec![quantization.quantization.tag()];
+        quantization.quantization.encode(&normalize(embedding), &mut code);
+        code
+    }
+
+    /// Build a memory from a row selected with `MEMORY_COLUMNS`
+    fn row_to_memory(row: &Row) -> rusqlite::Result<Memory> {
+        let tags: String = row.get(2)?;
+        let metadata: String = row.get(5)?;
+        let embedding: Option<Vec<u8>> = row.get(6)?;
+        Ok(Memory {
+            content_hash: row.get(0)?,
+            content: row.get(1)?,
+            tags: serde_json::from_str(&tags).unwrap_or_default(),
+            memory_type: row.get(3)?,
+            timestamp_seconds: row.get(4)?,
+            metadata: serde_json::from_str(&metadata).unwrap_or_default(),
+            embedding: embedding.map(|blob| Self::blob_to_embedding(&blob)),
+        })
+    }
+
+    fn query_memories(connection: &Connection, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Memory>> {
+        let mut statement = connection.prepare(sql)?;
+        let memories = statement
+            .query_map(params, Self::row_to_memory)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(memories)
+    }
+}
+
+#[async_trait]
+impl MemoryStorage for SqliteMemoryStorage {
+    async fn check_duplicate_exists(&self, content_hash: &str) -> StorageResult<bool> {
+        let content_hash = content_hash.to_string();
+        self.with_connection(move |connection| {
+            let exists = connection
+                .query_row("SELECT 1 FROM memories WHERE content_hash = ?1", params![content_hash], |_| Ok(()))
+                .optional()?
+                .is_some();
+            Ok(exists)
+        })
+        .await
+    }
+
+    async fn store(&self, memory: &Memory) -> StorageResult<StoreOutcome> {
+        if self.read_only {
+            return Err(StorageError::ReadOnly("The SQLite database".to_string()));
+        }
+        let content_hash = memory.content_hash.clone();
+        let existing = self.with_connection(move |connection| {
+            let content = connection
+                .query_row("SELECT content FROM memories WHERE content_hash = ?1", params![content_hash], |row| row.get::<_, String>(0))
+                .optional()?;
+            Ok(content)
+        })
+        .await?;
+        if let Some(existing) = existing {
+            return duplicate_or_conflict(memory, &existing);
+        }
+
+        // Generate embedding if not already present
+        let mut memory = memory.clone();
+        if memory.embedding.is_none() {
+            memory.embedding = Some(self.embedding_generator.generate_embedding(&memory.content).await?);
+        }
+
+        let quantization = self.quantization.clone();
+        self.with_connection(move |connection| {
+            let code = quantization
+                .as_ref()
+                .zip(memory.embedding.as_deref())
+                .map(|(quantization, embedding)| Self::embedding_to_code(quantization, embedding));
+            let transaction = connection.transaction()?;
+            let inserted = transaction.execute(
+                &format!("INSERT OR IGNORE INTO memories ({}, embedding_code) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)", MEMORY_COLUMNS),
+                params![
+                    memory.content_hash,
+                    memory.content,
+                    serde_json::to_string(&memory.tags)?,
+                    memory.memory_type,
+                    memory.timestamp_seconds,
+                    serde_json::to_string(&memory.metadata)?,
+                    memory.embedding.as_deref().map(Self::embedding_to_blob),
+                    code,
+                ],
+            )?;
+            // Lost a race against a concurrent store under the same hash
+            if inserted == 0 {
+                let existing: String = transaction.query_row(
+                    "SELECT content FROM memories WHERE content_hash = ?1",
+                    params![memory.content_hash],
+                    |row| row.get(0),
+                )?;
+                return Ok(duplicate_or_conflict(&memory, &existing)?);
+            }
+            for tag in &memory.tags {
+                transaction.execute(
+                    "INSERT OR IGNORE INTO memory_tags (content_hash, tag) VALUES (?1, ?2)",
+                    params![memory.content_hash, tag],
+                )?;
+            }
+            transaction.commit()?;
+
+            Ok(StoreOutcome::Stored)
+        })
+        .await
+    }
+
+    async fn retrieve(&self, query_embedding: &[f32], n_results: usize) -> StorageResult<Vec<MemoryQueryResult>> {
+        let query_embedding = query_embedding.to_vec();
+        let quantization = self.quantization.clone();
+        self.with_connection(move |connection| {
+            // Score every embedding first and only load the full rows of the best matches
+            let mut scores: Vec<(String, f32)> = Vec::new();
+            match &quantization {
+                Some(quantization) => {
+                    let kind = quantization.quantization;
+                    let query = kind.prepare(normalize(&query_embedding));
+                    let code_len = kind.code_len(query_embedding.len());
+                    let mut statement = connection.prepare("SELECT content_hash, embedding_code FROM memories WHERE embedding_code IS NOT NULL")?;
+                    let mut rows = statement.query([])?;
+                    while let Some(row) = rows.next()? {
+                        let code: Vec<u8> = row.get(1)?;
+                        // Codes of embeddings with other dimensions can't be compared
+                        if code.len() == code_len + 1 && code[0] == kind.tag() {
+                            scores.push((row.get(0)?, kind.score(&query, &code[1..])));
+                        }
+                    }
+                }
+                None => {
+                    let mut statement = connection.prepare("SELECT content_hash, embedding FROM memories WHERE embedding IS NOT NULL")?;
+                    let mut rows = statement.query([])?;
+                    while let Some(row) = rows.next()? {
+                        let blob: Vec<u8> = row.get(1)?;
+                        let score = cosine_similarity(&query_embedding, &Self::blob_to_embedding(&blob));
+                        scores.push((row.get(0)?, score));
+                    }
+                }
+            }
+
+            scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+            scores.truncate(quantization.as_ref().map_or(n_results, |quantization| quantization.candidates(n_results)));
+
+            let mut statement = connection.prepare(&format!("SELECT {} FROM memories WHERE content_hash = ?1", MEMORY_COLUMNS))?;
+            let mut results = Vec::with_capacity(scores.len());
+            for (content_hash, relevance_score) in scores {
+                if let Some(memory) = statement.query_row(params![content_hash], Self::row_to_memory).optional()? {
+                    results.push(MemoryQueryResult { memory, relevance_score });
+                }
+            }
+
+            // Rescore the candidates against their full-precision embeddings
+            if quantization.is_some() {
+                for result in &mut results {
+                    if let Some(embedding) = &result.memory.embedding {
+                        result.relevance_score = cosine_similarity(&query_embedding, embedding);
+                    }
+                }
+                results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+                results.truncate(n_results);
+            }
+            Ok(results)
+        })
+        .await
+    }
+
+    async fn get(&self, content_hash: &str) -> StorageResult<Option<Memory>> {
+        let content_hash = content_hash.to_string();
+        self.with_connection(move |connection| {
+            let memory = connection
+                .query_row(
+                    &format!("SELECT {} FROM memories WHERE content_hash = ?1", MEMORY_COLUMNS),
+                    params![content_hash],
+                    Self::row_to_memory,
+                )
+                .optional()?;
+            Ok(memory)
+        })
+        .await
+    }
+
+    async fn search_by_tag(&self, tags: &[String]) -> StorageResult<Vec<Memory>> {
+        if tags.is_empty() {
+            return Ok(Vec::new());
+        }
+        let tags = tags.to_vec();
+        self.with_connection(move |connection| {
+            let placeholders = vec!["?"; tags.len()].join(", ");
+            let sql = format!(
+                "SELECT {} FROM memories WHERE content_hash IN (SELECT content_hash FROM memory_tags WHERE tag IN ({}))",
+                MEMORY_COLUMNS, placeholders
+            );
+            Self::query_memories(connection, &sql, rusqlite::params_from_iter(tags.iter()))
+        })
+        .await
+    }
+
+    async fn search_by_time_range(&self, start_seconds: i64, end_seconds: i64) -> StorageResult<Vec<Memory>> {
+        check_time_range(start_seconds, end_seconds)?;
+        self.with_connection(move |connection| {
+            let sql = format!(
+                "SELECT {} FROM memories WHERE timestamp_seconds BETWEEN ?1 AND ?2",
+                MEMORY_COLUMNS
+            );
+            Self::query_memories(connection, &sql, params![start_seconds, end_seconds])
+        })
+        .await
+    }
+
+    async fn delete(&self, content_hash: &str) -> StorageResult<DeleteOutcome> {
+        if self.read_only {
+            return Err(StorageError::ReadOnly("The SQLite database".to_string()));
+        }
+        let content_hash = content_hash.to_string();
+        self.with_connection(move |connection| {
+            let deleted = connection.execute("DELETE FROM memories WHERE content_hash = ?1", params![content_hash])?;
+            if deleted > 0 {
+                Ok(DeleteOutcome::Deleted)
+            } else {
+                Err(StorageError::NotFound(content_hash).into())
+            }
+        })
+        .await
+    }
+
+    async fn list(&self, cursor: Option<&str>, limit: usize) -> StorageResult<MemoryPage> {
+        let cursor = cursor.unwrap_or_default().to_string();
+        self.with_connection(move |connection| {
+            // Keyset pagination on the primary key; fetch one extra row to know whether there's more
+            let sql = format!(
+                "SELECT {} FROM memories WHERE content_hash > ?1 ORDER BY content_hash LIMIT ?2",
+                MEMORY_COLUMNS
+            );
+            let mut memories = Self::query_memories(connection, &sql, params![cursor, limit as i64 + 1])?;
+            let next_cursor = if memories.len() > limit {
+                memories.truncate(limit);
+                memories.last().map(|memory| memory.content_hash.clone())
+            } else {
+                None
+            };
+            Ok(MemoryPage { memories, next_cursor })
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::storage::conformance::memory;
+
+    #[tokio::test]
+    async fn read_only_databases_reject_changes() {
+        let dir = std::env::temp_dir().join(format!("sqlite-read-only-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("memories.db");
+        let generator = Arc::new(DummyEmbeddingGenerator::new(2));
+        let kept = memory("kept", &[], 0, &[1.0, 0.0]);
+        SqliteMemoryStorage::new(path.clone(), generator.clone(), None, false).unwrap().store(&kept).await.unwrap();
+
+        let storage = SqliteMemoryStorage::new(path, generator, None, true).unwrap();
+        assert!(matches!(storage.store(&memory("new", &[], 0, &[0.0, 1.0])).await, Err(StorageError::ReadOnly(_))));
+        assert!(matches!(storage.delete(&kept.content_hash).await, Err(StorageError::ReadOnly(_))));
+        assert!(storage.get(&kept.content_hash).await.unwrap().is_some());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+use super::quantize::{Quantization, Query, Vectors};
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, HashMap},
+    ops::Range,
+};
+
+// Independent accumulators in `dot`, enough for the compiler to fill a 256-bit SIMD register
+const LANES: usize = 8;
+/// Matrices with at least this many rows are scanned on several threads
+pub const PARALLEL_SCAN_THRESHOLD: usize = 16_384;
+// Rows per thread below which spawning another thread costs more than it saves
+const MIN_ROWS_PER_THREAD: usize = 4_096;
+
+/// Dot product over the common length of two vectors. The sum is split into
+/// independent lanes so that it vectorises without relying on fast-math.
+pub(super) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let (a, b) = (&a[..len], &b[..len]);
+
+    let mut sums = [0.0f32; LANES];
+    let (chunks_a, chunks_b) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+    let tail: f32 = chunks_a.remainder().iter().zip(chunks_b.remainder()).map(|(x, y)| x * y).sum();
+    for (chunk_a, chunk_b) in chunks_a.zip(chunks_b) {
+        for ((sum, x), y) in sums.iter_mut().zip(chunk_a).zip(chunk_b) {
+            *sum += x * y;
+        }
+    }
+    sums.iter().sum::<f32>() + tail
+}
+
+/// Scale a vector to unit length, so dot products with other unit vectors
+/// are cosine similarities. Zero vectors are left as they are.
+pub(super) fn normalize(vector: &[f32]) -> Vec<f32> {
+    let magnitude = dot(vector, vector).sqrt();
+    if magnitude > 0.0 {
+        vector.iter().map(|x| x / magnitude).collect()
+    } else {
+        vector.to_vec()
+    }
+}
+
+/// A row or graph node with its similarity to a query, ordered by similarity
+#[derive(Clone, Copy, PartialEq)]
+pub(super) struct Scored {
+    pub similarity: f32,
+    pub node: usize,
+}
+
+impl Eq for Scored {}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.similarity.total_cmp(&other.similarity).then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Normalised embeddings stored row after row in one allocation, optionally
+/// quantised, for similarity search by a linear scan
+#[derive(Default)]
+pub(super) struct EmbeddingMatrix {
+    vectors: Vectors,
+    hashes: Vec<String>,
+    rows: HashMap<String, usize>,
+}
+
+impl EmbeddingMatrix {
+    pub(super) fn new(quantization: Option<Quantization>) -> Self {
+        Self { vectors: Vectors::new(quantization), ..Default::default() }
+    }
+
+    /// Bytes held by the embeddings
+    pub(super) fn bytes(&self) -> usize {
+        self.vectors.bytes()
+    }
+
+    /// Number of rows
+    pub(super) fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub(super) fn quantization(&self) -> Option<Quantization> {
+        self.vectors.quantization()
+    }
+
+    /// Every row, as content hashes with their normalised embeddings, decoded
+    /// if the matrix is quantised
+    pub(super) fn rows(&self) -> Vec<(String, Vec<f32>)> {
+        self.hashes.iter().enumerate().map(|(row, content_hash)| (content_hash.clone(), self.vectors.get(row))).collect()
+    }
+
+    /// Add a memory's embedding, replacing any earlier one for the same hash.
+    /// Returns false, leaving the matrix unchanged, if the embedding has
+    /// different dimensions from the others.
+    pub(super) fn insert(&mut self, content_hash: &str, embedding: &[f32]) -> bool {
+        if !self.hashes.is_empty() && embedding.len() != self.vectors.dimensions() {
+            tracing::warn!(
+                "Memory {} has a {}-dimensional embedding but the store holds {}-dimensional ones; it won't be found by similarity search",
+                content_hash,
+                embedding.len(),
+                self.vectors.dimensions()
+            );
+            return false;
+        }
+
+        let normalized = normalize(embedding);
+        match self.rows.get(content_hash) {
+            Some(&row) => self.vectors.set(row, &normalized),
+            None => {
+                self.rows.insert(content_hash.to_string(), self.hashes.len());
+                self.hashes.push(content_hash.to_string());
+                self.vectors.push(&normalized);
+            }
+        }
+        true
+    }
+
+    /// Remove a memory's embedding by moving the last row into its place
+    pub(super) fn remove(&mut self, content_hash: &str) {
+        let Some(row) = self.rows.remove(content_hash) else { return };
+        self.vectors.swap_remove(row);
+        self.hashes.swap_remove(row);
+        if row < self.hashes.len() {
+            self.rows.insert(self.hashes[row].clone(), row);
+        }
+    }
+
+    /// The `k` embeddings most similar to the query, best first, as content
+    /// hashes with their cosine similarity, estimated if the matrix is
+    /// quantised. Large matrices are split across threads, each keeping its
+    /// own top `k`.
+    pub(super) fn top_k(&self, query: &[f32], k: usize) -> Vec<(String, f32)> {
+        let query = self.vectors.prepare(normalize(query));
+        let rows = self.hashes.len();
+        let threads = if rows >= PARALLEL_SCAN_THRESHOLD {
+            let available = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+            available.min(rows / MIN_ROWS_PER_THREAD).max(1)
+        } else {
+            1
+        };
+
+        let mut best: Vec<Scored> = if threads == 1 {
+            self.scan(&query, 0..rows, k)
+        } else {
+            let rows_per_thread = rows.div_ceil(threads);
+            std::thread::scope(|scope| {
+                let scans: Vec<_> = (0..rows)
+                    .step_by(rows_per_thread)
+                    .map(|start| {
+                        let query = &query;
+                        scope.spawn(move || self.scan(query, start..(start + rows_per_thread).min(rows), k))
+                    })
+                    .collect();
+                // A scan that panicked would otherwise drop its rows from the results
+                scans
+                    .into_iter()
+                    .flat_map(|scan| scan.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                    .collect()
+            })
+        };
+
+        best.sort_unstable_by(|a, b| b.cmp(a));
+        best.into_iter()
+            .take(k)
+            .map(|scored| (self.hashes[scored.node].clone(), scored.similarity))
+            .collect()
+    }
+
+    // Top `k` of a range of rows, in no particular order
+    fn scan(&self, query: &Query, rows: Range<usize>, k: usize) -> Vec<Scored> {
+        let mut best: BinaryHeap<Reverse<Scored>> = BinaryHeap::with_capacity(k + 1);
+        for row in rows {
+            let scored = Scored { similarity: self.vectors.score(query, row), node: row };
+            if best.len() < k {
+                best.push(Reverse(scored));
+            } else if best.peek().is_some_and(|Reverse(worst)| scored > *worst) {
+                best.pop();
+                best.push(Reverse(scored));
+            }
+        }
+        best.into_iter().map(|Reverse(scored)| scored).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::cosine_similarity;
+
+    fn vector(seed: usize, dimensions: usize) -> Vec<f32> {
+        (0..dimensions).map(|i| (((seed * 31 + i * 17) % 101) as f32 - 50.0) / 50.0).collect()
+    }
+
+    #[test]
+    fn dot_matches_naive_sum() {
+        for len in [0, 1, 7, 8, 9, 384, 1000] {
+            let (a, b) = (vector(1, len), vector(2, len));
+            let naive: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+            assert!((dot(&a, &b) - naive).abs() < 1e-3, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn removing_moves_the_last_row() {
+        let mut matrix = EmbeddingMatrix::default();
+        for i in 0..5 {
+            matrix.insert(&i.to_string(), &vector(i, 16));
+        }
+        matrix.remove("1");
+        matrix.remove("4");
+        matrix.remove("missing");
+        assert_eq!(matrix.hashes.len(), 3);
+        assert_eq!(matrix.bytes(), 3 * 16 * 4);
+
+        for i in [0, 2, 3] {
+            let best = matrix.top_k(&vector(i, 16), 1);
+            assert_eq!(best[0].0, i.to_string());
+            assert!((best[0].1 - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn parallel_scan_matches_exact_ranking() {
+        let rows = PARALLEL_SCAN_THRESHOLD * 2 + 3;
+        let vectors: Vec<Vec<f32>> = (0..rows).map(|i| vector(i, 12)).collect();
+        let mut matrix = EmbeddingMatrix::default();
+        for (i, vector) in vectors.iter().enumerate() {
+            matrix.insert(&i.to_string(), vector);
+        }
+
+        let query = vector(rows + 1, 12);
+        let found = matrix.top_k(&query, 20);
+        assert_eq!(found.len(), 20);
+        assert!(found.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+        // Many rows share the best score, so compare scores rather than hashes
+        let mut expected: Vec<f32> = vectors.iter().map(|vector| cosine_similarity(&query, vector)).collect();
+        expected.sort_by(|a, b| b.total_cmp(a));
+        for ((_, similarity), expected) in found.iter().zip(expected) {
+            assert!((similarity - expected).abs() < 1e-5);
+        }
+    }
+}
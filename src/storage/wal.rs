@@ -0,0 +1,447 @@
+use crate::models::Memory;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+const LOG_FILE: &str = "memories.wal";
+// Prefix of logs set aside for compaction, followed by their number
+const SEGMENT_PREFIX: &str = "memories.wal.";
+const SNAPSHOT_FILE: &str = "memories.snapshot";
+
+/// Compact the log into a snapshot after this many records by default
+pub const DEFAULT_SNAPSHOT_INTERVAL: usize = 1000;
+
+/// Durability settings for `InMemoryStorage`
+#[derive(Debug, Clone)]
+pub struct WalConfig {
+    /// Directory holding the log and snapshot files
+    pub dir: PathBuf,
+    /// Number of log records after which the log is compacted into a snapshot
+    pub snapshot_interval: usize,
+}
+
+/// A change to the store. Stores carry the embedding, which `Memory` doesn't
+/// serialize, so that replaying the log doesn't need to re-embed.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub(super) enum WalEntry {
+    Store { memory: Memory, embedding: Option<Vec<f32>> },
+    Delete { content_hash: String },
+}
+
+impl WalEntry {
+    pub(super) fn store(memory: &Memory) -> Self {
+        WalEntry::Store { memory: memory.clone(), embedding: memory.embedding.clone() }
+    }
+
+    pub(super) fn content_hash(&self) -> &str {
+        match self {
+            WalEntry::Store { memory, .. } => &memory.content_hash,
+            WalEntry::Delete { content_hash } => content_hash,
+        }
+    }
+}
+
+// One line of the log or snapshot. The entry is kept as raw JSON so the
+// checksum covers exactly the bytes that were written.
+#[derive(Serialize, Deserialize)]
+struct WalRecord<'a> {
+    checksum: String,
+    #[serde(borrow)]
+    entry: &'a RawValue,
+}
+
+fn checksum(entry: &str) -> String {
+    hex::encode(Sha256::digest(entry.as_bytes()))
+}
+
+pub(super) fn encode(entry: &WalEntry) -> Result<String> {
+    let entry = serde_json::value::to_raw_value(entry)?;
+    let record = WalRecord { checksum: checksum(entry.get()), entry: &entry };
+    Ok(serde_json::to_string(&record)?)
+}
+
+fn decode(line: &str) -> Result<WalEntry> {
+    let record: WalRecord = serde_json::from_str(line).context("Malformed record")?;
+    if checksum(record.entry.get()) != record.checksum {
+        anyhow::bail!("Checksum mismatch");
+    }
+    Ok(serde_json::from_str(record.entry.get())?)
+}
+
+fn apply(entry: WalEntry, memories: &mut HashMap<String, Memory>) {
+    match entry {
+        WalEntry::Store { mut memory, embedding } => {
+            memory.embedding = embedding;
+            memories.insert(memory.content_hash.clone(), memory);
+        }
+        WalEntry::Delete { content_hash } => {
+            memories.remove(&content_hash);
+        }
+    }
+}
+
+/// Read every record in the file. An invalid final record is the remains of
+/// a write torn by a crash and is skipped if `allow_torn_tail` is set; any
+/// other invalid record is an error, so that compaction never silently drops
+/// data. Also returns how many records there were, torn or not.
+pub(super) fn read(path: &Path, allow_torn_tail: bool) -> Result<(Vec<WalEntry>, usize)> {
+    if !path.exists() {
+        return Ok((Vec::new(), 0));
+    }
+    let bytes = std::fs::read(path).context(format!("Failed to read {:?}", path))?;
+    let contents = String::from_utf8_lossy(&bytes);
+    let lines: Vec<&str> = contents.lines().filter(|line| !line.trim().is_empty()).collect();
+
+    let mut entries = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        match decode(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) if allow_torn_tail && i + 1 == lines.len() => {
+                tracing::warn!("Skipping torn final record in {:?}: {}", path, e);
+            }
+            Err(e) => {
+                return Err(e.context(format!("Corrupt record {} in {:?}", i + 1, path)));
+            }
+        }
+    }
+
+    Ok((entries, lines.len()))
+}
+
+/// Apply every record in the file, returning how many there were
+fn replay(path: &Path, memories: &mut HashMap<String, Memory>, allow_torn_tail: bool) -> Result<usize> {
+    let (entries, records) = read(path, allow_torn_tail)?;
+    for entry in entries {
+        apply(entry, memories);
+    }
+    Ok(records)
+}
+
+fn segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("{}{}", SEGMENT_PREFIX, segment))
+}
+
+// Logs set aside for a compaction that hasn't finished, oldest first
+fn segments(dir: &Path) -> Result<Vec<u64>> {
+    let mut segments = Vec::new();
+    for entry in std::fs::read_dir(dir).context(format!("Failed to list {:?}", dir))? {
+        let name = entry?.file_name();
+        if let Some(segment) = name.to_str().and_then(|name| name.strip_prefix(SEGMENT_PREFIX)).and_then(|n| n.parse().ok()) {
+            segments.push(segment);
+        }
+    }
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+// Make renames and new files in the directory durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> Result<()> {
+    File::open(dir).and_then(|dir| dir.sync_all()).context(format!("Failed to sync directory {:?}", dir))
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// The memories as of the end of `segment`: the snapshot with the segments
+/// up to it replayed over it. Stores that don't keep embeddings in memory
+/// compact these instead of their own memories.
+pub(super) fn replay_segments(dir: &Path, segment: u64) -> Result<HashMap<String, Memory>> {
+    let mut memories = HashMap::new();
+    replay(&dir.join(SNAPSHOT_FILE), &mut memories, false)?;
+    for old in segments(dir)?.into_iter().filter(|old| *old <= segment) {
+        replay(&segment_path(dir, old), &mut memories, true)?;
+    }
+    Ok(memories)
+}
+
+/// Write the memories to a new snapshot, then remove the log segments up to
+/// `segment`, which the memories reflect. Runs without holding the log, so
+/// writes go on meanwhile.
+///
+/// The memories may also reflect later changes, from segments after
+/// `segment` or the current log: replaying records over a state they led to
+/// leaves every memory as its last record set it, so recovery stays correct.
+pub(super) fn compact(dir: &Path, memories: &HashMap<String, Memory>, segment: u64) -> Result<()> {
+    // Write under a temporary name so a crash never leaves a partial snapshot behind
+    let snapshot_path = dir.join(SNAPSHOT_FILE);
+    let temp_path = snapshot_path.with_extension("snapshot.tmp");
+    {
+        let mut temp = BufWriter::new(File::create(&temp_path).context(format!("Failed to create snapshot {:?}", temp_path))?);
+        for memory in memories.values() {
+            let mut line = encode(&WalEntry::store(memory))?;
+            line.push('\n');
+            temp.write_all(line.as_bytes())?;
+        }
+        temp.into_inner().map_err(|e| e.into_error())?.sync_all().context("Failed to sync snapshot")?;
+    }
+    std::fs::rename(&temp_path, &snapshot_path).context(format!("Failed to move snapshot to {:?}", snapshot_path))?;
+    sync_dir(dir)?;
+
+    // Replaying segments on top of the snapshot is harmless, so a crash before they are removed loses nothing
+    for old in segments(dir)?.into_iter().filter(|old| *old <= segment) {
+        let path = segment_path(dir, old);
+        std::fs::remove_file(&path).context(format!("Failed to remove compacted log {:?}", path))?;
+    }
+    tracing::debug!("Wrote snapshot of {} memories to {:?}", memories.len(), snapshot_path);
+    Ok(())
+}
+
+/// Append-only JSONL log of store/delete operations, periodically compacted
+/// into a snapshot of the whole store. Compaction sets the log aside as a
+/// numbered segment and starts a new one, so that the snapshot can be
+/// written while changes keep being logged.
+pub(super) struct WriteAheadLog {
+    dir: PathBuf,
+    file: File,
+    snapshot_interval: usize,
+    records_since_snapshot: usize,
+    // Number given to the next segment set aside
+    next_segment: u64,
+}
+
+impl WriteAheadLog {
+    /// The memories in the snapshot, the segments and the log, and the number
+    /// of records in the segments and the log
+    pub(super) fn recover(config: &WalConfig) -> Result<(HashMap<String, Memory>, usize)> {
+        let mut memories = HashMap::new();
+        // Snapshots are moved into place whole, so only logs can end in a torn write
+        replay(&config.dir.join(SNAPSHOT_FILE), &mut memories, false)?;
+        let mut log_records = 0;
+        if config.dir.exists() {
+            for segment in segments(&config.dir)? {
+                log_records += replay(&segment_path(&config.dir, segment), &mut memories, true)?;
+            }
+        }
+        log_records += replay(&config.dir.join(LOG_FILE), &mut memories, true)?;
+        tracing::info!("Recovered {} memories from {:?} ({} log records)", memories.len(), config.dir, log_records);
+        Ok((memories, log_records))
+    }
+
+    /// Open the log, returning it together with the memories recovered from
+    /// the snapshot and the logs
+    pub(super) fn open(config: &WalConfig) -> Result<(Self, HashMap<String, Memory>)> {
+        std::fs::create_dir_all(&config.dir).context(format!("Failed to create directory: {:?}", config.dir))?;
+        let (memories, log_records) = Self::recover(config)?;
+
+        let log_path = config.dir.join(LOG_FILE);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .context(format!("Failed to open write-ahead log {:?}", log_path))?;
+
+        let mut wal = Self {
+            dir: config.dir.clone(),
+            file,
+            snapshot_interval: config.snapshot_interval.max(1),
+            records_since_snapshot: log_records,
+            next_segment: segments(&config.dir)?.last().map_or(0, |last| last + 1),
+        };
+        // Compact right away so new records never follow a torn one
+        if log_records > 0 {
+            let segment = wal.rotate()?;
+            compact(&config.dir, &memories, segment)?;
+        }
+
+        Ok((wal, memories))
+    }
+
+    pub(super) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Durably append a record
+    pub(super) fn append(&mut self, entry: &WalEntry) -> Result<()> {
+        let mut line = encode(entry)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes()).context("Failed to append to write-ahead log")?;
+        self.file.sync_data().context("Failed to sync write-ahead log")?;
+        self.records_since_snapshot += 1;
+        Ok(())
+    }
+
+    pub(super) fn should_snapshot(&self) -> bool {
+        self.records_since_snapshot >= self.snapshot_interval
+    }
+
+    /// Set the log aside for compaction and start a new one, returning the
+    /// number of the segment to pass to `compact`
+    pub(super) fn rotate(&mut self) -> Result<u64> {
+        let segment = self.next_segment;
+        let log_path = self.dir.join(LOG_FILE);
+        let segment_path = segment_path(&self.dir, segment);
+        std::fs::rename(&log_path, &segment_path).context(format!("Failed to move write-ahead log to {:?}", segment_path))?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .context(format!("Failed to open write-ahead log {:?}", log_path))?;
+        sync_dir(&self.dir)?;
+        self.next_segment += 1;
+        self.records_since_snapshot = 0;
+        Ok(segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::DummyEmbeddingGenerator;
+    use crate::storage::{conformance::memory, InMemoryStorage, MemoryStorage, Quantization, QuantizationConfig, StorageError};
+    use std::{sync::Arc, time::Duration};
+
+    const EMBEDDING: [f32; 2] = [0.25, 0.75];
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wal-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn config(dir: &Path) -> WalConfig {
+        WalConfig { dir: dir.to_path_buf(), snapshot_interval: 1000 }
+    }
+
+    fn write_lines(path: &Path, lines: &[String]) {
+        std::fs::write(path, lines.iter().map(|line| format!("{}\n", line)).collect::<String>()).unwrap();
+    }
+
+    #[test]
+    fn records_with_a_bad_checksum_are_rejected() {
+        let dir = test_dir("checksum");
+        let path = dir.join(LOG_FILE);
+        let good = encode(&WalEntry::store(&memory("a", &[], 0, &EMBEDDING))).unwrap();
+        let tampered = encode(&WalEntry::store(&memory("b", &[], 0, &EMBEDDING))).unwrap().replace(r#""content":"b""#, r#""content":"c""#);
+        write_lines(&path, &[tampered, good]);
+
+        // Only a final record may be torn, so the mismatch is an error even when torn tails are allowed
+        let error = read(&path, true).err().unwrap();
+        assert!(format!("{:#}", error).contains("Checksum mismatch"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_torn_final_record_is_skipped() {
+        let dir = test_dir("torn");
+        let path = dir.join(LOG_FILE);
+        let first = encode(&WalEntry::store(&memory("a", &[], 0, &EMBEDDING))).unwrap();
+        let second = encode(&WalEntry::store(&memory("b", &[], 0, &EMBEDDING))).unwrap();
+        let torn = second[..second.len() / 2].to_string();
+        write_lines(&path, &[first, torn]);
+
+        let (entries, records) = read(&path, true).unwrap();
+        assert_eq!(records, 2);
+        assert_eq!(entries.iter().map(WalEntry::content_hash).collect::<Vec<_>>(), vec!["hash-a"]);
+        // Snapshots are never torn, so there it is corruption
+        assert!(read(&path, false).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn recovery_replays_the_log_over_the_snapshot() {
+        let dir = test_dir("replay");
+        write_lines(
+            &dir.join(SNAPSHOT_FILE),
+            &[
+                encode(&WalEntry::store(&memory("a", &[], 0, &EMBEDDING))).unwrap(),
+                encode(&WalEntry::store(&memory("b", &[], 0, &EMBEDDING))).unwrap(),
+            ],
+        );
+        write_lines(&segment_path(&dir, 0), &[encode(&WalEntry::Delete { content_hash: "hash-a".to_string() }).unwrap()]);
+        write_lines(&dir.join(LOG_FILE), &[encode(&WalEntry::store(&memory("c", &[], 0, &EMBEDDING))).unwrap()]);
+
+        let (memories, log_records) = WriteAheadLog::recover(&config(&dir)).unwrap();
+        assert_eq!(log_records, 2);
+        let mut hashes: Vec<&String> = memories.keys().collect();
+        hashes.sort();
+        assert_eq!(hashes, vec!["hash-b", "hash-c"]);
+        assert_eq!(memories["hash-c"].embedding, Some(EMBEDDING.to_vec()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compaction_replaces_the_rotated_logs_with_a_snapshot() {
+        let dir = test_dir("compact");
+        let (mut wal, memories) = WriteAheadLog::open(&config(&dir)).unwrap();
+        assert!(memories.is_empty());
+
+        let mut memories = HashMap::new();
+        for content in ["a", "b"] {
+            let memory = memory(content, &[], 0, &EMBEDDING);
+            wal.append(&WalEntry::store(&memory)).unwrap();
+            memories.insert(memory.content_hash.clone(), memory);
+        }
+        let segment = wal.rotate().unwrap();
+        // Logged while the snapshot is being written
+        wal.append(&WalEntry::Delete { content_hash: "hash-a".to_string() }).unwrap();
+        compact(&dir, &memories, segment).unwrap();
+
+        assert!(segments(&dir).unwrap().is_empty());
+        let (snapshot, _) = read(&dir.join(SNAPSHOT_FILE), false).unwrap();
+        assert_eq!(snapshot.len(), 2);
+        let (recovered, log_records) = WriteAheadLog::recover(&config(&dir)).unwrap();
+        assert_eq!(log_records, 1);
+        assert_eq!(recovered.keys().collect::<Vec<_>>(), vec!["hash-b"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn read_only_stores_reject_changes_and_leave_the_log_alone() {
+        let dir = test_dir("read-only");
+        write_lines(&dir.join(LOG_FILE), &[encode(&WalEntry::store(&memory("a", &[], 0, &EMBEDDING))).unwrap()]);
+        let generator = Arc::new(DummyEmbeddingGenerator::new(2));
+
+        let recovered = InMemoryStorage::new(generator.clone(), Some(config(&dir)), None, None, true).unwrap();
+        assert!(recovered.get("hash-a").await.unwrap().is_some());
+        // Without a log, there is nothing to recover, but changes are rejected all the same
+        let empty = InMemoryStorage::new(generator, None, None, None, true).unwrap();
+        for storage in [recovered, empty] {
+            assert!(matches!(storage.store(&memory("b", &[], 0, &EMBEDDING)).await, Err(StorageError::ReadOnly(_))));
+            assert!(matches!(storage.delete("hash-a").await, Err(StorageError::ReadOnly(_))));
+        }
+
+        assert_eq!(read(&dir.join(LOG_FILE), false).unwrap().1, 1);
+        assert!(!dir.join(SNAPSHOT_FILE).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn quantised_stores_snapshot_the_embeddings_in_the_logs() {
+        let dir = test_dir("quantized");
+        let generator = Arc::new(DummyEmbeddingGenerator::new(2));
+        let wal_config = WalConfig { dir: dir.clone(), snapshot_interval: 2 };
+        let quantization = Some(QuantizationConfig::new(Quantization::Int8));
+        let storage = InMemoryStorage::new(generator, Some(wal_config), None, quantization, false).unwrap();
+        for content in ["a", "b"] {
+            storage.store(&memory(content, &[], 0, &EMBEDDING)).await.unwrap();
+        }
+        // The store itself only keeps codes of the embeddings
+        assert_eq!(storage.get("hash-a").await.unwrap().unwrap().embedding, None);
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !dir.join(SNAPSHOT_FILE).exists() || !segments(&dir).unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let (snapshot, _) = read(&dir.join(SNAPSHOT_FILE), false).unwrap();
+        assert_eq!(snapshot.len(), 2);
+        for entry in snapshot {
+            assert!(matches!(entry, WalEntry::Store { embedding: Some(embedding), .. } if embedding == EMBEDDING));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use super::wal::{self, WalEntry};
+use anyhow::{Context, Result};
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Writes accepted while a remote backend was unreachable, kept in a file
+/// in the write-ahead log's format until they have been replayed
+pub(super) struct WriteQueue {
+    path: PathBuf,
+    file: File,
+    entries: VecDeque<WalEntry>,
+}
+
+impl WriteQueue {
+    /// Open the queue, recovering the writes not yet replayed
+    pub(super) fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).context(format!("Failed to create directory: {:?}", dir))?;
+        }
+        let (entries, records) = wal::read(path, true)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context(format!("Failed to open write queue {:?}", path))?;
+
+        let mut queue = Self { path: path.to_path_buf(), file, entries: entries.into() };
+        if !queue.entries.is_empty() {
+            tracing::info!("{} writes in {:?} are waiting to be replayed", queue.entries.len(), path);
+        }
+        // Rewrite right away so new records never follow a torn one
+        if records > queue.entries.len() {
+            queue.rewrite()?;
+        }
+        Ok(queue)
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(super) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The queued writes, oldest first
+    pub(super) fn entries(&self) -> impl Iterator<Item = &WalEntry> {
+        self.entries.iter()
+    }
+
+    /// The most recent queued write to a memory, which decides whether it
+    /// exists once the queue has been replayed
+    pub(super) fn latest(&self, content_hash: &str) -> Option<&WalEntry> {
+        self.entries.iter().rev().find(|entry| entry.content_hash() == content_hash)
+    }
+
+    /// Durably append a write
+    pub(super) fn push(&mut self, entry: WalEntry) -> Result<()> {
+        let mut line = wal::encode(&entry)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes()).context("Failed to append to write queue")?;
+        self.file.sync_data().context("Failed to sync write queue")?;
+        self.entries.push_back(entry);
+        Ok(())
+    }
+
+    /// Forget the oldest `count` writes once they have been replayed.
+    /// Replaying is idempotent, so a crash before the file is rewritten
+    /// only means they are replayed again.
+    pub(super) fn remove_replayed(&mut self, count: usize) -> Result<()> {
+        self.entries.drain(..count.min(self.entries.len()));
+        self.rewrite()
+    }
+
+    // Replace the file with the writes still queued
+    fn rewrite(&mut self) -> Result<()> {
+        let temp_path = self.path.with_extension("tmp");
+        {
+            let mut temp = File::create(&temp_path).context(format!("Failed to create {:?}", temp_path))?;
+            for entry in &self.entries {
+                let mut line = wal::encode(entry)?;
+                line.push('\n');
+                temp.write_all(line.as_bytes())?;
+            }
+            temp.sync_all().context("Failed to sync write queue")?;
+        }
+        std::fs::rename(&temp_path, &self.path).context(format!("Failed to move write queue to {:?}", self.path))?;
+        self.file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .context(format!("Failed to open write queue {:?}", self.path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::conformance::memory;
+
+    #[test]
+    fn queued_writes_survive_reopening_in_order() {
+        let path = std::env::temp_dir().join(format!("write-queue-test-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let memory = memory("queued", &[], 0, &[0.5, 0.5]);
+        {
+            let mut queue = WriteQueue::open(&path).unwrap();
+            queue.push(WalEntry::store(&memory)).unwrap();
+            queue.push(WalEntry::Delete { content_hash: memory.content_hash.clone() }).unwrap();
+            queue.push(WalEntry::Delete { content_hash: "other".to_string() }).unwrap();
+        }
+        // A write torn by a crash is dropped
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"checksum\":").unwrap();
+
+        let mut queue = WriteQueue::open(&path).unwrap();
+        assert_eq!(queue.len(), 3);
+        assert!(matches!(queue.latest(&memory.content_hash), Some(WalEntry::Delete { .. })));
+        match queue.entries().next() {
+            Some(WalEntry::Store { memory: queued, embedding }) => {
+                assert_eq!(queued.content, "queued");
+                assert_eq!(embedding.as_deref(), Some(&[0.5, 0.5][..]));
+            }
+            _ => panic!("expected the store first"),
+        }
+
+        queue.remove_replayed(2).unwrap();
+        queue.push(WalEntry::Delete { content_hash: "last".to_string() }).unwrap();
+        drop(queue);
+
+        let queue = WriteQueue::open(&path).unwrap();
+        let hashes: Vec<&str> = queue.entries().map(|entry| entry.content_hash()).collect();
+        assert_eq!(hashes, ["other", "last"]);
+        assert!(queue.latest(&memory.content_hash).is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
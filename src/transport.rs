@@ -0,0 +1,294 @@
+use crate::auth::{self, ApiKey, ApiKeys};
+use crate::MemoryServer;
+use anyhow::{Context, Result};
+use axum::Router;
+use rmcp::{
+    transport::{
+        sse_server::{SseServer, SseServerConfig},
+        stdio,
+        streamable_http_server::session::local::LocalSessionManager,
+        StreamableHttpServerConfig, StreamableHttpService,
+    },
+    ServiceExt,
+};
+use std::{net::SocketAddr, path::Path, sync::Arc};
+use tokio_util::sync::CancellationToken;
+
+// Endpoints served by the HTTP transport
+const STREAMABLE_HTTP_PATH: &str = "/mcp";
+const SSE_PATH: &str = "/sse";
+const SSE_POST_PATH: &str = "/message";
+
+/// Serve a single client over stdin/stdout
+pub async fn serve_stdio(server: MemoryServer) -> Result<()> {
+    let service = server.new_session().serve(stdio()).await?;
+
+    tracing::info!("MCP Memory Service running on stdio. Waiting for requests...");
+
+    service.waiting().await?;
+    Ok(())
+}
+
+/// Serve any number of concurrent clients over HTTP until Ctrl-C.
+///
+/// Streamable HTTP is served at `/mcp`; the legacy SSE transport at `/sse`
+/// (with messages posted to `/message`). Every session gets its own
+/// [`MemoryServer::new_session`] sharing the same storage. When API keys are
+/// configured, every request must carry one as a bearer token.
+pub async fn serve_http(server: MemoryServer, addr: SocketAddr, api_keys: &[ApiKey]) -> Result<()> {
+    let ct = CancellationToken::new();
+    if api_keys.is_empty() {
+        tracing::warn!("No API keys configured; the HTTP transport accepts unauthenticated requests");
+    }
+    let router = http_router(server, addr, api_keys, ct.clone());
+
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind HTTP transport to {}", addr))?;
+
+    tracing::info!(
+        "MCP Memory Service listening on http://{addr}{STREAMABLE_HTTP_PATH} (streamable HTTP) and http://{addr}{SSE_PATH} (SSE)"
+    );
+
+    axum::serve(listener, router)
+        .with_graceful_shutdown(async move {
+            let _ = tokio::signal::ctrl_c().await;
+            ct.cancel();
+        })
+        .await
+        .context("HTTP transport failed")?;
+
+    Ok(())
+}
+
+// Both HTTP transports, behind the API key check if any keys are configured
+fn http_router(server: MemoryServer, addr: SocketAddr, api_keys: &[ApiKey], ct: CancellationToken) -> Router {
+    let streamable_http = StreamableHttpService::new(
+        {
+            let server = server.clone();
+            move || Ok(server.new_session())
+        },
+        LocalSessionManager::default().into(),
+        StreamableHttpServerConfig::default(),
+    );
+
+    let (sse_server, sse_router) = SseServer::new(SseServerConfig {
+        bind: addr,
+        sse_path: SSE_PATH.to_string(),
+        post_path: SSE_POST_PATH.to_string(),
+        ct,
+        sse_keep_alive: None,
+    });
+    sse_server.with_service(move || server.new_session());
+
+    let router = sse_router.nest_service(STREAMABLE_HTTP_PATH, streamable_http);
+    if api_keys.is_empty() {
+        return router;
+    }
+    router.layer(axum::middleware::from_fn_with_state(
+        Arc::new(ApiKeys::new(api_keys)),
+        auth::require_api_key,
+    ))
+}
+
+/// Serve local clients over a Unix domain socket until Ctrl-C.
+///
+/// The socket is only accessible by the owner (mode 0600), including while it
+/// is being set up. Each connection is its own session sharing the same
+/// storage, as with [`serve_http`].
+#[cfg(unix)]
+pub async fn serve_unix(server: MemoryServer, path: &Path) -> Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+    use tokio::net::{UnixListener, UnixStream};
+
+    if path.exists() {
+        if UnixStream::connect(path).await.is_ok() {
+            anyhow::bail!("Another server is already listening on {:?}", path);
+        }
+        // Stale socket left behind by a previous run
+        std::fs::remove_file(path).with_context(|| format!("Failed to remove stale socket {:?}", path))?;
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("Failed to create directory: {:?}", parent))?;
+    }
+
+    // Bind inside a fresh directory only the owner can enter, and only move the
+    // socket into place once its own permissions are restricted. The socket is
+    // created with the process umask, so other users could otherwise connect
+    // in the moment between binding and restricting it.
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("memory.sock");
+    let temp_dir = path.with_file_name(format!(".{}.{}", name, std::process::id()));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&temp_dir)
+        .with_context(|| format!("Failed to create directory: {:?}", temp_dir))?;
+    let temp_path = temp_dir.join(name);
+    let bound = UnixListener::bind(&temp_path)
+        .with_context(|| format!("Failed to bind Unix socket {:?}", temp_path))
+        .and_then(|listener| {
+            std::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o600))
+                .with_context(|| format!("Failed to restrict permissions of {:?}", temp_path))?;
+            std::fs::rename(&temp_path, path).with_context(|| format!("Failed to move socket to {:?}", path))?;
+            Ok(listener)
+        });
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    let listener = bound?;
+
+    tracing::info!("MCP Memory Service listening on Unix socket {:?}", path);
+
+    let ct = CancellationToken::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                let (stream, _) = match accepted {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        tracing::warn!("Failed to accept Unix socket connection: {}", e);
+                        continue;
+                    }
+                };
+                let session = server.new_session();
+                let ct = ct.child_token();
+                tokio::spawn(async move {
+                    match session.serve_with_ct(stream, ct).await {
+                        Ok(service) => {
+                            let _ = service.waiting().await;
+                        }
+                        Err(e) => tracing::warn!("Failed to start Unix socket session: {}", e),
+                    }
+                });
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        }
+    }
+
+    ct.cancel();
+    let _ = std::fs::remove_file(path);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub async fn serve_unix(_server: MemoryServer, _path: &Path) -> Result<()> {
+    anyhow::bail!("The Unix socket transport is only available on Unix platforms")
+}
+
+/// Relay stdin/stdout to a daemon serving the Unix socket transport, so that
+/// stdio-only MCP hosts can share it.
+#[cfg(unix)]
+pub async fn run_bridge(path: &Path) -> Result<()> {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::UnixStream;
+
+    let stream = UnixStream::connect(path)
+        .await
+        .with_context(|| format!("Failed to connect to memory daemon at {:?}", path))?;
+    let (mut reader, mut writer) = stream.into_split();
+
+    tracing::info!("Bridging stdio to memory daemon at {:?}", path);
+
+    tokio::spawn(async move {
+        let mut stdin = tokio::io::stdin();
+        if let Err(e) = tokio::io::copy(&mut stdin, &mut writer).await {
+            tracing::warn!("Failed to forward stdin to memory daemon: {}", e);
+        }
+        // Let the daemon see EOF so it ends the session
+        let _ = writer.shutdown().await;
+    });
+
+    let mut stdout = tokio::io::stdout();
+    tokio::io::copy(&mut reader, &mut stdout)
+        .await
+        .context("Failed to forward memory daemon output to stdout")?;
+    stdout.flush().await?;
+
+    tracing::info!("Memory daemon closed the connection.");
+
+    // A pending blocking read on stdin would otherwise keep the runtime from shutting down
+    std::process::exit(0)
+}
+
+#[cfg(not(unix))]
+pub async fn run_bridge(_path: &Path) -> Result<()> {
+    anyhow::bail!("The stdio bridge is only available on Unix platforms")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::{Client, Response, StatusCode};
+    use serde_json::{json, Value};
+
+    async fn start_http(api_keys: &[ApiKey]) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = http_router(crate::tests::test_server(), addr, api_keys, CancellationToken::new());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        format!("http://{}{}", addr, STREAMABLE_HTTP_PATH)
+    }
+
+    async fn post(url: &str, key: Option<&str>, session: Option<&str>, message: Value) -> Response {
+        let mut request = Client::new()
+            .post(url)
+            .header("Accept", "application/json, text/event-stream")
+            .json(&message);
+        if let Some(key) = key {
+            request = request.bearer_auth(key);
+        }
+        if let Some(session) = session {
+            request = request.header("Mcp-Session-Id", session);
+        }
+        request.send().await.unwrap()
+    }
+
+    // The JSON-RPC message answering a request, read from the event stream
+    async fn reply(mut response: Response) -> Value {
+        let mut body = String::new();
+        while let Some(chunk) = response.chunk().await.unwrap() {
+            body.push_str(&String::from_utf8_lossy(&chunk));
+            let message = body
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+                .find(|message| message.get("id").is_some());
+            if let Some(message) = message {
+                return message;
+            }
+        }
+        panic!("No reply in {:?}", body);
+    }
+
+    #[tokio::test]
+    async fn requests_without_a_valid_key_are_rejected() {
+        let url = start_http(&["reader:read".parse().unwrap()]).await;
+        let initialize = json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+            "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "0" }
+        }});
+        for key in [None, Some("writer")] {
+            let response = post(&url, key, None, initialize.clone()).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert!(post(&url, Some("reader"), None, initialize).await.status().is_success());
+    }
+
+    #[tokio::test]
+    async fn read_keys_can_search_but_not_store() {
+        let url = start_http(&["reader:read".parse().unwrap()]).await;
+        let key = Some("reader");
+        let initialized = post(&url, key, None, json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {
+            "protocolVersion": "2025-06-18", "capabilities": {}, "clientInfo": { "name": "test", "version": "0" }
+        }}))
+        .await;
+        let session = initialized.headers()["mcp-session-id"].to_str().unwrap().to_string();
+        reply(initialized).await;
+        let session = Some(session.as_str());
+        post(&url, key, session, json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await;
+
+        let call = |id: u64, name: &str, arguments: Value| {
+            json!({ "jsonrpc": "2.0", "id": id, "method": "tools/call", "params": { "name": name, "arguments": arguments } })
+        };
+        let stored = reply(post(&url, key, session, call(2, "store_memory", json!({ "content": "denied" }))).await).await;
+        assert_eq!(stored["error"]["code"], json!(auth::PERMISSION_DENIED.0), "{}", stored);
+        let searched = reply(post(&url, key, session, call(3, "search_by_tag", json!({ "tags": ["a"] }))).await).await;
+        assert!(searched.get("result").is_some(), "{}", searched);
+    }
+}